@@ -6,6 +6,7 @@ in vec4 a_Normal;
 in vec2 a_TexCoord;
 out vec2 v_TexCoord;
 out vec4 v_Color;
+out vec3 v_World;
 
 in vec4 i_World0;
 in vec4 i_World1;
@@ -17,5 +18,7 @@ void main() {
     mat4 m_World = transpose(mat4(i_World0, i_World1, i_World2, vec4(0.0, 0.0, 0.0, 1.0)));
     v_TexCoord = mix(i_UvRange.xy, i_UvRange.zw, a_TexCoord);
     v_Color = i_Color;
-    gl_Position = u_ViewProj * m_World * a_Position;
+    vec4 world = m_World * a_Position;
+    v_World = world.xyz;
+    gl_Position = u_ViewProj * world;
 }
@@ -0,0 +1,62 @@
+#version 150 core
+#include <post>
+
+// Has to match `render::post::BloomMode`.
+#define BLOOM_PREFILTER     0
+#define BLOOM_DOWNSAMPLE    1
+#define BLOOM_UPSAMPLE      2
+#define BLOOM_COMPOSITE     3
+
+// The matching downsample level when upsampling,
+// or the scene color when compositing.
+uniform sampler2D t_Source;
+
+// u_Params0: threshold, knee, intensity
+
+// Dual filtering, from Marius Bjørge, "Bandwidth-Efficient Rendering", SIGGRAPH 2015.
+vec3 downsample(vec2 uv) {
+    vec2 o = 0.5 * u_Resolution.zw;
+    vec3 sum = 4.0 * texture(t_Input, uv).rgb;
+    sum += texture(t_Input, uv - o).rgb;
+    sum += texture(t_Input, uv + o).rgb;
+    sum += texture(t_Input, uv + vec2(o.x, -o.y)).rgb;
+    sum += texture(t_Input, uv - vec2(o.x, -o.y)).rgb;
+    return sum / 8.0;
+}
+
+vec3 upsample(vec2 uv) {
+    vec2 o = 0.5 * u_Resolution.zw;
+    vec3 sum = texture(t_Input, uv + vec2(-2.0 * o.x, 0.0)).rgb;
+    sum += 2.0 * texture(t_Input, uv + vec2(-o.x, o.y)).rgb;
+    sum += texture(t_Input, uv + vec2(0.0, 2.0 * o.y)).rgb;
+    sum += 2.0 * texture(t_Input, uv + vec2(o.x, o.y)).rgb;
+    sum += texture(t_Input, uv + vec2(2.0 * o.x, 0.0)).rgb;
+    sum += 2.0 * texture(t_Input, uv + vec2(o.x, -o.y)).rgb;
+    sum += texture(t_Input, uv + vec2(0.0, -2.0 * o.y)).rgb;
+    sum += 2.0 * texture(t_Input, uv + vec2(-o.x, -o.y)).rgb;
+    return sum / 12.0;
+}
+
+// Keeps the part of the color above the threshold, with a quadratic
+// transition of width `knee` to avoid a hard cut.
+vec3 prefilter(vec3 color) {
+    float threshold = u_Params0.x;
+    float knee = threshold * u_Params0.y + 1e-5;
+    float brightness = max(color.r, max(color.g, color.b));
+    float soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee);
+    return color * max(soft, brightness - threshold) / max(brightness, 1e-5);
+}
+
+void main() {
+    if (u_Mode == BLOOM_PREFILTER) {
+        Target0 = vec4(prefilter(downsample(v_TexCoord)), 1.0);
+    } else if (u_Mode == BLOOM_DOWNSAMPLE) {
+        Target0 = vec4(downsample(v_TexCoord), 1.0);
+    } else if (u_Mode == BLOOM_UPSAMPLE) {
+        Target0 = vec4(upsample(v_TexCoord) + texture(t_Source, v_TexCoord).rgb, 1.0);
+    } else {
+        vec4 scene = texture(t_Source, v_TexCoord);
+        Target0 = vec4(scene.rgb + u_Params0.z * upsample(v_TexCoord), scene.a);
+    }
+}
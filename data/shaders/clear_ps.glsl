@@ -0,0 +1,14 @@
+#version 150 core
+
+layout(std140) uniform b_ClearParams {
+    vec4 u_Color;
+};
+
+out vec4 Target0;
+
+// Resets color, depth and stencil inside the current viewport, as target clears
+// always cover the whole target.
+void main() {
+    Target0 = u_Color;
+    gl_FragDepth = 1.0;
+}
@@ -0,0 +1,48 @@
+// Clustered light lookup, has to match `render::cluster`.
+// Requires <lights> and <globals> to be included first.
+
+layout(std430) readonly buffer b_LightClusters {
+    uvec2 u_LightClusters[];
+};
+
+layout(std430) readonly buffer b_LightIndices {
+    uint u_LightIndices[];
+};
+
+// Returns `[offset, count]` of the cluster containing a world position.
+uvec2 light_cluster(vec3 world) {
+    vec4 view = u_View * vec4(world, 1.0);
+    vec4 clip = u_ViewProj * vec4(world, 1.0);
+    vec2 uv = 0.5 * clip.xy / clip.w + 0.5;
+    float depth = -view.z;
+    float near = u_ClusterDepth.x;
+    float far = u_ClusterDepth.y;
+    float slice = u_ClusterDepth.z > 0.0
+        ? log(max(depth, near) / near) / log(far / near)
+        : (depth - near) / (far - near);
+    vec3 dims = vec3(u_ClusterDims.xyz);
+    uvec3 cell = uvec3(clamp(vec3(uv, slice) * dims, vec3(0.0), dims - 1.0));
+    return u_LightClusters[cell.x + u_ClusterDims.x * (cell.y + u_ClusterDims.y * cell.z)];
+}
+
+// Number of lights affecting a cluster: the unbounded lights come first,
+// followed by the ones binned into the cluster.
+uint light_count(uvec2 cluster) {
+    return u_NumGlobalLights + cluster.y;
+}
+
+// Index into `u_Lights` of the k-th light affecting a cluster.
+uint light_index(uvec2 cluster, uint k) {
+    return k < u_NumGlobalLights ? k : u_LightIndices[cluster.x + k - u_NumGlobalLights];
+}
+
+// Smooth falloff for lights with a finite range, stored in `intensity.z`.
+float light_attenuation(Light light, vec3 world) {
+    float range = light.intensity.z;
+    if (range <= 0.0) {
+        return 1.0;
+    }
+    float ratio = length(light.pos.xyz - world) / range;
+    float window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window;
+}
@@ -0,0 +1,12 @@
+#version 150 core
+#include <globals>
+
+in vec3 a_Position;
+in vec4 a_Color;
+out vec4 v_Color;
+
+// Debug shapes are given in world space, see `render::debug`.
+void main() {
+    v_Color = a_Color;
+    gl_Position = u_ViewProj * vec4(a_Position, 1.0);
+}
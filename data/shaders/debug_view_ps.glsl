@@ -0,0 +1,54 @@
+#version 150 core
+
+// Has to match `DebugView::mode`.
+const int NORMALS = 0;
+const int TANGENTS = 1;
+const int TEX_COORDS = 2;
+const int DEPTH = 3;
+const int OVERDRAW = 4;
+const int MIP_LEVEL = 5;
+
+layout(std140) uniform b_DebugViewParams {
+    int u_DebugView;
+    int u_DebugFlags;
+    int u_DebugHasMap;
+    vec4 u_DepthRange;
+};
+
+uniform sampler2D t_Map;
+
+in vec3 v_Normal;
+in vec3 v_Tangent;
+in vec2 v_TexCoord;
+in float v_Depth;
+out vec4 Target0;
+
+// Level of detail the hardware would pick, as `textureQueryLod` is not available.
+float mip_level(vec2 uv) {
+    vec2 texels = uv * vec2(textureSize(t_Map, 0));
+    vec2 dx = dFdx(texels);
+    vec2 dy = dFdy(texels);
+    return max(0.5 * log2(max(dot(dx, dx), dot(dy, dy))), 0.0);
+}
+
+void main() {
+    if (u_DebugView == NORMALS) {
+        Target0 = vec4(0.5 * normalize(v_Normal) + 0.5, 1.0);
+    } else if (u_DebugView == TANGENTS) {
+        Target0 = vec4(0.5 * normalize(v_Tangent) + 0.5, 1.0);
+    } else if (u_DebugView == TEX_COORDS) {
+        Target0 = vec4(fract(v_TexCoord), 0.0, 1.0);
+    } else if (u_DebugView == DEPTH) {
+        float depth = clamp((v_Depth - u_DepthRange.x) / (u_DepthRange.y - u_DepthRange.x), 0.0, 1.0);
+        Target0 = vec4(vec3(depth), 1.0);
+    } else if (u_DebugView == OVERDRAW) {
+        // accumulated by additive blending, saturates red first
+        Target0 = vec4(0.2, 0.05, 0.02, 1.0);
+    } else if (u_DebugHasMap == 0) {
+        Target0 = vec4(0.5, 0.5, 0.5, 1.0);
+    } else {
+        float t = min(mip_level(v_TexCoord), 5.0) / 5.0;
+        vec3 color = t < 0.5 ? mix(vec3(0.0, 0.0, 1.0), vec3(0.0, 1.0, 0.0), 2.0 * t) : mix(vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), 2.0 * t - 1.0);
+        Target0 = vec4(color, 1.0);
+    }
+}
@@ -0,0 +1,58 @@
+#version 150 core
+#define MAX_TARGETS 8U
+#include <globals>
+
+// Has to match `render::DISPLACEMENT_BUFFER`.
+const int DISPLACEMENT_BUFFER = 1 << 5;
+
+in vec4 a_Position;
+in vec2 a_TexCoord;
+in vec4 a_Normal;
+in vec4 a_Tangent;
+in ivec4 a_JointIndices;
+in vec4 a_JointWeights;
+
+in vec4 i_World0;
+in vec4 i_World1;
+in vec4 i_World2;
+
+#include <skinning>
+
+layout(std140) uniform b_DebugViewParams {
+    int u_DebugView;
+    int u_DebugFlags;
+    int u_DebugHasMap;
+    vec4 u_DepthRange;
+};
+
+out vec3 v_Normal;
+out vec3 v_Tangent;
+out vec2 v_TexCoord;
+out float v_Depth;
+
+void main() {
+    vec3 local_position = a_Position.xyz;
+    vec3 local_normal = a_Normal.xyz;
+    vec3 local_tangent = a_Tangent.xyz;
+
+    if ((u_DebugFlags & DISPLACEMENT_BUFFER) != 0) {
+        uint num_targets = uvec2(textureSize(u_Displacements, 0)).y / 3U;
+        for (uint i = 0U; i < min(num_targets, MAX_TARGETS); ++i) {
+            DisplacementContribution disp = u_DisplacementContributions[i];
+            if (disp.weight == 0.0) continue;
+            local_position += disp.position * disp.weight * texelFetch(u_Displacements, ivec2(gl_VertexID, 3U*i+0U), 0).xyz;
+            local_normal   += disp.normal   * disp.weight * texelFetch(u_Displacements, ivec2(gl_VertexID, 3U*i+1U), 0).xyz;
+            local_tangent  += disp.tangent  * disp.weight * texelFetch(u_Displacements, ivec2(gl_VertexID, 3U*i+2U), 0).xyz;
+        }
+    }
+
+    mat4 mx_world = transpose(mat4(i_World0, i_World1, i_World2, vec4(0.0, 0.0, 0.0, 1.0)));
+    mat4 mx_model = mx_world * compute_skin_transform();
+    vec4 world_position = mx_model * vec4(local_position, a_Position.w);
+
+    v_Normal = mat3(mx_model) * local_normal;
+    v_Tangent = mat3(mx_model) * local_tangent;
+    v_TexCoord = a_TexCoord;
+    v_Depth = -(u_View * world_position).z;
+    gl_Position = u_ViewProj * world_position;
+}
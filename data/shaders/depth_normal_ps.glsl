@@ -0,0 +1,10 @@
+#version 150 core
+
+in vec3 v_ViewPosition;
+in vec3 v_ViewNormal;
+out vec4 Target0;
+
+// View space normal and linear depth, read by `ssao_ps`.
+void main() {
+    Target0 = vec4(normalize(v_ViewNormal), -v_ViewPosition.z);
+}
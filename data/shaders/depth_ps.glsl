@@ -0,0 +1,11 @@
+#version 150 core
+
+in float v_Distance;
+in vec2 v_Range;
+out vec4 Target0;
+
+void main() {
+    // white at the near distance, black at the far one
+    float depth = clamp((v_Distance - v_Range.x) / max(v_Range.y - v_Range.x, 1e-6), 0.0, 1.0);
+    Target0 = vec4(vec3(1.0 - depth), 1.0);
+}
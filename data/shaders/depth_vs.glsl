@@ -0,0 +1,20 @@
+#version 150 core
+#include <globals>
+
+in vec4 a_Position;
+out float v_Distance;
+// near, far
+out vec2 v_Range;
+
+in vec4 i_World0;
+in vec4 i_World1;
+in vec4 i_World2;
+in vec4 i_MatParams;
+
+void main() {
+    mat4 m_World = transpose(mat4(i_World0, i_World1, i_World2, vec4(0.0, 0.0, 0.0, 1.0)));
+    vec4 world = m_World * a_Position;
+    v_Distance = -(u_View * world).z;
+    v_Range = i_MatParams.xy;
+    gl_Position = u_ViewProj * world;
+}
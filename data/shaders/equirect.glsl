@@ -0,0 +1,6 @@
+// Maps a direction to the texture coordinates of an equirectangular panorama,
+// loaded bottom row first like every other texture.
+vec2 equirect_uv(vec3 dir) {
+    const float PI = 3.141592653589793;
+    return vec2(atan(dir.z, dir.x) / (2.0 * PI) + 0.5, asin(clamp(dir.y, -1.0, 1.0)) / PI + 0.5);
+}
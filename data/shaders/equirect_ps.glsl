@@ -0,0 +1,11 @@
+#version 150 core
+#include <equirect>
+
+uniform sampler2D t_Input;
+
+in vec3 v_TexCoord;
+out vec4 Target0;
+
+void main() {
+    Target0 = texture(t_Input, equirect_uv(normalize(v_TexCoord)));
+}
@@ -0,0 +1,30 @@
+// Distance and height fog, see `scene::Fog`.
+// Requires <globals> to be included first.
+
+const int FOG_NONE = 0;
+const int FOG_LINEAR = 1;
+const int FOG_EXPONENTIAL = 2;
+
+// Amount of fog between the camera and the given world position.
+float fog_factor(vec3 world) {
+    int mode = int(u_FogColor.w);
+    if (mode == FOG_NONE) {
+        return 0.0;
+    }
+    float distance = length((u_View * vec4(world, 1.0)).xyz);
+    float fog;
+    if (mode == FOG_LINEAR) {
+        fog = clamp((distance - u_FogParams.x) / max(u_FogParams.y - u_FogParams.x, 0.0001), 0.0, 1.0);
+    } else {
+        fog = 1.0 - exp(-u_FogParams.x * distance);
+    }
+    // thins out above the base height
+    if (u_FogParams.w > 0.0) {
+        fog *= exp(-u_FogParams.w * max(world.y - u_FogParams.z, 0.0));
+    }
+    return fog;
+}
+
+vec4 apply_fog(vec4 color, vec3 world) {
+    return vec4(mix(color.rgb, u_FogColor.rgb, fog_factor(world)), color.a);
+}
@@ -0,0 +1,10 @@
+// Vertex stage of fullscreen passes: a single screen-covering triangle
+// generated from `gl_VertexID`, no vertex buffer needed.
+
+out vec2 v_TexCoord;
+
+void main() {
+    vec2 pos = vec2(gl_VertexID == 1 ? 3.0 : -1.0, gl_VertexID == 2 ? 3.0 : -1.0);
+    v_TexCoord = 0.5 * pos + 0.5;
+    gl_Position = vec4(pos, 0.0, 1.0);
+}
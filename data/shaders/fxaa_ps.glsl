@@ -0,0 +1,38 @@
+#version 150 core
+#include <post>
+
+// u_Params0: span max, reduce multiplier, reduce minimum
+
+// Simplified FXAA, after Timothy Lottes' "FXAA" white paper (NVIDIA, 2009).
+
+float luma(vec3 color) {
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+void main() {
+    vec2 texel = u_Resolution.zw;
+    vec4 center = texture(t_Input, v_TexCoord);
+    float luma_nw = luma(texture(t_Input, v_TexCoord + vec2(-1.0, -1.0) * texel).rgb);
+    float luma_ne = luma(texture(t_Input, v_TexCoord + vec2(1.0, -1.0) * texel).rgb);
+    float luma_sw = luma(texture(t_Input, v_TexCoord + vec2(-1.0, 1.0) * texel).rgb);
+    float luma_se = luma(texture(t_Input, v_TexCoord + vec2(1.0, 1.0) * texel).rgb);
+    float luma_m = luma(center.rgb);
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // blur along the edge, perpendicular to the luma gradient
+    vec2 dir = vec2(-((luma_nw + luma_ne) - (luma_sw + luma_se)), (luma_nw + luma_sw) - (luma_ne + luma_se));
+    float dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * u_Params0.y, u_Params0.z);
+    float rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2(-u_Params0.x), vec2(u_Params0.x)) * texel;
+
+    vec3 rgb_a = 0.5 * (
+        texture(t_Input, v_TexCoord + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture(t_Input, v_TexCoord + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 rgb_b = rgb_a * 0.5 + 0.25 * (
+        texture(t_Input, v_TexCoord - dir * 0.5).rgb +
+        texture(t_Input, v_TexCoord + dir * 0.5).rgb);
+    float luma_b = luma(rgb_b);
+    vec3 color = (luma_b < luma_min || luma_b > luma_max) ? rgb_a : rgb_b;
+    Target0 = vec4(color, center.a);
+}
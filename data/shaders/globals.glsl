@@ -3,4 +3,15 @@ layout(std140) uniform b_Globals {
     mat4 u_InverseProj;
     mat4 u_View;
     uint u_NumLights;
+    uint u_NumGlobalLights;
+    uvec4 u_ClusterDims;
+    vec4 u_ClusterDepth;
+    // intensity, specular mip count - 1, enabled
+    vec4 u_Environment;
+    // rgb, fog mode
+    vec4 u_FogColor;
+    // linear near and far or exponential density, height base, height falloff
+    vec4 u_FogParams;
+    // enabled, 1/width, 1/height of the SSAO target
+    vec4 u_Ssao;
 };
@@ -0,0 +1,22 @@
+// Per-frame uniforms of `Material::Custom` shaders, has to match `Globals`
+// in `render/mod.rs` and `globals.glsl`.
+
+struct Globals {
+    mx_vp: mat4x4<f32>,
+    mx_inv_proj: mat4x4<f32>,
+    mx_view: mat4x4<f32>,
+    num_lights: u32,
+    num_global_lights: u32,
+    cluster_dims: vec4<u32>,
+    cluster_depth: vec4<f32>,
+    // intensity, specular mip count - 1, enabled
+    environment: vec4<f32>,
+    // rgb, fog mode
+    fog_color: vec4<f32>,
+    // linear near and far or exponential density, height base, height falloff
+    fog_params: vec4<f32>,
+    // enabled, 1/width, 1/height of the SSAO target
+    ssao: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> u_Globals: Globals;
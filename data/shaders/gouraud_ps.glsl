@@ -1,27 +1,31 @@
 #version 150 core
+#include <globals>
+#include <shadows>
+#include <fog>
+#include <ssao>
 
+in vec3 v_World;
 in vec4 v_ResultColor;
 flat in vec4 v_ResultColorFlat;
+in vec4 v_Ambient;
+flat in vec4 v_AmbientFlat;
 flat in float v_Smooth;
 in vec4 v_LightEval[2];
 flat in vec4 v_LightEvalFlat[2];
 in vec4 v_ShadowCoord[2];
+flat in int v_ShadowTechnique[2];
+flat in vec4 v_ShadowBias[2];
 
 out vec4 Target0;
 
-uniform sampler2DShadow t_Shadow0;
-uniform sampler2DShadow t_Shadow1;
-
 void main() {
     Target0 = mix(v_ResultColorFlat, v_ResultColor, v_Smooth);
-    if (v_ShadowCoord[0].w != 0.0) {
-        vec3 coord = v_ShadowCoord[0].xyz / v_ShadowCoord[0].w;
-        float shadow = texture(t_Shadow0, 0.5 * coord + 0.5);
-        Target0 += shadow * mix(v_LightEvalFlat[0], v_LightEval[0], v_Smooth);
-    }
-    if (v_ShadowCoord[1].w != 0.0) {
-        vec3 coord = v_ShadowCoord[1].xyz / v_ShadowCoord[1].w;
-        float shadow = texture(t_Shadow1, 0.5 * coord + 0.5);
-        Target0 += shadow * mix(v_LightEvalFlat[1], v_LightEval[1], v_Smooth);
+    Target0 += ssao_factor() * mix(v_AmbientFlat, v_Ambient, v_Smooth);
+    for (int i = 0; i < 2; ++i) {
+        if (v_ShadowCoord[i].w != 0.0) {
+            float shadow = shadow_factor(i, v_ShadowCoord[i], v_ShadowTechnique[i], v_ShadowBias[i]);
+            Target0 += shadow * mix(v_LightEvalFlat[i], v_LightEval[i], v_Smooth);
+        }
     }
+    Target0 = apply_fog(Target0, v_World);
 }
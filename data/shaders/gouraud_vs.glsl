@@ -1,17 +1,23 @@
 #version 150 core
 #include <lights>
 #include <globals>
+#include <clusters>
 
 #define MAX_SHADOWS 2
 
 in vec4 a_Position;
 in vec4 a_Normal;
+out vec3 v_World;
 out vec4 v_ResultColor;
+out vec4 v_Ambient;
+flat out vec4 v_AmbientFlat;
 flat out vec4 v_ResultColorFlat;
 flat out float v_Smooth;
 out vec4 v_LightEval[MAX_SHADOWS];
 flat out vec4 v_LightEvalFlat[MAX_SHADOWS];
 out vec4 v_ShadowCoord[MAX_SHADOWS];
+flat out int v_ShadowTechnique[MAX_SHADOWS];
+flat out vec4 v_ShadowBias[MAX_SHADOWS];
 
 in vec4 i_World0;
 in vec4 i_World1;
@@ -24,15 +30,26 @@ void main() {
     mat4 m_World = transpose(mat4(i_World0, i_World1, i_World2, vec4(0.0, 0.0, 0.0, 1.0)));
     vec4 world = m_World * a_Position;
     vec3 normal = normalize(mat3(m_World) * a_Normal.xyz);
+    // two sided materials light the side of the vertex facing the camera,
+    // as the facing of the rasterized triangle is not known here
+    vec3 camera = -transpose(mat3(u_View)) * u_View[3].xyz;
+    if (i_MatParams.y > 0.0 && dot(normal, camera - world.xyz) < 0.0) {
+        normal = -normal;
+    }
     for(int i=0; i<MAX_SHADOWS; ++i) {
         v_ShadowCoord[i] = vec4(0.0);
+        v_ShadowTechnique[i] = 0;
+        v_ShadowBias[i] = vec4(0.0);
         v_LightEval[i] = v_LightEvalFlat[i] = vec4(0.0);
     }
     v_ResultColor = vec4(0.0);
+    v_Ambient = vec4(0.0);
     v_Smooth = i_MatParams.x;
 
-    for(uint i=0U; i < min(MAX_LIGHTS, u_NumLights); ++i) {
-        Light light = u_Lights[i];
+    uvec2 cluster = light_cluster(world.xyz);
+    for(uint k=0U; k < light_count(cluster); ++k) {
+        Light light = u_Lights[light_index(cluster, k)];
+        float attenuation = light_attenuation(light, world.xyz);
         vec3 dir = light.pos.xyz - light.pos.w * world.xyz;
         // evaluate light color
         float dot_nl = dot(normal, normalize(dir));
@@ -41,12 +58,14 @@ void main() {
             irradiance = mix(light.color_back, light.color, dot_nl*0.5 + 0.5);
             dot_nl = 0.0;
         }
-        v_ResultColor += light.intensity.x * i_Color * irradiance; //ambient
-        vec4 color = light.intensity.y * max(0.0, dot_nl) * i_Color * light.color;
+        v_Ambient += attenuation * light.intensity.x * i_Color * irradiance;
+        vec4 color = attenuation * light.intensity.y * max(0.0, dot_nl) * i_Color * light.color;
         // compute shadow coordinates
         int shadow_index = light.shadow_params[0];
         if (0 <= shadow_index && shadow_index < MAX_SHADOWS) {
-            v_ShadowCoord[shadow_index] = light.projection * world;
+            v_ShadowCoord[shadow_index] = light.projection * vec4(world.xyz + normal * light.shadow_bias.y, 1.0);
+            v_ShadowTechnique[shadow_index] = light.shadow_params[1];
+            v_ShadowBias[shadow_index] = light.shadow_bias;
             v_LightEval[shadow_index] = color;
             v_LightEvalFlat[shadow_index] = color;
         } else {
@@ -55,5 +74,7 @@ void main() {
     }
 
     v_ResultColorFlat = v_ResultColor;
+    v_AmbientFlat = v_Ambient;
+    v_World = world.xyz;
     gl_Position = u_ViewProj * world;
 }
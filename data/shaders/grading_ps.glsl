@@ -0,0 +1,18 @@
+#version 150 core
+#include <post>
+#include <srgb>
+
+// Color lookup table, authored in sRGB space.
+uniform sampler3D t_Lut;
+
+// u_Params0: intensity, LUT size
+
+void main() {
+    vec4 color = texture(t_Input, v_TexCoord);
+    vec3 srgb = linear_to_srgb(clamp(color.rgb, 0.0, 1.0));
+    // sample at texel centers, so the end points map exactly to the first/last entries
+    float size = u_Params0.y;
+    vec3 coord = srgb * ((size - 1.0) / size) + 0.5 / size;
+    vec3 graded = srgb_to_linear(texture(t_Lut, coord).rgb);
+    Target0 = vec4(mix(color.rgb, graded, u_Params0.x), color.a);
+}
@@ -0,0 +1,34 @@
+// Prelude of render graph passes, see `render::graph::WgslPass`.
+//
+// Provides a vertex stage covering the target with a single triangle, the
+// fragment stage is expected to be provided as `fs_main`. The inputs of the
+// pass are bound in group 1, in the order they were given.
+
+struct PassParams {
+    // `WgslPass::params`.
+    user: array<vec4<f32>, 4>,
+    // World to clip space transform of the camera.
+    mx_vp: mat4x4<f32>,
+    // Clip to world space transform of the camera.
+    mx_inv_vp: mat4x4<f32>,
+    // Size of the output: width, height, 1/width, 1/height.
+    resolution: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> u_Pass: PassParams;
+@group(0) @binding(1) var s_Linear: sampler;
+
+struct PassOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> PassOutput {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: PassOutput;
+    out.position = vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+    // WGSL textures have their origin at the top left
+    out.tex_coord = vec2<f32>(corner.x, 1.0 - corner.y);
+    return out;
+}
@@ -0,0 +1,48 @@
+// Shared code of the image-based lighting precomputation, see `render::ibl`.
+
+const PI: f32 = 3.141592653589793;
+
+struct IblParams {
+    // perceptual roughness of the prefiltered mip level
+    roughness: f32,
+    // width and height of the output, in texels
+    size: u32,
+    sample_count: u32,
+    _padding0: u32,
+}
+
+// Direction through the center of texel `id` on the given cube face,
+// with faces ordered +X, -X, +Y, -Y, +Z, -Z.
+fn cube_direction(id: vec2<u32>, face: u32, size: u32) -> vec3<f32> {
+    let uv = (vec2<f32>(id) + 0.5) / f32(size) * 2.0 - 1.0;
+    var dir: vec3<f32>;
+    switch face {
+        case 0u: { dir = vec3<f32>(1.0, -uv.y, -uv.x); }
+        case 1u: { dir = vec3<f32>(-1.0, -uv.y, uv.x); }
+        case 2u: { dir = vec3<f32>(uv.x, 1.0, uv.y); }
+        case 3u: { dir = vec3<f32>(uv.x, -1.0, -uv.y); }
+        case 4u: { dir = vec3<f32>(uv.x, -uv.y, 1.0); }
+        default: { dir = vec3<f32>(-uv.x, -uv.y, -1.0); }
+    }
+    return normalize(dir);
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// Rotates `v` from tangent space, where Z is up, to the frame around `n`.
+fn tangent_to_world(v: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(n.z) < 0.999);
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return normalize(tangent * v.x + bitangent * v.y + n * v.z);
+}
+
+// GGX distributed half vector around `n`.
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, alpha: f32) -> vec3<f32> {
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return tangent_to_world(vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta), n);
+}
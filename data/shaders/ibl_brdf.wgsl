@@ -0,0 +1,39 @@
+@group(0) @binding(0) var t_Output: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(1) var<uniform> u_Params: IblParams;
+
+fn smith_schlick(ndotx: f32, k: f32) -> f32 {
+    return ndotx / (ndotx * (1.0 - k) + k);
+}
+
+// Scale and bias applied to F0 by the split-sum approximation, indexed by
+// N.V horizontally and perceptual roughness vertically.
+@compute @workgroup_size(8, 8, 1)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= u_Params.size || id.y >= u_Params.size) {
+        return;
+    }
+    let ndotv = (f32(id.x) + 0.5) / f32(u_Params.size);
+    let roughness = (f32(id.y) + 0.5) / f32(u_Params.size);
+    let alpha = roughness * roughness;
+    let k = alpha / 2.0;
+    let n = vec3<f32>(0.0, 0.0, 1.0);
+    let v = vec3<f32>(sqrt(1.0 - ndotv * ndotv), 0.0, ndotv);
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < u_Params.sample_count; i++) {
+        let h = importance_sample_ggx(hammersley(i, u_Params.sample_count), n, alpha);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let ndotl = max(l.z, 0.0);
+        if (ndotl > 0.0) {
+            let ndoth = max(h.z, 0.0);
+            let vdoth = max(dot(v, h), 0.0);
+            let g = smith_schlick(ndotv, k) * smith_schlick(ndotl, k);
+            let g_vis = g * vdoth / max(ndoth * ndotv, 0.0001);
+            let fc = pow(1.0 - vdoth, 5.0);
+            scale += (1.0 - fc) * g_vis;
+            bias += fc * g_vis;
+        }
+    }
+    let count = f32(u_Params.sample_count);
+    textureStore(t_Output, id.xy, vec4<f32>(scale / count, bias / count, 0.0, 1.0));
+}
@@ -0,0 +1,15 @@
+@group(0) @binding(0) var t_Input: texture_2d<f32>;
+@group(0) @binding(1) var s_Input: sampler;
+@group(0) @binding(2) var t_Output: texture_storage_2d_array<rgba16float, write>;
+@group(0) @binding(3) var<uniform> u_Params: IblParams;
+
+// Resamples an equirectangular panorama, loaded bottom row first, into a cube map.
+@compute @workgroup_size(8, 8, 1)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= u_Params.size || id.y >= u_Params.size) {
+        return;
+    }
+    let dir = cube_direction(id.xy, id.z, u_Params.size);
+    let uv = vec2<f32>(atan2(dir.z, dir.x) / (2.0 * PI) + 0.5, asin(clamp(dir.y, -1.0, 1.0)) / PI + 0.5);
+    textureStore(t_Output, id.xy, id.z, textureSampleLevel(t_Input, s_Input, uv, 0.0));
+}
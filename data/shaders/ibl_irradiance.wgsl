@@ -0,0 +1,23 @@
+@group(0) @binding(0) var t_Env: texture_cube<f32>;
+@group(0) @binding(1) var s_Env: sampler;
+@group(0) @binding(2) var t_Output: texture_storage_2d_array<rgba16float, write>;
+@group(0) @binding(3) var<uniform> u_Params: IblParams;
+
+// Cosine weighted average of the environment, i.e. the irradiance divided by PI.
+@compute @workgroup_size(8, 8, 1)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= u_Params.size || id.y >= u_Params.size) {
+        return;
+    }
+    let n = cube_direction(id.xy, id.z, u_Params.size);
+    var sum = vec3<f32>(0.0);
+    for (var i = 0u; i < u_Params.sample_count; i++) {
+        let xi = hammersley(i, u_Params.sample_count);
+        let phi = 2.0 * PI * xi.x;
+        let cos_theta = sqrt(1.0 - xi.y);
+        let sin_theta = sqrt(xi.y);
+        let l = tangent_to_world(vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta), n);
+        sum += textureSampleLevel(t_Env, s_Env, l, 0.0).rgb;
+    }
+    textureStore(t_Output, id.xy, id.z, vec4<f32>(sum / f32(u_Params.sample_count), 1.0));
+}
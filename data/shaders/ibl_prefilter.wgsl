@@ -0,0 +1,27 @@
+@group(0) @binding(0) var t_Env: texture_cube<f32>;
+@group(0) @binding(1) var s_Env: sampler;
+@group(0) @binding(2) var t_Output: texture_storage_2d_array<rgba16float, write>;
+@group(0) @binding(3) var<uniform> u_Params: IblParams;
+
+// Environment convolved with the GGX lobe of `u_Params.roughness`,
+// assuming the view direction matches the normal.
+@compute @workgroup_size(8, 8, 1)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= u_Params.size || id.y >= u_Params.size) {
+        return;
+    }
+    let n = cube_direction(id.xy, id.z, u_Params.size);
+    let alpha = u_Params.roughness * u_Params.roughness;
+    var sum = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < u_Params.sample_count; i++) {
+        let h = importance_sample_ggx(hammersley(i, u_Params.sample_count), n, alpha);
+        let l = normalize(2.0 * dot(n, h) * h - n);
+        let ndotl = dot(n, l);
+        if (ndotl > 0.0) {
+            sum += textureSampleLevel(t_Env, s_Env, l, 0.0).rgb * ndotl;
+            weight += ndotl;
+        }
+    }
+    textureStore(t_Output, id.xy, id.z, vec4<f32>(sum / max(weight, 0.0001), 1.0));
+}
@@ -1,4 +1,4 @@
-#define MAX_LIGHTS  4U
+#extension GL_ARB_shader_storage_buffer_object : require
 
 struct Light {
     mat4 projection;
@@ -9,8 +9,9 @@ struct Light {
     vec4 color_back;
     vec4 intensity;
     ivec4 shadow_params;
+    vec4 shadow_bias;
 };
 
-layout(std140) uniform b_Lights {
-    Light u_Lights[MAX_LIGHTS];
+layout(std430) readonly buffer b_Lights {
+    Light u_Lights[];
 };
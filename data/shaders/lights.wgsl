@@ -0,0 +1,62 @@
+// Lights and clustered light lookup of `Material::Custom` shaders, has to
+// match `lights.glsl` and `clusters.glsl`.
+
+#include <globals>
+
+struct Light {
+    projection: mat4x4<f32>,
+    // position, or direction with `w = 0` for directional lights
+    pos: vec4<f32>,
+    dir: vec4<f32>,
+    focus: vec4<f32>,
+    color: vec4<f32>,
+    // ground color of hemisphere lights, zero otherwise
+    color_back: vec4<f32>,
+    // ambient, diffuse, range
+    intensity: vec4<f32>,
+    shadow_params: vec4<i32>,
+    shadow_bias: vec4<f32>,
+};
+
+@group(0) @binding(1) var<storage, read> b_Lights: array<Light>;
+@group(0) @binding(2) var<storage, read> b_LightClusters: array<vec2<u32>>;
+@group(0) @binding(3) var<storage, read> b_LightIndices: array<u32>;
+
+// Returns `[offset, count]` of the cluster containing a world position.
+fn light_cluster(world: vec3<f32>) -> vec2<u32> {
+    let view_pos = u_Globals.mx_view * vec4<f32>(world, 1.0);
+    let clip = u_Globals.mx_vp * vec4<f32>(world, 1.0);
+    let uv = 0.5 * clip.xy / clip.w + 0.5;
+    let depth = -view_pos.z;
+    let near = u_Globals.cluster_depth.x;
+    let far = u_Globals.cluster_depth.y;
+    let depth_slice = select((depth - near) / (far - near), log(max(depth, near) / near) / log(far / near), u_Globals.cluster_depth.z > 0.0);
+    let dims = vec3<f32>(u_Globals.cluster_dims.xyz);
+    let cell = vec3<u32>(clamp(vec3<f32>(uv, depth_slice) * dims, vec3<f32>(0.0), dims - 1.0));
+    return b_LightClusters[cell.x + u_Globals.cluster_dims.x * (cell.y + u_Globals.cluster_dims.y * cell.z)];
+}
+
+// Number of lights affecting a cluster: the unbounded lights come first,
+// followed by the ones binned into the cluster.
+fn light_count(cluster: vec2<u32>) -> u32 {
+    return u_Globals.num_global_lights + cluster.y;
+}
+
+// Index into `b_Lights` of the k-th light affecting a cluster.
+fn light_index(cluster: vec2<u32>, k: u32) -> u32 {
+    if (k < u_Globals.num_global_lights) {
+        return k;
+    }
+    return b_LightIndices[cluster.x + k - u_Globals.num_global_lights];
+}
+
+// Smooth falloff for lights with a finite range, stored in `intensity.z`.
+fn light_attenuation(light: Light, world: vec3<f32>) -> f32 {
+    let range = light.intensity.z;
+    if (range <= 0.0) {
+        return 1.0;
+    }
+    let ratio = length(light.pos.xyz - world) / range;
+    let falloff = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return falloff * falloff;
+}
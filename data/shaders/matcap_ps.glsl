@@ -0,0 +1,25 @@
+#version 150 core
+#include <globals>
+#include <fog>
+
+in vec3 v_World;
+in vec3 v_ViewNormal;
+in vec3 v_ViewPosition;
+in vec4 v_UvRange;
+out vec4 Target0;
+
+uniform sampler2D t_Map;
+
+void main() {
+    vec3 normal = normalize(v_ViewNormal);
+    // back faces are only drawn by double sided materials, lit as seen
+    if (!gl_FrontFacing) {
+        normal = -normal;
+    }
+    // look the normal up as seen along the view ray, not the view axis
+    vec3 view = normalize(-v_ViewPosition);
+    vec3 x = normalize(vec3(view.z, 0.0, -view.x));
+    vec3 y = cross(view, x);
+    vec2 uv = vec2(dot(x, normal), dot(y, normal)) * 0.495 + 0.5;
+    Target0 = apply_fog(texture(t_Map, mix(v_UvRange.xy, v_UvRange.zw, uv)), v_World);
+}
@@ -0,0 +1,24 @@
+#version 150 core
+#include <globals>
+
+in vec4 a_Position;
+in vec4 a_Normal;
+out vec3 v_World;
+out vec3 v_ViewNormal;
+out vec3 v_ViewPosition;
+out vec4 v_UvRange;
+
+in vec4 i_World0;
+in vec4 i_World1;
+in vec4 i_World2;
+in vec4 i_UvRange;
+
+void main() {
+    mat4 m_World = transpose(mat4(i_World0, i_World1, i_World2, vec4(0.0, 0.0, 0.0, 1.0)));
+    vec4 world = m_World * a_Position;
+    v_World = world.xyz;
+    v_ViewNormal = mat3(u_View) * normalize(mat3(m_World) * a_Normal.xyz);
+    v_ViewPosition = (u_View * world).xyz;
+    v_UvRange = i_UvRange;
+    gl_Position = u_ViewProj * world;
+}
@@ -0,0 +1,26 @@
+// Vertex inputs of `Material::Custom` shaders, has to match `Vertex` and
+// `Instance` in `render/mod.rs`.
+
+struct VertexInput {
+    @location(0) position: vec4<f32>,
+    @location(1) tex_coord: vec2<f32>,
+    @location(2) normal: vec4<f32>,
+    @location(3) tangent: vec4<f32>,
+    @location(4) joint_indices: vec4<i32>,
+    @location(5) joint_weights: vec4<f32>,
+};
+
+struct InstanceInput {
+    // rows of the model to world transform
+    @location(6) world0: vec4<f32>,
+    @location(7) world1: vec4<f32>,
+    @location(8) world2: vec4<f32>,
+    @location(9) color: vec4<f32>,
+    @location(10) mat_params: vec4<f32>,
+    @location(11) uv_range: vec4<f32>,
+};
+
+// Model to world transform of the instance.
+fn instance_world(instance: InstanceInput) -> mat4x4<f32> {
+    return transpose(mat4x4<f32>(instance.world0, instance.world1, instance.world2, vec4<f32>(0.0, 0.0, 0.0, 1.0)));
+}
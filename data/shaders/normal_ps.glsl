@@ -0,0 +1,14 @@
+#version 150 core
+
+in vec3 v_ViewNormal;
+out vec4 Target0;
+
+void main() {
+    vec3 normal = normalize(v_ViewNormal);
+    // back faces are only drawn by double sided materials, lit as seen
+    if (!gl_FrontFacing) {
+        normal = -normal;
+    }
+    // view space normal, [-1, 1] mapped to [0, 1]
+    Target0 = vec4(normal * 0.5 + 0.5, 1.0);
+}
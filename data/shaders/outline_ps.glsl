@@ -0,0 +1,13 @@
+#version 150 core
+
+layout(std140) uniform b_OutlineParams {
+    vec4 u_OutlineColor;
+    vec4 u_OutlineExtent;
+    int u_OutlineFlags;
+};
+
+out vec4 Target0;
+
+void main() {
+    Target0 = u_OutlineColor;
+}
@@ -0,0 +1,58 @@
+#version 150 core
+#define MAX_TARGETS 8U
+#include <globals>
+
+// Has to match `render::DISPLACEMENT_BUFFER`.
+const int DISPLACEMENT_BUFFER = 1 << 5;
+// Has to match `render::outline::RADIAL`.
+const int RADIAL = 1 << 0;
+
+in vec4 a_Position;
+in vec4 a_Normal;
+in ivec4 a_JointIndices;
+in vec4 a_JointWeights;
+
+in vec4 i_World0;
+in vec4 i_World1;
+in vec4 i_World2;
+
+#include <skinning>
+
+layout(std140) uniform b_OutlineParams {
+    vec4 u_OutlineColor;
+    vec4 u_OutlineExtent;
+    int u_OutlineFlags;
+};
+
+// Posed like `pick_vs.glsl`, then pushed outwards on screen by the outline width.
+void main() {
+    vec3 local_position = a_Position.xyz;
+    if ((u_OutlineFlags & DISPLACEMENT_BUFFER) != 0) {
+        uint num_targets = uvec2(textureSize(u_Displacements, 0)).y / 3U;
+        for (uint i = 0U; i < min(num_targets, MAX_TARGETS); ++i) {
+            DisplacementContribution disp = u_DisplacementContributions[i];
+            local_position += disp.position * disp.weight * texelFetch(u_Displacements, ivec2(gl_VertexID, 3U*i+0U), 0).xyz;
+        }
+    }
+
+    mat4 mx_world = transpose(mat4(i_World0, i_World1, i_World2, vec4(0.0, 0.0, 0.0, 1.0)));
+    mat4 mx_model = mx_world * compute_skin_transform();
+    gl_Position = u_ViewProj * mx_model * vec4(local_position, a_Position.w);
+
+    // zero for the marking pass and lines
+    if (u_OutlineExtent.x > 0.0) {
+        vec2 dir;
+        if ((u_OutlineFlags & RADIAL) != 0) {
+            // sprites have no meaningful normals, but are convex
+            vec4 center = u_ViewProj * mx_world * vec4(0.0, 0.0, 0.0, 1.0);
+            dir = gl_Position.xy / gl_Position.w - center.xy / center.w;
+        } else {
+            dir = (u_ViewProj * mx_model * vec4(a_Normal.xyz, 0.0)).xy;
+        }
+        // normalize in pixels, so the width is the same along both axes
+        vec2 pixels = dir * u_OutlineExtent.zw;
+        if (dot(pixels, pixels) > 0.0) {
+            gl_Position.xy += normalize(pixels) * u_OutlineExtent.xy * gl_Position.w;
+        }
+    }
+}
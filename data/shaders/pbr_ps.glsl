@@ -1,19 +1,18 @@
 #version 150 core
 #include <lights>
 #include <globals>
-
-const int BASE_COLOR_MAP          = 1 << 0;
-const int NORMAL_MAP              = 1 << 1;
-const int METALLIC_ROUGHNESS_MAP  = 1 << 2;
-const int EMISSIVE_MAP            = 1 << 3;
-const int OCCLUSION_MAP           = 1 << 4;
-const int DISPLACEMENT_BUFFER     = 1 << 5;
+#include <clusters>
+#include <fog>
+#include <ssao>
 
 uniform sampler2D u_BaseColorSampler;
 uniform sampler2D u_NormalSampler;
 uniform sampler2D u_EmissiveSampler;
 uniform sampler2D u_MetallicRoughnessSampler;
 uniform sampler2D u_OcclusionSampler;
+uniform samplerCube t_Irradiance;
+uniform samplerCube t_Specular;
+uniform sampler2D t_BrdfLut;
 
 layout(std140) uniform b_PbrParams {
     vec4 u_BaseColorFactor;
@@ -22,7 +21,6 @@ layout(std140) uniform b_PbrParams {
     vec2 u_MetallicRoughnessValues;
     float u_NormalScale;
     float u_OcclusionStrength;
-    int u_PbrFlags;
 };
 
 in vec3 v_Position;
@@ -80,39 +78,37 @@ float ggx(PbrInfo pbr) {
     return roughness_sq / (PI * f * f);
 }
 
-bool available(int flag) {
-    return (u_PbrFlags & flag) == flag;
-}
-
 void main() {
     vec3 v = normalize(u_Camera - v_Position);
 
-    vec3 n;
-    if (available(NORMAL_MAP)) {
-        n = texture(u_NormalSampler, v_TexCoord).rgb;
-        n = normalize(v_Tbn * ((2.0 * n - 1.0) * vec3(u_NormalScale, u_NormalScale, 1.0)));
-    } else {
-        n = v_Tbn[2].xyz;
+#ifdef NORMAL_MAP
+    vec3 n = texture(u_NormalSampler, v_TexCoord).rgb;
+    n = normalize(v_Tbn * ((2.0 * n - 1.0) * vec3(u_NormalScale, u_NormalScale, 1.0)));
+#else
+    vec3 n = v_Tbn[2].xyz;
+#endif
+    // back faces are only drawn by double sided materials, lit as seen
+    if (!gl_FrontFacing) {
+        n = -n;
     }
 
     float perceptual_roughness = u_MetallicRoughnessValues.y;
     float metallic = u_MetallicRoughnessValues.x;
 
-    if (available(METALLIC_ROUGHNESS_MAP)) {
-        vec4 mr_sample = texture(u_MetallicRoughnessSampler, v_TexCoord);
-        perceptual_roughness = mr_sample.g * perceptual_roughness;
-        metallic = mr_sample.b * metallic;
-    }
+#ifdef METALLIC_ROUGHNESS_MAP
+    vec4 mr_sample = texture(u_MetallicRoughnessSampler, v_TexCoord);
+    perceptual_roughness = mr_sample.g * perceptual_roughness;
+    metallic = mr_sample.b * metallic;
+#endif
 
     perceptual_roughness = clamp(perceptual_roughness, MIN_ROUGHNESS, 1.0);
     metallic = clamp(metallic, 0.0, 1.0);
 
-    vec4 base_color;
-    if (available(BASE_COLOR_MAP)) {
-        base_color = texture(u_BaseColorSampler, v_TexCoord) * u_BaseColorFactor;
-    } else {
-        base_color = u_BaseColorFactor;
-    }
+#ifdef BASE_COLOR_MAP
+    vec4 base_color = texture(u_BaseColorSampler, v_TexCoord) * u_BaseColorFactor;
+#else
+    vec4 base_color = u_BaseColorFactor;
+#endif
 
     vec3 f0 = vec3(0.04);
     vec3 diffuse_color = mix(base_color.rgb * (1.0 - f0), vec3(0.0, 0.0, 0.0), metallic);
@@ -132,9 +128,10 @@ void main() {
     float alpha_roughness = perceptual_roughness * perceptual_roughness;
 
     vec3 color = vec3(0.0);
-    for (uint i = 0U; i < min(MAX_LIGHTS, u_NumLights); ++i) {
-        Light light = u_Lights[i];
-        vec3 l = normalize(light.dir.xyz);
+    uvec2 cluster = light_cluster(v_Position);
+    for (uint k = 0U; k < light_count(cluster); ++k) {
+        Light light = u_Lights[light_index(cluster, k)];
+        vec3 l = normalize(light.pos.xyz - light.pos.w * v_Position);
         vec3 h = normalize(l + v);
         vec3 reflection = -normalize(reflect(v, n));
 
@@ -161,18 +158,29 @@ void main() {
         float d = ggx(pbr_inputs);
         vec3 diffuse_contrib = (1.0 - f) * lambertian_diffuse(pbr_inputs);
         vec3 spec_contrib = f * g * d / (4.0 * ndotl * ndotv);
-        color += ndotl * light.intensity.y * light.color.rgb * (diffuse_contrib + spec_contrib);
+        color += light_attenuation(light, v_Position) * ndotl * light.intensity.y * light.color.rgb * (diffuse_contrib + spec_contrib);
     }
 
-    if (available(OCCLUSION_MAP)) {
-        float ao = texture(u_OcclusionSampler, v_TexCoord).r;
-        color = mix(color, color * ao, u_OcclusionStrength);
+    // image-based lighting, see `render::ibl`
+    if (u_Environment.z > 0.0) {
+        float ndotv = clamp(dot(n, v), 0.001, 1.0);
+        vec3 r = reflect(-v, n);
+        vec2 brdf = texture(t_BrdfLut, vec2(ndotv, perceptual_roughness)).rg;
+        vec3 diffuse = texture(t_Irradiance, n).rgb * diffuse_color;
+        vec3 specular = textureLod(t_Specular, r, perceptual_roughness * u_Environment.y).rgb * (specular_environment_r0 * brdf.x + specular_environment_r90 * brdf.y);
+        color += ssao_factor() * u_Environment.x * (diffuse + specular);
     }
 
-    if (available(EMISSIVE_MAP)) {
-        vec3 emissive = texture(u_EmissiveSampler, v_TexCoord).rgb * u_EmissiveFactor;
-        color += emissive;
-    }
+    // applies on top of the SSAO of the ambient term above
+#ifdef OCCLUSION_MAP
+    float ao = texture(u_OcclusionSampler, v_TexCoord).r;
+    color = mix(color, color * ao, u_OcclusionStrength);
+#endif
+
+#ifdef EMISSIVE_MAP
+    vec3 emissive = texture(u_EmissiveSampler, v_TexCoord).rgb * u_EmissiveFactor;
+    color += emissive;
+#endif
 
-    Target0 = vec4(color, base_color.a);
+    Target0 = apply_fog(vec4(color, base_color.a), v_Position);
 }
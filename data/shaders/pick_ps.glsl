@@ -0,0 +1,14 @@
+#version 150 core
+
+layout(std140) uniform b_PickParams {
+    uint u_PickId;
+    int u_PickFlags;
+};
+
+out uint Target0;
+out float Target1;
+
+void main() {
+    Target0 = u_PickId;
+    Target1 = gl_FragCoord.z;
+}
@@ -0,0 +1,36 @@
+#version 150 core
+#define MAX_TARGETS 8U
+#include <globals>
+
+// Has to match `render::DISPLACEMENT_BUFFER`.
+const int DISPLACEMENT_BUFFER = 1 << 5;
+
+in vec4 a_Position;
+in ivec4 a_JointIndices;
+in vec4 a_JointWeights;
+
+in vec4 i_World0;
+in vec4 i_World1;
+in vec4 i_World2;
+
+#include <skinning>
+
+layout(std140) uniform b_PickParams {
+    uint u_PickId;
+    int u_PickFlags;
+};
+
+// Same transform as `pbr_vs.glsl`, so picking matches what is drawn.
+void main() {
+    vec3 local_position = a_Position.xyz;
+    if ((u_PickFlags & DISPLACEMENT_BUFFER) != 0) {
+        uint num_targets = uvec2(textureSize(u_Displacements, 0)).y / 3U;
+        for (uint i = 0U; i < min(num_targets, MAX_TARGETS); ++i) {
+            DisplacementContribution disp = u_DisplacementContributions[i];
+            local_position += disp.position * disp.weight * texelFetch(u_Displacements, ivec2(gl_VertexID, 3U*i+0U), 0).xyz;
+        }
+    }
+
+    mat4 mx_world = transpose(mat4(i_World0, i_World1, i_World2, vec4(0.0, 0.0, 0.0, 1.0)));
+    gl_Position = u_ViewProj * mx_world * compute_skin_transform() * vec4(local_position, a_Position.w);
+}
@@ -0,0 +1,14 @@
+// Inputs shared by the post-processing passes, see `render::post`.
+
+layout(std140) uniform b_PostParams {
+    vec4 u_Params0;
+    vec4 u_Params1;
+    // Size of `t_Input`: width, height, 1/width, 1/height.
+    vec4 u_Resolution;
+    int u_Mode;
+};
+
+uniform sampler2D t_Input;
+
+in vec2 v_TexCoord;
+out vec4 Target0;
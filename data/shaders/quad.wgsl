@@ -0,0 +1,38 @@
+// Prelude of custom post-processing passes, see `render::post::CustomPass`.
+//
+// Mirrors the `quad` pipeline: the vertex stage covers `u_Quad.rect` and the
+// fragment stage is expected to be provided as `fs_main`.
+
+struct QuadParams {
+    rect: vec4<f32>,
+    depth: f32,
+};
+
+struct PostParams {
+    // `CustomPass::params`.
+    user: array<vec4<f32>, 4>,
+    // Size of `t_Input`: width, height, 1/width, 1/height.
+    resolution: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> u_Quad: QuadParams;
+@group(0) @binding(1) var<uniform> u_Post: PostParams;
+@group(0) @binding(2) var t_Input: texture_2d<f32>;
+@group(0) @binding(3) var s_Input: sampler;
+@group(0) @binding(4) var t_Depth: texture_depth_2d;
+
+struct QuadOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+};
+
+@vertex
+fn vs_quad(@builtin(vertex_index) index: u32) -> QuadOutput {
+    var corners = array<vec2<f32>, 4>(vec2(1.0, 0.0), vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0));
+    let corner = corners[index];
+    var out: QuadOutput;
+    out.position = vec4<f32>(mix(u_Quad.rect.xy, u_Quad.rect.zw, corner), u_Quad.depth, 1.0);
+    // WGSL textures have their origin at the top left
+    out.tex_coord = vec2<f32>(corner.x, 1.0 - corner.y);
+    return out;
+}
@@ -0,0 +1,120 @@
+// Shadow map filtering, has to match `render::ShadowType`.
+#define SHADOW_OFF          0
+#define SHADOW_BASIC        1
+#define SHADOW_PCF          2
+#define SHADOW_PCF_POISSON  3
+#define SHADOW_PCSS         4
+
+#define POISSON_SAMPLES     16
+
+uniform sampler2DShadow t_Shadow0;
+uniform sampler2DShadow t_Shadow1;
+uniform sampler2D t_ShadowDepth0;
+uniform sampler2D t_ShadowDepth1;
+
+const vec2 POISSON_DISK[POISSON_SAMPLES] = vec2[](
+    vec2(-0.94201624, -0.39906216), vec2( 0.94558609, -0.76890725),
+    vec2(-0.09418410, -0.92938870), vec2( 0.34495938,  0.29387760),
+    vec2(-0.91588581,  0.45771432), vec2(-0.81544232, -0.87912464),
+    vec2(-0.38277543,  0.27676845), vec2( 0.97484398,  0.75648379),
+    vec2( 0.44323325, -0.97511554), vec2( 0.53742981, -0.47373420),
+    vec2(-0.26496911, -0.41893023), vec2( 0.79197514,  0.19090188),
+    vec2(-0.24188840,  0.99706507), vec2(-0.81409955,  0.91437590),
+    vec2( 0.19984126,  0.78641367), vec2( 0.14383161, -0.14100790)
+);
+
+float shadow_compare(int map, vec3 coord) {
+    return map == 0 ? texture(t_Shadow0, coord) : texture(t_Shadow1, coord);
+}
+
+float shadow_depth(int map, vec2 uv) {
+    return map == 0 ? texture(t_ShadowDepth0, uv).r : texture(t_ShadowDepth1, uv).r;
+}
+
+vec2 shadow_texel_size(int map) {
+    return 1.0 / vec2(map == 0 ? textureSize(t_Shadow0, 0) : textureSize(t_Shadow1, 0));
+}
+
+// Interleaved gradient noise, used to rotate the Poisson disk per pixel.
+mat2 shadow_disk_rotation() {
+    float angle = 6.2831853 * fract(52.9829189 * fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))));
+    float s = sin(angle), c = cos(angle);
+    return mat2(c, s, -s, c);
+}
+
+float filter_pcf(int map, vec3 coord) {
+    vec2 texel = shadow_texel_size(map);
+    float sum = 0.0;
+    for (int x = -1; x <= 1; ++x) {
+        for (int y = -1; y <= 1; ++y) {
+            sum += shadow_compare(map, vec3(coord.xy + vec2(x, y) * texel, coord.z));
+        }
+    }
+    return sum / 9.0;
+}
+
+float filter_poisson(int map, vec3 coord, vec2 radius) {
+    mat2 rotation = shadow_disk_rotation();
+    float sum = 0.0;
+    for (int i = 0; i < POISSON_SAMPLES; ++i) {
+        vec2 offset = rotation * POISSON_DISK[i] * radius;
+        sum += shadow_compare(map, vec3(coord.xy + offset, coord.z));
+    }
+    return sum / float(POISSON_SAMPLES);
+}
+
+// `light_size` is the size of the emitter in shadow map UV units.
+float filter_pcss(int map, vec3 coord, float light_size) {
+    // Blocker search: average depth of the occluders within the light's footprint.
+    mat2 rotation = shadow_disk_rotation();
+    float blocker_sum = 0.0;
+    int num_blockers = 0;
+    for (int i = 0; i < POISSON_SAMPLES; ++i) {
+        vec2 offset = rotation * POISSON_DISK[i] * light_size;
+        float depth = shadow_depth(map, coord.xy + offset);
+        if (depth < coord.z) {
+            blocker_sum += depth;
+            num_blockers += 1;
+        }
+    }
+    if (num_blockers == 0) {
+        return 1.0;
+    }
+    float blocker = blocker_sum / float(num_blockers);
+    // Penumbra estimation for parallel light: the depth is linear across the
+    // range of the orthographic projection, so the penumbra widens linearly
+    // with the occluder distance and reaches `light_size` over the whole range.
+    float penumbra = light_size * (coord.z - blocker);
+    vec2 radius = max(vec2(penumbra), shadow_texel_size(map));
+    return filter_poisson(map, coord, radius);
+}
+
+// Returns the lit fraction of the fragment in [0, 1].
+//
+// * `map` is the shadow map index, negative when the light casts no shadow,
+//   only `t_Shadow0` and `t_Shadow1` are bound, see `render::MAX_SHADOWS`.
+// * `technique` is one of the `SHADOW_*` techniques.
+// * `bias` holds the depth bias in `x` and the PCSS light size in `z`.
+//
+// Always 1.0 unless `SHADOWS` is defined, see `render::source::Features`.
+float shadow_factor(int map, vec4 shadow_coord, int technique, vec4 bias) {
+#ifdef SHADOWS
+    if (map < 0 || map > 1 || technique == SHADOW_OFF || shadow_coord.w == 0.0) {
+        return 1.0;
+    }
+    vec3 coord = 0.5 * shadow_coord.xyz / shadow_coord.w + 0.5;
+    coord.z -= bias.x;
+    if (technique == SHADOW_PCF) {
+        return filter_pcf(map, coord);
+    }
+    if (technique == SHADOW_PCF_POISSON) {
+        return filter_poisson(map, coord, 1.5 * shadow_texel_size(map));
+    }
+    if (technique == SHADOW_PCSS) {
+        return filter_pcss(map, coord, bias.z);
+    }
+    return shadow_compare(map, coord);
+#else
+    return 1.0;
+#endif
+}
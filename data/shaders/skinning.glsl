@@ -0,0 +1,38 @@
+// Skinning and morph target inputs shared by the vertex stages of `Material::Pbr`,
+// picking and outlines. Requires `MAX_TARGETS`, `a_JointIndices` and `a_JointWeights`
+// to be declared first.
+
+// Toggles displacement contributions to `a_Position/a_Normal/a_Tangent`.
+struct DisplacementContribution {
+    // position: 1.0 if morph target weights should influence a_Position
+    // normal: 1.0 if morph target weights should influence a_Normal
+    // tangent: 1.0 if morph target weights should influence a_Tangent
+    // weight: The weight to be applied.
+    float position, normal, tangent, weight;
+};
+
+layout(std140) uniform b_DisplacementContributions {
+    DisplacementContribution u_DisplacementContributions[MAX_TARGETS];
+};
+
+uniform samplerBuffer b_JointTransforms;
+uniform sampler2D u_Displacements;
+
+//TODO: store each join transform in 3 vectors, similar to `i_WorldX`
+
+mat4 fetch_joint_transform(int i) {
+    //Note: has to match `render::VECS_PER_BONE`
+    vec4 row0 = texelFetch(b_JointTransforms, 3 * i + 0);
+    vec4 row1 = texelFetch(b_JointTransforms, 3 * i + 1);
+    vec4 row2 = texelFetch(b_JointTransforms, 3 * i + 2);
+
+    return transpose(mat4(row0, row1, row2, vec4(0.0, 0.0, 0.0, 1.0)));
+}
+
+mat4 compute_skin_transform() {
+    return
+        a_JointWeights.x * fetch_joint_transform(a_JointIndices.x) +
+        a_JointWeights.y * fetch_joint_transform(a_JointIndices.y) +
+        a_JointWeights.z * fetch_joint_transform(a_JointIndices.z) +
+        a_JointWeights.w * fetch_joint_transform(a_JointIndices.w);
+}
@@ -1,10 +1,13 @@
 #version 150 core
+#include <globals>
+#include <fog>
 
 in vec2 v_TexCoord;
+in vec3 v_World;
 out vec4 Target0;
 
 uniform sampler2D t_Map;
 
 void main() {
-    Target0 = texture(t_Map, v_TexCoord);
+    Target0 = apply_fog(texture(t_Map, v_TexCoord), v_World);
 }
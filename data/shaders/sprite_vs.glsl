@@ -4,6 +4,7 @@
 in vec4 a_Position;
 in vec2 a_TexCoord;
 out vec2 v_TexCoord;
+out vec3 v_World;
 in vec4 i_World0;
 in vec4 i_World1;
 in vec4 i_World2;
@@ -12,5 +13,7 @@ in vec4 i_UvRange;
 void main() {
     mat4 m_World = transpose(mat4(i_World0, i_World1, i_World2, vec4(0.0, 0.0, 0.0, 1.0)));
     v_TexCoord = mix(i_UvRange.xy, i_UvRange.zw, a_TexCoord);
-    gl_Position = u_ViewProj * m_World * a_Position;
+    vec4 world = m_World * a_Position;
+    v_World = world.xyz;
+    gl_Position = u_ViewProj * world;
 }
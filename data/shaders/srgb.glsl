@@ -0,0 +1,13 @@
+// Has to match `color::to_linear_rgb`.
+vec3 srgb_to_linear(vec3 x) {
+    vec3 lo = x / 12.92;
+    vec3 hi = pow((x + 0.055) / 1.055, vec3(2.4));
+    return mix(lo, hi, step(vec3(0.04045), x));
+}
+
+// Has to match `color::from_linear_rgb`.
+vec3 linear_to_srgb(vec3 x) {
+    vec3 lo = 12.92 * x;
+    vec3 hi = 1.055 * pow(x, vec3(1.0 / 2.4)) - 0.055;
+    return mix(lo, hi, step(vec3(0.0031308), x));
+}
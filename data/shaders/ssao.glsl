@@ -0,0 +1,12 @@
+// Ambient occlusion computed by the SSAO pass, see `render::ssao`.
+// Requires <globals> to be included first.
+
+uniform sampler2D t_Ssao;
+
+// Visibility of the ambient light at the current fragment, 1 when SSAO is disabled.
+float ssao_factor() {
+    if (u_Ssao.x == 0.0) {
+        return 1.0;
+    }
+    return texture(t_Ssao, gl_FragCoord.xy * u_Ssao.yz).r;
+}
@@ -0,0 +1,27 @@
+#version 150 core
+#include <post>
+
+// u_Params0: direction in texels, depth sharpness
+
+// Separable gaussian blur of the occlusion that doesn't cross depth edges.
+void main() {
+    const float WEIGHTS[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+    vec2 center = texture(t_Input, v_TexCoord).rg;
+    if (center.g <= 0.0) {
+        Target0 = vec4(center, 0.0, 1.0);
+        return;
+    }
+    vec2 step = u_Params0.xy * u_Resolution.zw;
+    float sum = center.r * WEIGHTS[0];
+    float total = WEIGHTS[0];
+    for (int i = 1; i < 5; ++i) {
+        for (int side = -1; side <= 1; side += 2) {
+            vec2 tap = texture(t_Input, v_TexCoord + float(side * i) * step).rg;
+            // relative depth difference, so the falloff doesn't depend on the distance
+            float weight = WEIGHTS[i] * exp(-u_Params0.z * abs(tap.g - center.g) / center.g);
+            sum += tap.r * weight;
+            total += weight;
+        }
+    }
+    Target0 = vec4(sum / total, center.g, 0.0, 1.0);
+}
@@ -0,0 +1,2 @@
+#version 150 core
+#include <fullscreen>
@@ -0,0 +1,71 @@
+#version 150 core
+#include <globals>
+
+// Has to match `render::ssao::MAX_SAMPLES`.
+#define MAX_SAMPLES 64
+
+layout(std140) uniform b_SsaoParams {
+    // hemisphere offsets around +Z, scaled to the unit sphere
+    vec4 u_Kernel[MAX_SAMPLES];
+    mat4 u_Proj;
+    // radius, bias, strength, sample count
+    vec4 u_SsaoParams;
+    // offset and scale of the rendered viewport, in texture coordinates
+    vec4 u_Viewport;
+};
+
+uniform sampler2D t_NormalDepth;
+
+in vec2 v_TexCoord;
+out vec4 Target0;
+
+// View space position of the pixel at `uv` with the given linear depth.
+vec3 view_position(vec2 uv, float depth) {
+    vec2 ndc = 2.0 * (uv - u_Viewport.xy) / u_Viewport.zw - 1.0;
+    vec4 p0 = u_InverseProj * vec4(ndc, -1.0, 1.0);
+    vec4 p1 = u_InverseProj * vec4(ndc, 0.0, 1.0);
+    vec3 a = p0.xyz / p0.w;
+    vec3 b = p1.xyz / p1.w;
+    return a + (b - a) * ((-depth - a.z) / (b.z - a.z));
+}
+
+// Jimenez' interleaved gradient noise, rotates the kernel per pixel.
+float noise(vec2 pixel) {
+    return fract(52.9829189 * fract(dot(pixel, vec2(0.06711056, 0.00583715))));
+}
+
+void main() {
+    vec4 normal_depth = texture(t_NormalDepth, v_TexCoord);
+    float depth = normal_depth.w;
+    // background, nothing to occlude
+    if (depth <= 0.0) {
+        Target0 = vec4(1.0, 0.0, 0.0, 1.0);
+        return;
+    }
+    vec3 position = view_position(v_TexCoord, depth);
+    vec3 normal = normalize(normal_depth.xyz);
+
+    float angle = 6.283185307 * noise(gl_FragCoord.xy);
+    vec3 random = vec3(cos(angle), sin(angle), 0.0);
+    vec3 tangent = normalize(random - normal * dot(random, normal));
+    mat3 tbn = mat3(tangent, cross(normal, tangent), normal);
+
+    float radius = u_SsaoParams.x;
+    int count = int(u_SsaoParams.w);
+    float occlusion = 0.0;
+    for (int i = 0; i < count; ++i) {
+        vec3 sample_pos = position + tbn * u_Kernel[i].xyz * radius;
+        vec4 clip = u_Proj * vec4(sample_pos, 1.0);
+        vec2 uv = u_Viewport.xy + (clip.xy / clip.w * 0.5 + 0.5) * u_Viewport.zw;
+        float scene_depth = texture(t_NormalDepth, uv).w;
+        if (scene_depth <= 0.0) {
+            continue;
+        }
+        // ignore occluders far in front of the sampled point
+        float range = smoothstep(0.0, 1.0, radius / abs(depth - scene_depth));
+        occlusion += (scene_depth <= -sample_pos.z - u_SsaoParams.y ? 1.0 : 0.0) * range;
+    }
+    float ao = pow(1.0 - occlusion / float(max(count, 1)), u_SsaoParams.z);
+    // the depth is kept for the bilateral blur
+    Target0 = vec4(ao, depth, 0.0, 1.0);
+}
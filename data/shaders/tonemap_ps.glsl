@@ -0,0 +1,69 @@
+#version 150 core
+#include <srgb>
+
+// Has to match `render::ToneMapping`.
+#define TONE_MAPPING_NONE       0
+#define TONE_MAPPING_REINHARD   1
+#define TONE_MAPPING_ACES       2
+#define TONE_MAPPING_AGX        3
+
+in vec2 v_TexCoord;
+out vec4 Target0;
+
+uniform sampler2D t_Input;
+
+layout(std140) uniform b_TonemapParams {
+    float u_Exposure;
+    int u_Operator;
+    int u_OutputSrgb;
+};
+
+// Narkowicz 2015, "ACES Filmic Tone Mapping Curve".
+vec3 tonemap_aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+// Polynomial fit of the AgX base contrast curve by Benjamin Wrensch,
+// https://iolite-engine.com/blog_posts/minimal_agx_implementation
+vec3 agx_contrast(vec3 x) {
+    vec3 x2 = x * x;
+    vec3 x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+vec3 tonemap_agx(vec3 color) {
+    const mat3 agx_in = mat3(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104);
+    const mat3 agx_out = mat3(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116);
+    const float min_ev = -12.47393;
+    const float max_ev = 4.026069;
+    color = agx_in * color;
+    color = clamp(log2(max(color, vec3(1e-10))), min_ev, max_ev);
+    color = (color - min_ev) / (max_ev - min_ev);
+    color = agx_contrast(color);
+    color = agx_out * color;
+    // the curve outputs sRGB encoded values, bring them back to linear
+    return pow(max(color, vec3(0.0)), vec3(2.2));
+}
+
+void main() {
+    vec4 hdr = texture(t_Input, v_TexCoord);
+    vec3 color = hdr.rgb * u_Exposure;
+    if (u_Operator == TONE_MAPPING_REINHARD) {
+        color = color / (1.0 + color);
+    } else if (u_Operator == TONE_MAPPING_ACES) {
+        color = tonemap_aces(color);
+    } else if (u_Operator == TONE_MAPPING_AGX) {
+        color = tonemap_agx(color);
+    }
+    color = clamp(color, 0.0, 1.0);
+    if (u_OutputSrgb != 0) {
+        color = linear_to_srgb(color);
+    }
+    Target0 = vec4(color, hdr.a);
+}
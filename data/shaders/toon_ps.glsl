@@ -0,0 +1,73 @@
+#version 150 core
+#include <lights>
+#include <globals>
+#include <clusters>
+#include <shadows>
+#include <fog>
+#include <ssao>
+
+in vec3 v_World;
+in vec3 v_Normal;
+// rgb, specular threshold
+in vec4 v_Color;
+// rgb, power
+in vec4 v_Rim;
+// of the gradient ramp, zero without one
+in vec4 v_UvRange;
+
+out vec4 Target0;
+
+uniform sampler2D t_Map;
+
+// Quantizes the diffuse term in [0, 1], two tones without a ramp.
+vec3 toon_diffuse(float intensity) {
+    if (v_UvRange == vec4(0.0)) {
+        return vec3(step(0.5, intensity));
+    }
+    vec2 uv = mix(v_UvRange.xy, v_UvRange.zw, vec2(clamp(intensity, 0.0, 1.0), 0.5));
+    return texture(t_Map, uv).rgb;
+}
+
+void main() {
+    vec3 normal = normalize(v_Normal);
+    // back faces are only drawn by double sided materials, lit as seen
+    if (!gl_FrontFacing) {
+        normal = -normal;
+    }
+    vec3 camera = -transpose(mat3(u_View)) * u_View[3].xyz;
+    vec3 view = normalize(camera - v_World);
+    float specular_threshold = v_Color.w;
+    float ambient_occlusion = ssao_factor();
+
+    vec3 color = vec3(0.0);
+    uvec2 cluster = light_cluster(v_World);
+    for (uint k = 0U; k < light_count(cluster); ++k) {
+        Light light = u_Lights[light_index(cluster, k)];
+        vec4 shadow_coord = light.projection * vec4(v_World + normal * light.shadow_bias.y, 1.0);
+        float shadow = light_attenuation(light, v_World) * shadow_factor(light.shadow_params[0], shadow_coord, light.shadow_params[1], light.shadow_bias);
+        // hemisphere and ambient lights are not quantized
+        if (dot(light.color_back, light.color_back) > 0.0) {
+            vec3 dir = light.pos.xyz - light.pos.w * v_World;
+            vec4 irradiance = mix(light.color_back, light.color, dot(normal, normalize(dir)) * 0.5 + 0.5);
+            color += ambient_occlusion * light.intensity.x * v_Color.rgb * irradiance.rgb;
+            continue;
+        }
+        color += ambient_occlusion * light.intensity.x * v_Color.rgb * light.color.rgb;
+        if (shadow == 0.0) {
+            continue;
+        }
+        vec3 dir = normalize(light.pos.xyz - light.pos.w * v_World);
+        float dot_nl = dot(normal, dir);
+        color += light.intensity.y * toon_diffuse(shadow * max(0.0, dot_nl)) * v_Color.rgb * light.color.rgb;
+        if (dot_nl > 0.0 && specular_threshold < 1.0) {
+            float ks = dot(normal, normalize(dir + view));
+            color += shadow * step(specular_threshold, ks) * light.color.rgb;
+        }
+    }
+
+    // rim light, brightest on the silhouette
+    float rim = pow(1.0 - max(0.0, dot(normal, view)), max(v_Rim.w, 0.001));
+    color += rim * v_Rim.rgb;
+
+    Target0 = apply_fog(vec4(color, 1.0), v_World);
+}
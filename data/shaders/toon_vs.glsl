@@ -0,0 +1,28 @@
+#version 150 core
+#include <globals>
+
+in vec4 a_Position;
+in vec4 a_Normal;
+out vec3 v_World;
+out vec3 v_Normal;
+out vec4 v_Color;
+out vec4 v_Rim;
+out vec4 v_UvRange;
+
+in vec4 i_World0;
+in vec4 i_World1;
+in vec4 i_World2;
+in vec4 i_MatParams;
+in vec4 i_Color;
+in vec4 i_UvRange;
+
+void main() {
+    mat4 m_World = transpose(mat4(i_World0, i_World1, i_World2, vec4(0.0, 0.0, 0.0, 1.0)));
+    vec4 world = m_World * a_Position;
+    v_World = world.xyz;
+    v_Normal = normalize(mat3(m_World) * a_Normal.xyz);
+    v_Color = i_Color;
+    v_Rim = i_MatParams;
+    v_UvRange = i_UvRange;
+    gl_Position = u_ViewProj * world;
+}
@@ -0,0 +1,49 @@
+#version 150 core
+#define MAX_TARGETS 8U
+#include <globals>
+
+// Has to match `render::DISPLACEMENT_BUFFER`.
+const int DISPLACEMENT_BUFFER = 1 << 5;
+// Has to match `render::debug::TANGENTS`.
+const int TANGENTS = 1 << 0;
+
+// per instance, one line is drawn for every vertex of the mesh
+in vec4 a_Position;
+in vec4 a_Normal;
+in vec4 a_Tangent;
+in ivec4 a_JointIndices;
+in vec4 a_JointWeights;
+
+#include <skinning>
+
+layout(std140) uniform b_VertexLinesParams {
+    mat4 u_World;
+    vec4 u_LineColor;
+    float u_LineLength;
+    int u_LineFlags;
+};
+
+out vec4 v_Color;
+
+void main() {
+    vec3 local_position = a_Position.xyz;
+    vec3 local_direction = (u_LineFlags & TANGENTS) != 0 ? a_Tangent.xyz : a_Normal.xyz;
+    if ((u_LineFlags & DISPLACEMENT_BUFFER) != 0) {
+        uint num_targets = uvec2(textureSize(u_Displacements, 0)).y / 3U;
+        uint row = (u_LineFlags & TANGENTS) != 0 ? 2U : 1U;
+        for (uint i = 0U; i < min(num_targets, MAX_TARGETS); ++i) {
+            DisplacementContribution disp = u_DisplacementContributions[i];
+            float weight = (u_LineFlags & TANGENTS) != 0 ? disp.tangent : disp.normal;
+            local_position += disp.position * disp.weight * texelFetch(u_Displacements, ivec2(gl_InstanceID, 3U*i+0U), 0).xyz;
+            local_direction += weight * disp.weight * texelFetch(u_Displacements, ivec2(gl_InstanceID, 3U*i+row), 0).xyz;
+        }
+    }
+
+    mat4 mx_model = u_World * compute_skin_transform();
+    vec3 position = (mx_model * vec4(local_position, 1.0)).xyz;
+    if (gl_VertexID == 1) {
+        position += u_LineLength * normalize(mat3(mx_model) * local_direction);
+    }
+    v_Color = u_LineColor;
+    gl_Position = u_ViewProj * vec4(position, 1.0);
+}
@@ -0,0 +1,14 @@
+#version 150 core
+#include <post>
+
+// u_Params0: intensity, radius, softness
+// u_Params1: color
+
+void main() {
+    vec4 color = texture(t_Input, v_TexCoord);
+    // keep the falloff circular regardless of the aspect ratio
+    vec2 d = (v_TexCoord - 0.5) * vec2(u_Resolution.x * u_Resolution.w, 1.0);
+    float radius = u_Params0.y;
+    float amount = u_Params0.x * smoothstep(radius - u_Params0.z, radius, length(d));
+    Target0 = vec4(mix(color.rgb, u_Params1.rgb, amount), color.a);
+}
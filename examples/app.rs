@@ -10,7 +10,7 @@ impl Three for App {
     fn update(&mut self) {
         let vertices = vec![[-0.5, -0.5, -0.5].into(), [0.5, -0.5, -0.5].into(), [0.0, 0.5, -0.5].into()];
         let geometry = three::Geometry::with_vertices(vertices);
-        let material = three::material::Basic { color: 0xFFFF00, map: None };
+        let material = three::material::Basic { color: 0xFFFF00, ..Default::default() };
         let mesh = window.factory.mesh(geometry, material);
         window.scene.add(&mesh);
         window.scene.background = three::Background::Color(0xC6F0FF);
@@ -14,7 +14,7 @@ fn main() {
     // let mut controls = Orbit::builder(&cam).position([0.0, 2.0, -5.0]).target([0.0, 0.0, 0.0]).build();
 
     let geometry = make_tetrahedron_geometry();
-    // let material = three::material::Wireframe { color: 0xFFFF00 };
+    // let material = three::material::Wireframe { color: 0xFFFF00, ..Default::default() };
     // let mut mesh = win.factory.mesh_dynamic(geometry, material);
     // let vertex_count = mesh.vertex_count();
     // win.scene.add(&mesh);
@@ -1,112 +1,70 @@
-use std::sync::mpsc;
-use std::{env, fs, io};
+use std::path::PathBuf;
+use std::{env, fs};
 
-use notify::Watcher;
-use std::path::{Path, PathBuf};
-use std::time::Duration;
-use three::Object;
-
-const MANDELBROT_VERTEX_SHADER_CODE: &'static str = r#"
-#version 150 core
-#include <locals>
+const MANDELBROT_SHADER_CODE: &'static str = r#"
+#include <mesh>
 #include <globals>
 
-in vec4 a_Position;
-in vec2 a_TexCoord;
-
-out vec2 v_TexCoord;
-
-void main() {
-    v_TexCoord = mix(u_UvRange.xy, u_UvRange.zw, a_TexCoord);
-    gl_Position = u_ViewProj * u_World * a_Position;
-}
-"#;
-
-const MANDELBROT_PIXEL_SHADER_CODE: &'static str = r#"
-#version 150 core
+@group(1) @binding(0) var t_Map: texture_2d<f32>;
+@group(1) @binding(1) var s_Map: sampler;
 
-in vec2 v_TexCoord;
-out vec4 Target0;
+const SCALE: f32 = 3.0;
+const CENTER: vec2<f32> = vec2<f32>(0.5, 0.0);
+const ITER: i32 = 100;
 
-uniform sampler2D t_Map;
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+};
 
-const float SCALE = 3.0;
-const vec2 CENTER = vec2(0.5, 0.0);
-const int ITER = 100;
+@vertex
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coord = mix(instance.uv_range.xy, instance.uv_range.zw, vertex.tex_coord);
+    out.position = u_Globals.mx_vp * instance_world(instance) * vertex.position;
+    return out;
+}
 
-void main() {
-    vec2 c;
-    c.x = 1.3333 * (v_TexCoord.x - 0.5) * SCALE - CENTER.x;
-    c.y = (v_TexCoord.y - 0.5) * SCALE - CENTER.y;
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let c = vec2<f32>(1.3333 * (in.tex_coord.x - 0.5) * SCALE - CENTER.x, (in.tex_coord.y - 0.5) * SCALE - CENTER.y);
 
-    int i;
-    vec2 z = c;
-    for (i = 0; i < ITER; ++i) {
-        float x = (z.x * z.x - z.y * z.y) + c.x;
-        float y = (z.y * z.x + z.x * z.y) + c.y;
-        if ((x * x + y * y) > 4.0) {
+    var i = 0;
+    var z = c;
+    for (; i < ITER; i++) {
+        let next = vec2<f32>(z.x * z.x - z.y * z.y, 2.0 * z.x * z.y) + c;
+        if (dot(next, next) > 4.0) {
             break;
         }
-        z.x = x;
-        z.y = y;
+        z = next;
     }
 
-    vec2 t = vec2(i == ITER ? 0.0 : float(i) / 100.0, 0.5);
-    Target0 = texture(t_Map, t);
+    let t = vec2<f32>(select(f32(i) / 100.0, 0.0, i == ITER), 0.5);
+    return textureSample(t_Map, s_Map, t);
 }
 "#;
 
 fn main() {
-    let dir = env::args().nth(1).map(PathBuf::from).or(env::current_dir().ok()).unwrap();
+    // the shader is written out, so keep it out of the working directory by default
+    let dir = env::args().nth(1).map(PathBuf::from).unwrap_or_else(|| env::temp_dir().join("three-reload"));
 
-    use io::Write;
     let _ = fs::create_dir_all(&dir);
-    fs::File::create(dir.join("sprite_vs.glsl")).unwrap().write_all(MANDELBROT_VERTEX_SHADER_CODE.as_bytes()).unwrap();
-    fs::File::create(dir.join("sprite_ps.glsl")).unwrap().write_all(MANDELBROT_PIXEL_SHADER_CODE.as_bytes()).unwrap();
+    fs::write(dir.join("mandelbrot.wgsl"), MANDELBROT_SHADER_CODE).unwrap();
 
-    println!("Edit sprite_vs.glsl or sprite_ps.glsl and review.");
+    println!("Edit and save {} to reload it, errors are logged.", dir.join("mandelbrot.wgsl").display());
 
-    let mut win = three::Window::new("Three-rs shader reloading example");
+    env_logger::init();
+    let mut win = three::Window::builder("Three-rs shader reloading example").shader_directory(&dir).build();
     let cam = win.factory.orthographic_camera([0.0, 0.0], 1.0, -1.0 .. 1.0);
 
-    let (tx, rx) = mpsc::channel();
-    let mut watcher = notify::watcher(tx, Duration::from_secs(1)).unwrap();
-    watcher.watch(&dir, notify::RecursiveMode::NonRecursive).unwrap();
-
+    let shader = win.factory.custom_shader("#include <mandelbrot>", "#include <mandelbrot>", Default::default()).unwrap();
     let map_path = concat!(env!("CARGO_MANIFEST_DIR"), "/test_data/texture.png");
-    let map = win.factory.load_texture(map_path);
-    let material = three::material::Sprite { map };
-    let sprite = win.factory.sprite(material);
-    sprite.set_scale(1.0);
-    win.scene.add(&sprite);
+    let mut material = three::material::Custom::new(shader);
+    material.textures.insert("Map".to_string(), win.factory.load_texture(map_path));
+    let mesh = win.factory.mesh(three::Geometry::plane(2.0, 2.0), material);
+    win.scene.add(&mesh);
 
-    let mut reload = true;
     while win.update() && !win.input.hit(three::KEY_ESCAPE) {
-        while let Ok(event) = rx.try_recv() {
-            use notify::DebouncedEvent::{Create, Write};
-            match event {
-                Create(_) | Write(_) => reload = true,
-                _ => {}
-            }
-        }
-        if reload {
-            reload = false;
-            let source_set = three::render::source::Set { sprite: three::render::source::Sprite::user(&dir).unwrap(), ..Default::default() };
-            match three::render::PipelineStates::new(&source_set, &mut win.factory) {
-                Ok(pipeline_states) => win.renderer.reload(pipeline_states),
-                Err(err) => println!("{:#?}", err),
-            }
-        }
         win.render(&cam);
     }
 }
-
-/// Reads the entire contents of a file into a `String`.
-pub fn read_file_to_string(path: &Path) -> io::Result<String> {
-    use self::io::Read;
-    let file = fs::File::open(path)?;
-    let len = file.metadata()?.len() as usize;
-    let mut contents = String::with_capacity(len);
-    let _ = io::BufReader::new(file).read_to_string(&mut contents)?;
-    Ok(contents)
-}
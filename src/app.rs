@@ -87,6 +87,10 @@ impl AppBuilder {
     }
 
     /// Specifies the user shader directory.
+    ///
+    /// The directory is watched for changes, WGSL chunks in it replace the
+    /// built-in ones and reload the pipelines including them, see
+    /// [`Renderer::watch_shaders`](render/struct.Renderer.html#method.watch_shaders).
     pub fn shader_directory<P: Into<PathBuf>>(&mut self, option: P) -> &mut Self {
         self.shader_directory = Some(option.into());
         self
@@ -144,7 +148,12 @@ impl AppBuilder {
         //     try_override!(basic, gouraud, pbr, phong, quad, shadow, skybox, sprite,);
         // }
         //
-        // let (renderer, windowedContext, mut factory) = Renderer::new(builder, context, &event_loop, &source_set);
+        // let (mut renderer, windowedContext, mut factory) = Renderer::new(builder, context, &event_loop, &source_set);
+        // if let Some(path) = self.shader_directory.as_ref() {
+        //     if let Err(err) = renderer.watch_shaders(path.clone()) {
+        //         error!("Can't watch {}: {}", path.display(), err);
+        //     }
+        // }
         // let dpi = windowedContext.window().get_hidpi_factor();
         // let scene = factory.scene();
         // Window { event_loop, windowedContext, dpi, input: Input::new(), renderer, factory, scene, reset_input: true, is_fullscreen }
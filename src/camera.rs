@@ -56,9 +56,17 @@
 //! [`Orthographic`]: struct.Orthographic.html
 //! [`Perspective`]: struct.Perspective.html
 
-// use crate::hub::{Hub, Operation, SubNode};
+// use crate::hub::Operation;
 // use object::{Base, DowncastObject, Object, ObjectType};
-// use scene::SyncGuard;
+
+use cgmath::prelude::*;
+use cgmath::{Matrix4, Point3, Vector3, Vector4};
+
+// use cgmath::Vector2;
+// use crate::hub::{Hub, SubNode};
+// use crate::node::NodePointer;
+// use crate::raycast::Ray;
+// use crate::scene::SyncGuard;
 
 use std::ops;
 
@@ -131,6 +139,84 @@ impl Camera {
         todo!()
         // self.as_ref().send(Operation::SetProjection(projection.into()));
     }
+
+    // /// View and projection matrices, from the world transform of the camera.
+    // pub(crate) fn matrices(&self, hub: &Hub, first_child: &Option<NodePointer>, aspect_ratio: f32) -> (Matrix4<f32>, Matrix4<f32>) {
+    //     let internal = &hub[self] as *const _;
+    //     let transform = hub.walk_all(first_child).find(|w| w.node as *const _ == internal).map_or(hub[self].transform, |w| w.world_transform);
+    //     let projection = match hub[self].sub_node {
+    //         SubNode::Camera(ref projection) => projection.matrix(aspect_ratio),
+    //         _ => panic!("Camera had incorrect sub node"),
+    //     };
+    //     (Matrix4::from(transform.inverse_transform().unwrap()), projection.into())
+    // }
+    //
+    // /// Returns the view matrix, i.e. the inverse of the world transform of the camera.
+    // pub fn view_matrix(&self, sync_guard: &SyncGuard) -> mint::ColumnMatrix4<f32> {
+    //     self.matrices(&sync_guard.hub, &sync_guard.scene.first_child, 1.0).0.into()
+    // }
+    //
+    // /// Returns the combined projection and view matrix, mapping world space to clip space.
+    // pub fn view_projection_matrix(&self, sync_guard: &SyncGuard, aspect_ratio: f32) -> mint::ColumnMatrix4<f32> {
+    //     let (mx_view, mx_proj) = self.matrices(&sync_guard.hub, &sync_guard.scene.first_child, aspect_ratio);
+    //     (mx_proj * mx_view).into()
+    // }
+    //
+    // /// Returns the world space ray going through a point in Normalized Display
+    // /// Coordinates, e.g. [`Input::mouse_pos_ndc`](../struct.Input.html#method.mouse_pos_ndc).
+    // ///
+    // /// The ray starts on the near plane of the camera.
+    // pub fn ray_from_ndc<P: Into<mint::Point2<f32>>>(&self, sync_guard: &SyncGuard, aspect_ratio: f32, ndc: P) -> Ray {
+    //     let (mx_view, mx_proj) = self.matrices(&sync_guard.hub, &sync_guard.scene.first_child, aspect_ratio);
+    //     let (origin, direction) = unproject_ray(mx_view, mx_proj, ndc.into());
+    //     Ray { origin: origin.into(), direction: direction.into() }
+    // }
+    //
+    // /// Projects a world space point to screen pixels, from the top left corner
+    // /// of a viewport of the given size, e.g. [`Window::size`](../struct.Window.html#method.size).
+    // ///
+    // /// Returns `None` for points behind the camera.
+    // pub fn world_to_screen<P: Into<mint::Point3<f32>>>(&self, sync_guard: &SyncGuard, size: mint::Vector2<f32>, point: P) -> Option<mint::Point2<f32>> {
+    //     let (mx_view, mx_proj) = self.matrices(&sync_guard.hub, &sync_guard.scene.first_child, size.x / size.y);
+    //     let clip = mx_proj * mx_view * Point3::from(point.into()).to_homogeneous();
+    //     if clip.w <= 0.0 {
+    //         return None;
+    //     }
+    //     let ndc = clip.truncate() / clip.w;
+    //     Some([0.5 * (ndc.x + 1.0) * size.x, 0.5 * (1.0 - ndc.y) * size.y].into())
+    // }
+    //
+    // /// Returns the world space point under a screen pixel, at `depth` along
+    // /// the view direction of the camera. Inverse of [`world_to_screen`](#method.world_to_screen).
+    // pub fn screen_to_world<P: Into<mint::Point2<f32>>>(&self, sync_guard: &SyncGuard, size: mint::Vector2<f32>, screen_pos: P, depth: f32) -> mint::Point3<f32> {
+    //     let screen_pos = screen_pos.into();
+    //     let (mx_view, mx_proj) = self.matrices(&sync_guard.hub, &sync_guard.scene.first_child, size.x / size.y);
+    //     let ndc = Vector2::new(2.0 * screen_pos.x / size.x - 1.0, 1.0 - 2.0 * screen_pos.y / size.y);
+    //     // view space points on the near plane and halfway through the depth range,
+    //     // the latter stays finite for infinite projections
+    //     let mx_inv_proj = mx_proj.invert().unwrap();
+    //     let a = Point3::from_homogeneous(mx_inv_proj * Vector4::new(ndc.x, ndc.y, -1.0, 1.0));
+    //     let b = Point3::from_homogeneous(mx_inv_proj * Vector4::new(ndc.x, ndc.y, 0.0, 1.0));
+    //     let view = a + (b - a) * ((-depth - a.z) / (b.z - a.z));
+    //     mx_view.invert().unwrap().transform_point(view).into()
+    // }
+    //
+    // /// Returns the planes bounding the volume visible by the camera, in world space.
+    // pub fn frustum(&self, sync_guard: &SyncGuard, aspect_ratio: f32) -> Frustum {
+    //     Frustum::from_matrix(self.view_projection_matrix(sync_guard, aspect_ratio))
+    // }
+}
+
+/// World space origin and direction of the ray through `ndc`, starting on the
+/// near plane.
+//TODO: Remove dead_code lint once `raycast` is built
+#[allow(dead_code)]
+pub(crate) fn unproject_ray(mx_view: Matrix4<f32>, mx_proj: Matrix4<f32>, ndc: mint::Point2<f32>) -> (Point3<f32>, Vector3<f32>) {
+    let mx_inverse = (mx_proj * mx_view).invert().unwrap();
+    // NDC depth `0` stays finite for infinite projections, unlike `1`
+    let unproject = |z: f32| Point3::from_homogeneous(mx_inverse * Vector4::new(ndc.x, ndc.y, z, 1.0));
+    let near = unproject(-1.0);
+    (near, (unproject(0.0) - near).normalize())
 }
 
 // impl DowncastObject for Camera {
@@ -220,3 +306,124 @@ impl Perspective {
         }
     }
 }
+
+/// A plane of points `p` with `dot(normal, p) + distance == 0`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    /// Unit normal, pointing to the inside of the frustum.
+    pub normal: mint::Vector3<f32>,
+    /// Signed distance from the origin, along the normal.
+    pub distance: f32,
+}
+
+impl Plane {
+    /// Signed distance from the plane to a point, positive on the normal side.
+    pub fn distance_to<P: Into<mint::Point3<f32>>>(&self, point: P) -> f32 {
+        Vector3::from(self.normal).dot(Point3::from(point.into()).to_vec()) + self.distance
+    }
+}
+
+/// Volume visible by a camera, see [`from_matrix`](#method.from_matrix).
+///
+/// The far plane of an infinite perspective projection has a zero normal
+/// and a positive distance, so every point is in front of it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far planes.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of a projection and view matrix (Gribb-Hartmann).
+    pub fn from_matrix<M: Into<mint::ColumnMatrix4<f32>>>(matrix: M) -> Self {
+        let m = Matrix4::from(matrix.into());
+        let row = |i: usize| Vector4::new(m.x[i], m.y[i], m.z[i], m.w[i]);
+        let plane = |v: Vector4<f32>| {
+            let length = v.truncate().magnitude();
+            let scale = if length > 0.0 { 1.0 / length } else { 1.0 };
+            Plane { normal: (v.truncate() * scale).into(), distance: v.w * scale }
+        };
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        Frustum { planes: [plane(r3 + r0), plane(r3 - r0), plane(r3 + r1), plane(r3 - r1), plane(r3 + r2), plane(r3 - r2)] }
+    }
+
+    /// Returns whether a point lies inside the frustum.
+    pub fn contains_point<P: Into<mint::Point3<f32>>>(&self, point: P) -> bool {
+        let point = point.into();
+        self.planes.iter().all(|plane| plane.distance_to(point) >= 0.0)
+    }
+
+    /// Returns whether a sphere is at least partly inside the frustum.
+    ///
+    /// Spheres close to the frustum corners may be reported as visible.
+    pub fn intersects_sphere<P: Into<mint::Point3<f32>>>(&self, center: P, radius: f32) -> bool {
+        let center = center.into();
+        self.planes.iter().all(|plane| plane.distance_to(center) >= -radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_plane(plane: &Plane, normal: [f32; 3], distance: f32) {
+        let error = (Vector3::from(plane.normal) - Vector3::from(normal)).magnitude() + (plane.distance - distance).abs();
+        assert!(error < 1e-5, "{:?} != {:?}, {}", plane, normal, distance);
+    }
+
+    #[test]
+    fn orthographic_planes() {
+        let projection = Projection::orthographic([0.0, 0.0], 1.0, 0.1 .. 10.0);
+        let frustum = Frustum::from_matrix(projection.matrix(2.0));
+        let [left, right, bottom, top, near, far] = &frustum.planes;
+        assert_plane(left, [1.0, 0.0, 0.0], 2.0);
+        assert_plane(right, [-1.0, 0.0, 0.0], 2.0);
+        assert_plane(bottom, [0.0, 1.0, 0.0], 1.0);
+        assert_plane(top, [0.0, -1.0, 0.0], 1.0);
+        assert_plane(near, [0.0, 0.0, -1.0], -0.1);
+        assert_plane(far, [0.0, 0.0, 1.0], 10.0);
+        assert!(frustum.contains_point([1.5, -0.5, -5.0]));
+        assert!(!frustum.contains_point([0.0, 0.0, -20.0]));
+        assert!(frustum.intersects_sphere([2.5, 0.0, -5.0], 1.0));
+        assert!(!frustum.intersects_sphere([3.5, 0.0, -5.0], 1.0));
+    }
+
+    #[test]
+    fn infinite_perspective_planes() {
+        let projection = Projection::perspective(90.0, 0.5 ..);
+        let frustum = Frustum::from_matrix(projection.matrix(1.0));
+        let [left, right, bottom, top, near, far] = &frustum.planes;
+        let h = 0.5f32.sqrt();
+        assert_plane(left, [h, 0.0, -h], 0.0);
+        assert_plane(right, [-h, 0.0, -h], 0.0);
+        assert_plane(bottom, [0.0, h, -h], 0.0);
+        assert_plane(top, [0.0, -h, -h], 0.0);
+        assert_plane(near, [0.0, 0.0, -1.0], -0.5);
+        assert_plane(far, [0.0, 0.0, 0.0], 1.0);
+        assert!(frustum.contains_point([0.0, 0.0, -1.0]));
+        assert!(frustum.contains_point([0.0, 0.0, -1.0e6]));
+        assert!(!frustum.contains_point([0.0, 0.0, -0.25]));
+        assert!(!frustum.contains_point([2.0, 0.0, -1.0]));
+    }
+
+    #[test]
+    fn unproject_round_trip() {
+        let mx_view = Matrix4::look_at_rh(Point3::new(1.0, 2.0, 3.0), Point3::new(0.0, 0.5, 0.0), Vector3::unit_y());
+        for projection in [Projection::perspective(60.0, 0.1 .. 100.0), Projection::perspective(60.0, 0.1 ..), Projection::orthographic([0.5, 0.0], 2.0, -1.0 .. 10.0)] {
+            let mx_proj = Matrix4::from(projection.matrix(1.5));
+            let mx_vp = mx_proj * mx_view;
+            for ndc in [[0.0f32, 0.0], [0.5, -0.25], [-1.0, 1.0]] {
+                let (origin, direction) = unproject_ray(mx_view, mx_proj, ndc.into());
+                assert!((direction.magnitude() - 1.0).abs() < 1e-5);
+                for (distance, depth) in [(0.0, Some(-1.0)), (5.0, None)] {
+                    let clip = mx_vp * (origin + direction * distance).to_homogeneous();
+                    let projected = clip.truncate() / clip.w;
+                    assert!((projected.x - ndc[0]).abs() < 1e-4 && (projected.y - ndc[1]).abs() < 1e-4, "{:?}: {:?} != {:?}", projection, projected, ndc);
+                    if let Some(depth) = depth {
+                        assert!((projected.z - depth).abs() < 1e-4, "{:?}: near depth {}", projection, projected.z);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -51,11 +51,29 @@ pub fn from_linear_rgb(c: [f32; 3]) -> Color {
     let f = |x: f32| -> u32 {
         let y = if x > 0.0031308 {
             let a = 0.055;
-            (1.0 + a) * x.powf(-2.4) - a
+            (1.0 + a) * x.powf(1.0 / 2.4) - a
         } else {
             12.92 * x
         };
-        (y * 255.0).round() as u32
+        (y.clamp(0.0, 1.0) * 255.0).round() as u32
     };
     f(c[0]) << 16 | f(c[1]) << 8 | f(c[2])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_rgb_round_trip() {
+        for v in 0 ..= 0xFF {
+            let c = v << 16 | (0xFF - v) << 8 | v;
+            assert_eq!(from_linear_rgb(to_linear_rgb(c)), c, "{:06X}", c);
+        }
+    }
+
+    #[test]
+    fn linear_rgb_clamps() {
+        assert_eq!(from_linear_rgb([-1.0, 0.0, 2.0]), 0x0000FF);
+    }
+}
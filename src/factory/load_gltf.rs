@@ -89,11 +89,13 @@ fn load_material<'a>(mat: gltf::Material<'a>, textures: &[Texture<[f32; 4]>]) ->
         (color::from_linear_rgb([x[0], x[1], x[2]]), x[3])
     };
 
+    let state = material::RenderState { side: if mat.double_sided() { material::Side::Double } else { material::Side::Front }, ..Default::default() };
+
     if false {
         // is_basic_material {
-        material::Basic { color: base_color_factor, map: base_color_map }.into()
+        material::Basic { color: base_color_factor, map: base_color_map, state }.into()
     } else {
-        material::Pbr { base_color_factor, base_color_alpha, metallic_factor: pbr.metallic_factor(), roughness_factor: pbr.roughness_factor(), occlusion_strength: mat.occlusion_texture().map_or(1.0, |t| t.strength()), emissive_factor: color::from_linear_rgb(mat.emissive_factor()), normal_scale: mat.normal_texture().map_or(1.0, |t| t.scale()), base_color_map, normal_map, emissive_map, metallic_roughness_map, occlusion_map }.into()
+        material::Pbr { base_color_factor, base_color_alpha, metallic_factor: pbr.metallic_factor(), roughness_factor: pbr.roughness_factor(), occlusion_strength: mat.occlusion_texture().map_or(1.0, |t| t.strength()), emissive_factor: color::from_linear_rgb(mat.emissive_factor()), normal_scale: mat.normal_texture().map_or(1.0, |t| t.scale()), base_color_map, normal_map, emissive_map, metallic_roughness_map, occlusion_map, state }.into()
     }
 }
 
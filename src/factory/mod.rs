@@ -6,6 +6,7 @@ use std::collections::hash_map::{Entry, HashMap};
 use std::collections::HashSet;
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::{cmp, fs, io, iter, ops};
 
 use cgmath::Vector3;
@@ -27,11 +28,12 @@ use crate::{
     color::{Color, BLACK},
     geometry::Geometry,
     hub::{Hub, HubPtr, LightData, SubLight, SubNode},
-    light::{Ambient, Directional, Hemisphere, Point, ShadowMap},
+    light::{Ambient, Directional, Hemisphere, Point, ShadowBias, ShadowMap},
     material::{self, Material},
     mesh::{DynamicMesh, Mesh},
     object::{self, Group, Object},
-    render::{basic_pipe, BackendFactory, BackendResources, BasicPipelineState, DisplacementContribution, DynamicData, GpuData, Instance, InstanceCacheKey, PipelineCreationError, ShadowFormat, Source, Vertex, DEFAULT_VERTEX, VECS_PER_BONE, ZEROED_DISPLACEMENT_CONTRIBUTION},
+    raycast::Collider,
+    render::{basic_pipe, graph, ibl, post, CustomShader, RenderTarget, ShaderDirectory, ShaderState, TargetFormat, BackendFactory, BackendResources, BasicPipelineState, DisplacementContribution, DynamicData, GpuData, Instance, InstanceCacheKey, PipelineCreationError, ShadowFormat, Source, Vertex, DEFAULT_VERTEX, VECS_PER_BONE, ZEROED_DISPLACEMENT_CONTRIBUTION},
     scene::{Background, Scene},
     skeleton::{Bone, InverseBindMatrix, Skeleton},
     sprite::Sprite,
@@ -51,7 +53,16 @@ pub type MapVertices<'a> = gfx::mapping::Writer<'a, BackendResources, Vertex>;
 /// `Factory` is used to instantiate game objects.
 pub struct Factory {
     pub(crate) backend: BackendFactory,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    /// Color format of the window, see `TargetFormat::Color`.
+    surface_format: wgpu::TextureFormat,
+    /// Created on the first call to `Factory::environment`.
+    ibl: Option<ibl::Generator>,
     hub: HubPtr,
+    /// Set by `Renderer::watch_shaders`, chunks included by new pipelines
+    /// are taken from it.
+    shader_directory: ShaderDirectory,
     // quad_buf: gfx::handle::Buffer<BackendResources, Vertex>,
     texture_cache: HashMap<PathBuf, Texture<[f32; 4]>>,
     // default_sampler: gfx::handle::Sampler<BackendResources>,
@@ -72,6 +83,7 @@ impl Factory {
     }
 
     fn create_gpu_data(&mut self, geometry: Geometry) -> GpuData {
+        let collider = Arc::new(Collider::new(&geometry));
         let vertices = Self::mesh_vertices(&geometry);
         let (vbuf, mut slice) = if geometry.faces.is_empty() {
             self.backend.create_vertex_buffer_with_slice(&vertices, ())
@@ -118,20 +130,20 @@ impl Factory {
             None
         };
 
-        GpuData { slice, vertices: vbuf, instances, displacements, pending: None, instance_cache_key: None, displacement_contributions }
+        GpuData { slice, vertices: vbuf, instances, displacements, pending: None, instance_cache_key: None, displacement_contributions, collider: Some(collider) }
     }
 
-    pub(crate) fn new(mut backend: BackendFactory) -> Self {
+    pub(crate) fn new(mut backend: BackendFactory, device: wgpu::Device, queue: wgpu::Queue, surface_format: wgpu::TextureFormat, shader_directory: ShaderDirectory) -> Self {
         let quad_buf = backend.create_vertex_buffer(&QUAD);
         let default_sampler = backend.create_sampler_linear();
-        Factory { backend: backend, hub: Hub::new(), quad_buf, texture_cache: HashMap::new(), default_sampler: default_sampler }
+        Factory { backend: backend, device, queue, surface_format, ibl: None, hub: Hub::new(), shader_directory, quad_buf, texture_cache: HashMap::new(), default_sampler: default_sampler }
     }
 
     /// Create new empty [`Scene`](struct.Scene.html).
     pub fn scene(&mut self) -> Scene {
         let hub = self.hub.clone();
         let background = Background::Color(BLACK);
-        Scene { hub, first_child: None, background }
+        Scene { hub, first_child: None, background, environment: None, fog: None, fog_height: None, fog_blend_background: false }
     }
 
     /// Creates an instance of all the objects described in the template.
@@ -401,7 +413,7 @@ impl Factory {
     /// // Create multiple meshes with the same GPU data and material.
     /// let material = three::material::Basic {
     ///     color: 0xFFFF00,
-    ///     map: None,
+    ///     .. Default::default()
     /// };
     /// let first = window.factory.create_instanced_mesh(&upload_geometry, material.clone());
     /// let second = window.factory.create_instanced_mesh(&upload_geometry, material.clone());
@@ -446,7 +458,7 @@ impl Factory {
     /// // Create multiple meshes with the same GPU data and material.
     /// let material = three::material::Basic {
     ///     color: 0xFFFF00,
-    ///     map: None,
+    ///     .. Default::default()
     /// };
     /// let first = window.factory.create_instanced_mesh(&upload_geometry, material.clone());
     /// let second = window.factory.create_instanced_mesh(&upload_geometry, material.clone());
@@ -487,7 +499,7 @@ impl Factory {
             (data.len(), dest_buf, upload_buf)
         };
         let instances = self.create_instance_buffer();
-        DynamicMesh { object: self.hub.lock().unwrap().spawn_visual(material.into(), GpuData { slice, vertices, instances, displacements: None, pending: None, instance_cache_key: None, displacement_contributions: ZEROED_DISPLACEMENT_CONTRIBUTION.to_vec() }, None), geometry, dynamic: DynamicData { num_vertices, buffer: upload_buf } }
+        DynamicMesh { object: self.hub.lock().unwrap().spawn_visual(material.into(), GpuData { slice, vertices, instances, displacements: None, pending: None, instance_cache_key: None, displacement_contributions: ZEROED_DISPLACEMENT_CONTRIBUTION.to_vec(), collider: None }, None), geometry, dynamic: DynamicData { num_vertices, buffer: upload_buf } }
     }
 
     /// Create a `Mesh` sharing the geometry with another one.
@@ -523,7 +535,7 @@ impl Factory {
         let mut slice = todo!();
         slice.instances = Some((1, 0));
         let material = Material::from(material);
-        Sprite::new(self.hub.lock().unwrap().spawn_visual(material, GpuData { slice, vertices: self.quad_buf.clone(), instances, displacements: None, pending: None, instance_cache_key: None, displacement_contributions: ZEROED_DISPLACEMENT_CONTRIBUTION.to_vec() }, None))
+        Sprite::new(self.hub.lock().unwrap().spawn_visual(material, GpuData { slice, vertices: self.quad_buf.clone(), instances, displacements: None, pending: None, instance_cache_key: None, displacement_contributions: ZEROED_DISPLACEMENT_CONTRIBUTION.to_vec(), collider: None }, None))
     }
 
     /// Create a `Sprite` sharing the material with another one.
@@ -540,22 +552,22 @@ impl Factory {
 
     /// Create new `AmbientLight`.
     pub fn ambient_light(&mut self, color: Color, intensity: f32) -> Ambient {
-        Ambient::new(self.hub.lock().unwrap().spawn_light(LightData { color, intensity, sub_light: SubLight::Ambient, shadow: None }))
+        Ambient::new(self.hub.lock().unwrap().spawn_light(LightData { color, intensity, sub_light: SubLight::Ambient, shadow: None, shadow_bias: ShadowBias::default() }))
     }
 
     /// Create new `DirectionalLight`.
     pub fn directional_light(&mut self, color: Color, intensity: f32) -> Directional {
-        Directional::new(self.hub.lock().unwrap().spawn_light(LightData { color, intensity, sub_light: SubLight::Directional, shadow: None }))
+        Directional::new(self.hub.lock().unwrap().spawn_light(LightData { color, intensity, sub_light: SubLight::Directional, shadow: None, shadow_bias: ShadowBias::default() }))
     }
 
     /// Create new `HemisphereLight`.
     pub fn hemisphere_light(&mut self, sky_color: Color, ground_color: Color, intensity: f32) -> Hemisphere {
-        Hemisphere::new(self.hub.lock().unwrap().spawn_light(LightData { color: sky_color, intensity, sub_light: SubLight::Hemisphere { ground: ground_color }, shadow: None }))
+        Hemisphere::new(self.hub.lock().unwrap().spawn_light(LightData { color: sky_color, intensity, sub_light: SubLight::Hemisphere { ground: ground_color }, shadow: None, shadow_bias: ShadowBias::default() }))
     }
 
     /// Create new `PointLight`.
     pub fn point_light(&mut self, color: Color, intensity: f32) -> Point {
-        Point::new(self.hub.lock().unwrap().spawn_light(LightData { color, intensity, sub_light: SubLight::Point, shadow: None }))
+        Point::new(self.hub.lock().unwrap().spawn_light(LightData { color, intensity, sub_light: SubLight::Point { range: None }, shadow: None, shadow_bias: ShadowBias::default() }))
     }
 
     /// Create a `Sampler` with default properties.
@@ -581,17 +593,58 @@ impl Factory {
         ShadowMap { resource, target }
     }
 
+    /// Create an offscreen target to render into, see
+    /// [`Renderer::render_to`](struct.Renderer.html#method.render_to).
+    ///
+    /// Its color is available as a texture through
+    /// [`RenderTarget::texture`](struct.RenderTarget.html#method.texture).
+    pub fn render_target(&mut self, width: u32, height: u32, format: TargetFormat) -> RenderTarget {
+        let sampler = self.default_sampler();
+        RenderTarget::new(&self.device, [width, height], format.resolve(self.surface_format), sampler.0)
+    }
+
     /// Create a basic mesh pipeline using a custom shader.
     pub fn basic_pipeline<P: AsRef<Path>>() -> Result<BasicPipelineState, PipelineCreationError> {
     // pub fn basic_pipeline<P: AsRef<Path>>(&mut self, dir: P, name: &str, primitive: gfx::Primitive, rasterizer: gfx::state::Rasterizer, color_mask: gfx::state::ColorMask, blend_state: gfx::state::Blend, depth_state: gfx::state::Depth, stencil_state: gfx::state::Stencil) -> Result<BasicPipelineState, PipelineCreationError> {
     //     let vs = Source::user(&dir, name, "vs")?;
     //     let ps = Source::user(&dir, name, "ps")?;
-    //     let shaders = self.backend.create_shader_set(vs.0.as_bytes(), ps.0.as_bytes())?;
+    //     let shaders = self.backend.create_shader_set(vs.variant(Features::empty())?.as_bytes(), ps.variant(Features::empty())?.as_bytes())?;
     //     let init = basic_pipe::Init { out_color: ("Target0", color_mask, blend_state), out_depth: (depth_state, stencil_state), ..basic_pipe::new() };
     //     let pso = self.backend.create_pipeline_state(&shaders, primitive, rasterizer, init)?;
         Ok(pso)
     }
 
+    /// Compile the WGSL vertex and fragment stages of a
+    /// [`material::Custom`](material/struct.Custom.html).
+    ///
+    /// The sources are checked before creating the pipeline, so errors are
+    /// reported here along with the offending lines. Chunks are taken from
+    /// the directory of [`Renderer::watch_shaders`](render/struct.Renderer.html#method.watch_shaders)
+    /// when it has them.
+    pub fn custom_shader(&mut self, vertex: &str, fragment: &str, state: ShaderState) -> Result<CustomShader, PipelineCreationError> {
+        let directory = self.shader_directory.lock().unwrap();
+        CustomShader::new(&self.device, vertex, fragment, state, directory.as_deref())
+    }
+
+    /// Create a custom post-processing pass from WGSL code.
+    ///
+    /// See [`CustomPass`](render/post/struct.CustomPass.html) for the bindings available to the shader.
+    pub fn post_pass(&mut self, wgsl: &str) -> Result<post::CustomPass, PipelineCreationError> {
+        let directory = self.shader_directory.lock().unwrap();
+        post::CustomPass::new(&self.device, wgsl, directory.as_deref())
+    }
+
+    /// Create a fullscreen render graph pass from WGSL code, reading `inputs`
+    /// and drawing into `output`.
+    ///
+    /// See [`WgslPass`](render/graph/struct.WgslPass.html) for the bindings available to the shader.
+    ///
+    /// Fails if `output` is `Resource::Depth`, or one of the `inputs`.
+    pub fn graph_pass(&mut self, wgsl: &str, inputs: &[graph::Resource], output: graph::Resource) -> Result<graph::WgslPass, PipelineCreationError> {
+        let directory = self.shader_directory.lock().unwrap();
+        graph::WgslPass::new(&self.device, wgsl, inputs, output, directory.as_deref())
+    }
+
     /// Create new UI (on-screen) text. See [`Text`](struct.Text.html) for default settings.
     pub fn ui_text<S: Into<String>>(&mut self, font: &Font, text: S) -> Text {
         let sub = SubNode::UiText(TextData::new(font, text));
@@ -663,6 +716,7 @@ impl Factory {
             "bmp" => F::Bmp,
             "ico" => F::Ico,
             "hdr" => F::Hdr,
+            "exr" => F::OpenExr,
             _ => panic!("Unrecognized image extension: {}", extension),
         }
     }
@@ -717,8 +771,8 @@ impl Factory {
     fn load_obj_material(&mut self, mat: &obj::Material, has_normals: bool, has_uv: bool, obj_dir: Option<&Path>) -> Material {
         let cf2u = |c: [f32; 3]| c.iter().fold(0, |u, &v| (u << 8) + cmp::min((v * 255.0) as u32, 0xFF));
         match *mat {
-            obj::Material { kd: Some(color), ns: Some(glossiness), .. } if has_normals => material::Phong { color: cf2u(color), glossiness }.into(),
-            obj::Material { kd: Some(color), .. } if has_normals => material::Lambert { color: cf2u(color), flat: false }.into(),
+            obj::Material { kd: Some(color), ns: Some(glossiness), .. } if has_normals => material::Phong { color: cf2u(color), glossiness, ..Default::default() }.into(),
+            obj::Material { kd: Some(color), .. } if has_normals => material::Lambert { color: cf2u(color), ..Default::default() }.into(),
             obj::Material { kd: Some(color), ref map_kd, .. } => material::Basic {
                 color: cf2u(color),
                 map: match (has_uv, map_kd) {
@@ -728,9 +782,10 @@ impl Factory {
                     }
                     _ => None,
                 },
+                ..Default::default()
             }
             .into(),
-            _ => material::Basic { color: 0xffffff, map: None }.into(),
+            _ => material::Basic { color: 0xffffff, ..Default::default() }.into(),
         }
     }
 
@@ -747,24 +802,81 @@ impl Factory {
     }
 
     /// Load texture from file, with default `Sampler`.
-    /// Supported file formats are: PNG, JPEG, GIF, WEBP, PPM, TIFF, TGA, BMP, ICO, HDR.
+    /// Supported file formats are: PNG, JPEG, GIF, WEBP, PPM, TIFF, TGA, BMP, ICO, HDR, EXR.
     pub fn load_texture<P: AsRef<Path>>(&mut self, path_str: P) -> Texture<[f32; 4]> {
         let sampler = self.default_sampler();
         self.request_texture(path_str, sampler)
     }
 
     /// Load texture from file, with custom `Sampler`.
-    /// Supported file formats are: PNG, JPEG, GIF, WEBP, PPM, TIFF, TGA, BMP, ICO, HDR.
+    /// Supported file formats are: PNG, JPEG, GIF, WEBP, PPM, TIFF, TGA, BMP, ICO, HDR, EXR.
     pub fn load_texture_with_sampler<P: AsRef<Path>>(&mut self, path_str: P, sampler: Sampler) -> Texture<[f32; 4]> {
         self.request_texture(path_str, sampler)
     }
 
+    /// Load a floating point texture from a Radiance `.hdr` or OpenEXR `.exr` file.
+    ///
+    /// Unlike [`load_texture`](#method.load_texture) the values are kept linear
+    /// and are not clamped to `[0, 1]`, which suits `Background::Equirectangular`
+    /// and [`equirect_to_cubemap`](#method.equirect_to_cubemap).
+    pub fn load_hdr_texture<P: AsRef<Path>>(&mut self, path: P) -> Texture<[f32; 4]> {
+        let path = path.as_ref();
+        let format = Factory::parse_texture_format(path);
+        let file = fs::File::open(path).unwrap_or_else(|e| panic!("Unable to open {}: {:?}", path.display(), e));
+        let img = image::load(io::BufReader::new(file), format).unwrap_or_else(|e| panic!("Unable to decode {}: {:?}", path.display(), e)).flipv().to_rgba32f();
+        let (width, height) = img.dimensions();
+        let texels = img.into_raw().into_iter().map(half::f16::from_f32).collect::<Vec<_>>();
+        let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&path.to_string_lossy()),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        self.queue.write_texture(texture.as_image_copy(), bytemuck::cast_slice(&texels), wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(8 * width), rows_per_image: Some(height) }, size);
+        let sampler = self.default_sampler();
+        Texture::new(texture.create_view(&wgpu::TextureViewDescriptor::default()), sampler.0, [width, height])
+    }
+
+    /// Convert an equirectangular panorama into a cube map with faces of `size` texels.
+    ///
+    /// A face size of a quarter of the panorama width keeps roughly all of its detail.
+    pub fn equirect_to_cubemap(&mut self, texture: &Texture<[f32; 4]>, size: u32) -> CubeMap<[f32; 4]> {
+        let (device, queue) = (&self.device, &self.queue);
+        self.ibl.get_or_insert_with(|| ibl::Generator::new(device, queue)).equirect_to_cube(device, queue, texture, size)
+    }
+
     /// Load cubemap from files.
-    /// Supported file formats are: PNG, JPEG, GIF, WEBP, PPM, TIFF, TGA, BMP, ICO, HDR.
+    /// Supported file formats are: PNG, JPEG, GIF, WEBP, PPM, TIFF, TGA, BMP, ICO, HDR, EXR.
     pub fn load_cubemap<P: AsRef<Path>>(&mut self, paths: &CubeMapPath<P>) -> CubeMap<[f32; 4]> {
         Factory::load_cubemap_impl(paths, self.default_sampler(), &mut self.backend)
     }
 
+    /// Load a color grading lookup table from file.
+    ///
+    /// The image has to be a horizontal strip of `N` tiles of `N` x `N` pixels,
+    /// see [`ColorGrading`](render/post/struct.ColorGrading.html).
+    pub fn load_lut<P: AsRef<Path>>(&mut self, path: P) -> Result<post::Lut, post::LutError> {
+        let path = path.as_ref();
+        let format = Factory::parse_texture_format(path);
+        let file = fs::File::open(path)?;
+        let img = image::load(io::BufReader::new(file), format)?.to_rgba8();
+        post::Lut::from_strip(&self.device, &self.queue, &img)
+    }
+
+    /// Precompute image-based lighting from a cube map.
+    ///
+    /// The result is meant for [`Scene::environment`](scene/struct.Scene.html#structfield.environment).
+    /// Generating it takes a few GPU passes, so it should be done once and reused.
+    pub fn environment(&mut self, cube_map: &CubeMap<[f32; 4]>) -> ibl::Environment {
+        let (device, queue) = (&self.device, &self.queue);
+        self.ibl.get_or_insert_with(|| ibl::Generator::new(device, queue)).generate(device, queue, cube_map)
+    }
+
     /// Load mesh from Wavefront Obj format.
     pub fn load_obj(&mut self, path_str: &str) -> (HashMap<String, object::Group>, Vec<Mesh>) {
         use genmesh::{Indexer, LruIndexer, Polygon, Triangulate, Vertices};
@@ -823,15 +935,16 @@ impl Factory {
                 info!("\tmaterial {} with {} normals and {} uvs", gr.name, num_normals, num_uvs);
                 let material = match gr.material {
                     Some(obj::ObjMaterial::Mtl(ref rc_mat)) => self.load_obj_material(&*rc_mat, num_normals != 0, num_uvs != 0, path_parent),
-                    _ => material::Basic { color: 0xFFFFFF, map: None }.into(),
+                    _ => material::Basic { color: 0xFFFFFF, ..Default::default() }.into(),
                 };
                 info!("\t{:?}", material);
 
+                let collider = Collider::with_faces(vertices.iter().map(|v| Vector3::new(v.pos[0], v.pos[1], v.pos[2])).collect(), indices.chunks(3).map(|f| [f[0] as u32, f[1] as u32, f[2] as u32]).collect());
                 let (vertices, mut slice) = self.backend.create_vertex_buffer_with_slice(&vertices, &indices[..]);
                 slice.instances = Some((1, 0));
                 // let instances = self.backend.create_buffer(1, gfx::buffer::Role::Vertex, gfx::memory::Usage::Dynamic, gfx::memory::Bind::TRANSFER_DST).unwrap();
                 let instances = todo!();
-                let mesh = Mesh { object: hub.spawn_visual(material, GpuData { slice, vertices, instances, displacements: None, pending: None, instance_cache_key: None, displacement_contributions: ZEROED_DISPLACEMENT_CONTRIBUTION.to_vec() }, None) };
+                let mesh = Mesh { object: hub.spawn_visual(material, GpuData { slice, vertices, instances, displacements: None, pending: None, instance_cache_key: None, displacement_contributions: ZEROED_DISPLACEMENT_CONTRIBUTION.to_vec(), collider: Some(Arc::new(collider)) }, None) };
                 group.add(&mesh);
                 meshes.push(mesh);
             }
@@ -7,10 +7,10 @@ use std::{mem, ops};
 use crate::{
     camera::Projection,
     color::{self, Color},
-    light::{LightOperation, ShadowMap, ShadowProjection},
+    light::{LightOperation, ShadowBias, ShadowMap, ShadowProjection},
     material::Material,
     mesh::DynamicMesh,
-    node::{NodeInternal, NodePointer, TransformInternal},
+    node::{Highlight, NodeInternal, NodePointer, TransformInternal},
     object::Base,
     render::{GpuData},
     skeleton::{Bone, Skeleton},
@@ -24,7 +24,7 @@ pub(crate) enum SubLight {
     Ambient,
     Directional,
     Hemisphere { ground: Color },
-    Point,
+    Point { range: Option<f32> },
 }
 
 #[derive(Clone, Debug)]
@@ -33,6 +33,7 @@ pub(crate) struct LightData {
     pub intensity: f32,
     pub sub_light: SubLight,
     pub shadow: Option<(ShadowMap, ShadowProjection)>,
+    pub shadow_bias: ShadowBias,
 }
 
 #[derive(Clone, Debug)]
@@ -90,6 +91,7 @@ pub(crate) enum Operation {
     SetWeights(Vec<f32>),
     SetName(String),
     SetProjection(Projection),
+    SetHighlight(Option<Highlight>),
 }
 
 pub(crate) type HubPtr = Arc<Mutex<Hub>>;
@@ -281,6 +283,9 @@ impl Hub {
         //             }
         //             _ => unreachable!(),
         //         },
+        //         Operation::SetHighlight(highlight) => {
+        //             self.nodes[&ptr].highlight = highlight;
+        //         }
         //     }
         // }
 
@@ -302,6 +307,12 @@ impl Hub {
         match operation {
             LightOperation::Color(color) => data.color = color,
             LightOperation::Intensity(intensity) => data.intensity = intensity,
+            LightOperation::ShadowBias(bias) => data.shadow_bias = bias,
+            LightOperation::Range(range) => {
+                if let SubLight::Point { range: ref mut value } = data.sub_light {
+                    *value = range;
+                }
+            }
         }
     }
 
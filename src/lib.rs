@@ -285,6 +285,7 @@ pub mod prelude {
 
 // pub mod light;
 // mod mesh;
+// pub mod raycast;
 // pub mod scene;
 // pub mod skeleton;
 // mod sprite;
@@ -311,14 +312,14 @@ pub mod prelude {
 // pub use mesh::{DynamicMesh, Mesh};
 //
 // #[doc(inline)]
-// pub use node::{Local, Node, Transform, World};
+// pub use node::{Highlight, Local, Node, Transform, World};
 //
 //
 // #[doc(inline)]
 // pub use render::Renderer;
 //
 // #[doc(inline)]
-// pub use scene::{Background, Scene};
+// pub use scene::{Background, Fog, FogHeight, Scene};
 //
 // #[doc(inline)]
 // pub use sprite::Sprite;
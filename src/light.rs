@@ -16,6 +16,8 @@ use crate::{
 pub(crate) enum LightOperation {
     Color(Color),
     Intensity(f32),
+    ShadowBias(ShadowBias),
+    Range(Option<f32>),
 }
 
 /// Marks light sources and implements their common methods.
@@ -48,6 +50,32 @@ pub struct ShadowMap {
     pub(crate) target: wgpu::TextureView,
 }
 
+/// Offsets applied when a surface is compared against a light's [`ShadowMap`].
+///
+/// Raising these values removes self-shadowing artifacts ("shadow acne") at the
+/// cost of shadows detaching from their casters ("peter panning").
+///
+/// [`ShadowMap`]: struct.ShadowMap.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowBias {
+    /// Constant offset subtracted from the receiver depth, in shadow map depth units.
+    ///
+    /// Default: `0.002`.
+    pub depth: f32,
+
+    /// Distance the receiver position is pushed along its normal before being
+    /// projected into the shadow map, in world units.
+    ///
+    /// Default: `0.02`.
+    pub normal_offset: f32,
+}
+
+impl Default for ShadowBias {
+    fn default() -> Self {
+        ShadowBias { depth: 0.002, normal_offset: 0.02 }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) enum ShadowProjection {
     Orthographic(Orthographic),
@@ -109,11 +137,22 @@ impl Directional {
     }
 
     /// Adds or updates the shadow map for this light source.
+    ///
+    /// At most two lights cast shadows in a frame, the others are lit
+    /// without shadows and an error is logged.
     pub fn set_shadow(&mut self, map: ShadowMap, extent_y: f32, range: ops::Range<f32>) {
         let sp = ShadowProjection::Orthographic(Orthographic { center: [0.0; 2].into(), extent_y, range });
         let msg = Operation::SetShadow(map, sp);
         let _ = self.object.tx.send((self.object.node.downgrade(), msg));
     }
+
+    /// Changes the bias used when sampling the shadow map of this light source.
+    ///
+    /// See [`ShadowBias`](struct.ShadowBias.html) for the default values.
+    pub fn set_shadow_bias(&mut self, bias: ShadowBias) {
+        let msg = Operation::SetLight(LightOperation::ShadowBias(bias));
+        let _ = self.object.tx.send((self.object.node.downgrade(), msg));
+    }
 }
 
 impl AsRef<Base> for Directional {
@@ -184,6 +223,17 @@ impl Point {
     pub(crate) fn new(object: Base) -> Self {
         Point { object }
     }
+
+    /// Limits the distance the light reaches, in world units.
+    ///
+    /// The contribution fades out smoothly towards `range`, which lets the
+    /// renderer skip the light for everything outside of it. `None` (the
+    /// default) makes the light reach infinitely far, so it has to be
+    /// evaluated for every fragment in the scene.
+    pub fn set_range(&mut self, range: Option<f32>) {
+        let msg = Operation::SetLight(LightOperation::Range(range));
+        let _ = self.object.tx.send((self.object.node.downgrade(), msg));
+    }
 }
 
 impl AsRef<Base> for Point {
@@ -1,9 +1,13 @@
 //! Material parameters for mesh rendering.
 
 
+use std::collections::BTreeMap;
+
+use bitflags::bitflags;
+
 use crate::{
     color::Color,
-    render::BasicPipelineState,
+    render::{BasicPipelineState, CustomShader},
     texture::Texture,
 };
 
@@ -28,11 +32,16 @@ pub mod basic {
         ///
         /// Default: `None`.
         pub map: Option<Texture<[f32; 4]>>,
+
+        /// Face culling, depth and color writes.
+        ///
+        /// Default: `RenderState::default()`.
+        pub state: RenderState,
     }
 
     impl Default for Basic {
         fn default() -> Self {
-            Self { color: color::WHITE, map: None }
+            Self { color: color::WHITE, map: None, state: RenderState::default() }
         }
     }
 
@@ -55,6 +64,110 @@ pub mod basic {
     impl Eq for Custom {}
 }
 
+/// Parameters for a material drawn by user supplied WGSL shaders.
+///
+/// The shader sources can include the chunks of the engine with
+/// `#include <name>`:
+///
+/// * `<mesh>`: `VertexInput` and `InstanceInput`, the vertex attributes of the
+///   mesh and of its instance, with `instance_world` returning the model to
+///   world transform,
+/// * `<globals>`: `u_Globals`, holding the camera transforms and the fog
+///   parameters,
+/// * `<lights>`: `b_Lights` and the clustered light lookup, see `lights.wgsl`.
+///
+/// The engine resources are bound in group 0. The material resources are
+/// bound in group 1: a uniform block holding [`uniforms`](#structfield.uniforms),
+/// and a `t_{name}` texture and `s_{name}` sampler for every entry of
+/// [`textures`](#structfield.textures). Textures missing from the map are
+/// bound to a white texture. The stages have to be named `vs_main` and `fs_main`:
+///
+/// ```wgsl
+/// #include <mesh>
+/// #include <globals>
+///
+/// struct Params {
+///     tint: vec4<f32>,
+/// };
+///
+/// @group(1) @binding(0) var<uniform> u_Material: Params;
+///
+/// @vertex
+/// fn vs_main(vertex: VertexInput, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+///     return u_Globals.mx_vp * instance_world(instance) * vertex.position;
+/// }
+///
+/// @fragment
+/// fn fs_main() -> @location(0) vec4<f32> {
+///     return u_Material.tint;
+/// }
+/// ```
+///
+/// Meshes with a custom material are neither instanced, skinned nor morphed.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Custom {
+    /// Compiled shaders, see [`Factory::custom_shader`](../struct.Factory.html#method.custom_shader).
+    pub shader: CustomShader,
+
+    /// Contents of the uniform block of group 1, laid out as declared in WGSL.
+    ///
+    /// The bytes missing from the block declared by the shader are zeroed.
+    ///
+    /// Default: empty, the block is zeroed.
+    pub uniforms: Vec<u8>,
+
+    /// Textures bound to the `t_{name}` and `s_{name}` variables of group 1.
+    ///
+    /// Default: empty.
+    pub textures: BTreeMap<String, Texture<[f32; 4]>>,
+}
+
+impl Custom {
+    /// Creates a material without uniforms nor textures.
+    pub fn new(shader: CustomShader) -> Self {
+        Self { shader, uniforms: Vec::new(), textures: BTreeMap::new() }
+    }
+
+    /// Sets the uniforms to the bytes of `value`.
+    ///
+    /// `T` has to follow the WGSL alignment rules, e.g. by using arrays of
+    /// four floats instead of three.
+    pub fn set_uniforms<T: bytemuck::Pod>(&mut self, value: &T) {
+        self.uniforms = bytemuck::bytes_of(value).to_vec();
+    }
+}
+
+/// Parameters for a depth material.
+///
+/// Renders triangle meshes in grayscale by their distance to the camera,
+/// white at `near` and black from `far` on.
+#[derive(Derivative)]
+#[derivative(Clone, Debug, PartialEq, Hash, Eq)]
+pub struct Depth {
+    /// Distance to the camera rendered white.
+    ///
+    /// Default: `0.1`.
+    #[derivative(Hash(hash_with = "util::hash_f32"))]
+    pub near: f32,
+
+    /// Distance to the camera rendered black.
+    ///
+    /// Default: `100.0`.
+    #[derivative(Hash(hash_with = "util::hash_f32"))]
+    pub far: f32,
+
+    /// Face culling, depth and color writes.
+    ///
+    /// Default: `RenderState::default()`.
+    pub state: RenderState,
+}
+
+impl Default for Depth {
+    fn default() -> Self {
+        Self { near: 0.1, far: 100.0, state: RenderState::default() }
+    }
+}
+
 /// Parameters for a Lamberian diffusion reflection model.
 ///
 /// Renders triangle meshes with the Gouraud illumination model.
@@ -69,11 +182,16 @@ pub struct Lambert {
     ///
     /// Default: `false` (lighting is interpolated across faces).
     pub flat: bool,
+
+    /// Face culling, depth and color writes.
+    ///
+    /// Default: `RenderState::default()`.
+    pub state: RenderState,
 }
 
 impl Default for Lambert {
     fn default() -> Self {
-        Self { color: color::WHITE, flat: false }
+        Self { color: color::WHITE, flat: false, state: RenderState::default() }
     }
 }
 
@@ -86,14 +204,52 @@ pub struct Line {
     ///
     /// Default: `0xFFFFFF` (white).
     pub color: Color,
+
+    /// Face culling, depth and color writes.
+    ///
+    /// Default: `RenderState::default()`.
+    pub state: RenderState,
 }
 
 impl Default for Line {
     fn default() -> Self {
-        Self { color: color::WHITE }
+        Self { color: color::WHITE, state: RenderState::default() }
     }
 }
 
+/// Parameters for a matcap (material capture) material.
+///
+/// Renders triangle meshes with the colors of a sphere image looked up by
+/// the normal as seen from the camera, without lights.
+#[derive(Clone, Hash, Debug, PartialEq, Eq)]
+pub struct Matcap {
+    /// Image of a lit sphere filling the texture.
+    pub map: Texture<[f32; 4]>,
+
+    /// Face culling, depth and color writes.
+    ///
+    /// Default: `RenderState::default()`.
+    pub state: RenderState,
+}
+
+impl Matcap {
+    /// Creates a matcap material with the default render state.
+    pub fn new(map: Texture<[f32; 4]>) -> Self {
+        Self { map, state: RenderState::default() }
+    }
+}
+
+/// Parameters for a normal material.
+///
+/// Renders triangle meshes with the view space normals mapped to colors.
+#[derive(Clone, Hash, Debug, Default, PartialEq, Eq)]
+pub struct Normal {
+    /// Face culling, depth and color writes.
+    ///
+    /// Default: `RenderState::default()`.
+    pub state: RenderState,
+}
+
 /// Parameters for a PBR (physically based rendering) lighting model.
 ///
 /// Renders triangle meshes with a PBR (physically-based rendering)
@@ -171,11 +327,16 @@ pub struct Pbr {
     ///
     /// Default: `None`.
     pub occlusion_map: Option<Texture<[f32; 4]>>,
+
+    /// Face culling, depth and color writes.
+    ///
+    /// Default: `RenderState::default()`.
+    pub state: RenderState,
 }
 
 impl Default for Pbr {
     fn default() -> Self {
-        Self { base_color_factor: color::WHITE, base_color_alpha: 1.0, metallic_factor: 1.0, roughness_factor: 1.0, occlusion_strength: 1.0, emissive_factor: color::BLACK, normal_scale: 1.0, base_color_map: None, normal_map: None, emissive_map: None, metallic_roughness_map: None, occlusion_map: None }
+        Self { base_color_factor: color::WHITE, base_color_alpha: 1.0, metallic_factor: 1.0, roughness_factor: 1.0, occlusion_strength: 1.0, emissive_factor: color::BLACK, normal_scale: 1.0, base_color_map: None, normal_map: None, emissive_map: None, metallic_roughness_map: None, occlusion_map: None, state: RenderState::default() }
     }
 }
 
@@ -197,11 +358,16 @@ pub struct Phong {
     /// Default: `30.0`.
     #[derivative(Hash(hash_with = "util::hash_f32"))]
     pub glossiness: f32,
+
+    /// Face culling, depth and color writes.
+    ///
+    /// Default: `RenderState::default()`.
+    pub state: RenderState,
 }
 
 impl Default for Phong {
     fn default() -> Self {
-        Self { color: color::WHITE, glossiness: 30.0 }
+        Self { color: color::WHITE, glossiness: 30.0, state: RenderState::default() }
     }
 }
 
@@ -214,6 +380,72 @@ impl Default for Phong {
 pub struct Sprite {
     /// The texture the apply to the sprite.
     pub map: Texture<[f32; 4]>,
+
+    /// Face culling, depth and color writes.
+    ///
+    /// Default: `RenderState::default()`.
+    pub state: RenderState,
+}
+
+impl Sprite {
+    /// Creates a sprite material with the default render state.
+    pub fn new(map: Texture<[f32; 4]>) -> Self {
+        Self { map, state: RenderState::default() }
+    }
+}
+
+/// Parameters for a toon (cel) shading model.
+///
+/// Renders triangle meshes with quantized diffuse lighting, a hard specular
+/// highlight and a rim light around the silhouette.
+#[derive(Derivative)]
+#[derivative(Clone, Debug, PartialEq, Hash, Eq)]
+pub struct Toon {
+    /// Solid color of the lit surface.
+    ///
+    /// Default: `WHITE`.
+    pub color: Color,
+
+    /// Horizontal gradient mapping the diffuse intensity, from `0.0` on the
+    /// left to `1.0` on the right, to the light color. A ramp with a few
+    /// flat bands gives as many tones.
+    ///
+    /// Default: `None` (two tones, lit and unlit).
+    pub gradient_map: Option<Texture<[f32; 4]>>,
+
+    /// Minimum cosine between the normal and the half vector covered by the
+    /// specular highlight, `1.0` disabling it.
+    ///
+    /// Default: `0.98`.
+    #[derivative(Hash(hash_with = "util::hash_f32"))]
+    pub specular_threshold: f32,
+
+    /// Color of the rim light, `BLACK` disabling it.
+    ///
+    /// Default: `BLACK`.
+    pub rim_color: Color,
+
+    /// Sharpness of the rim light, higher values giving a thinner rim.
+    ///
+    /// Default: `4.0`.
+    #[derivative(Hash(hash_with = "util::hash_f32"))]
+    pub rim_power: f32,
+
+    /// Whether the shadows of other objects are drawn onto the mesh.
+    ///
+    /// Default: `true`.
+    pub receive_shadows: bool,
+
+    /// Face culling, depth and color writes.
+    ///
+    /// Default: `RenderState::default()`.
+    pub state: RenderState,
+}
+
+impl Default for Toon {
+    fn default() -> Self {
+        Self { color: color::WHITE, gradient_map: None, specular_threshold: 0.98, rim_color: color::BLACK, rim_power: 4.0, receive_shadows: true, state: RenderState::default() }
+    }
 }
 
 /// Parameters for mesh wireframe rasterization.
@@ -225,6 +457,92 @@ pub struct Wireframe {
     ///
     /// Default: `WHITE`.
     pub color: Color,
+
+    /// Face culling, depth and color writes.
+    ///
+    /// Default: `RenderState::default()`.
+    pub state: RenderState,
+}
+
+impl Default for Wireframe {
+    fn default() -> Self {
+        Self { color: color::WHITE, state: RenderState::default() }
+    }
+}
+
+/// Faces of a mesh that are drawn, front faces being counter-clockwise.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Side {
+    /// Front faces only.
+    Front,
+    /// Back faces only.
+    Back,
+    /// Both faces, e.g. for foliage cards and other thin surfaces.
+    Double,
+}
+
+bitflags! {
+    /// Color channels written to the target.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct ColorMask: u8 {
+        const RED = 1 << 0;
+        const GREEN = 1 << 1;
+        const BLUE = 1 << 2;
+        const ALPHA = 1 << 3;
+        const COLOR = Self::RED.bits() | Self::GREEN.bits() | Self::BLUE.bits();
+        const ALL = Self::COLOR.bits() | Self::ALPHA.bits();
+    }
+}
+
+/// Rasterizer, depth and output state shared by the built-in materials.
+///
+/// Part of the material, so meshes with different states are drawn with
+/// different pipelines and are never instanced together. `Custom` materials
+/// use the state given to [`Factory::custom_shader`](../struct.Factory.html#method.custom_shader)
+/// instead, and `basic::Custom` the state of their pipeline.
+#[derive(Derivative)]
+#[derivative(Clone, Copy, Debug, PartialEq, Hash, Eq)]
+pub struct RenderState {
+    /// Faces that are drawn.
+    ///
+    /// Default: `Side::Front`.
+    pub side: Side,
+
+    /// Whether fragments behind the depth buffer are discarded.
+    ///
+    /// Default: `true`.
+    pub depth_test: bool,
+
+    /// Whether the depth of the mesh is written, usually disabled for decals
+    /// and transparent surfaces.
+    ///
+    /// Default: `true`.
+    pub depth_write: bool,
+
+    /// Constant depth offset in units of the smallest resolvable depth
+    /// difference, the `units` of a polygon offset. Negative values bring the
+    /// mesh closer, e.g. to draw decals over coplanar surfaces.
+    ///
+    /// Default: `0`.
+    pub depth_bias: i32,
+
+    /// Depth offset scaled by the depth slope of the polygon, the `factor` of
+    /// a polygon offset. Non-finite values are drawn as `0.0`.
+    ///
+    /// Default: `0.0`.
+    #[derivative(Hash(hash_with = "util::hash_f32"))]
+    pub slope_scale_bias: f32,
+
+    /// Color channels written to the target.
+    ///
+    /// Default: `ColorMask::ALL`.
+    pub color_mask: ColorMask,
+}
+
+impl Default for RenderState {
+    fn default() -> Self {
+        Self { side: Side::Front, depth_test: true, depth_write: true, depth_bias: 0, slope_scale_bias: 0.0, color_mask: ColorMask::ALL }
+    }
 }
 
 /// Specifies the appearance of a [`Mesh`](struct.Mesh.html).
@@ -233,6 +551,9 @@ pub enum Material {
     /// Renders triangle meshes with a solid color or texture.
     Basic(Basic),
 
+    /// Renders triangle meshes with user supplied WGSL shaders.
+    Custom(Custom),
+
     /// Renders triangle meshes with a custom pipeline with a basic material as
     /// its input.
     CustomBasic(basic::Custom),
@@ -250,11 +571,23 @@ pub enum Material {
     /// illumination model
     Pbr(Pbr),
 
+    /// Renders triangle meshes with the colors of a lit sphere image.
+    Matcap(Matcap),
+
+    /// Renders triangle meshes with their view space normals as colors.
+    Normal(Normal),
+
+    /// Renders triangle meshes in grayscale by their distance to the camera.
+    Depth(Depth),
+
     /// Renders [`Sprite`] objects with the given texture.
     ///
     /// [`Sprite`]: ../sprite/struct.Sprite.html
     Sprite(Sprite),
 
+    /// Renders triangle meshes with quantized toon shading.
+    Toon(Toon),
+
     /// Renders the edges of a triangle mesh with a solid color.
     Wireframe(Wireframe),
 }
@@ -265,12 +598,24 @@ impl From<Basic> for Material {
     }
 }
 
+impl From<Custom> for Material {
+    fn from(params: Custom) -> Self {
+        Material::Custom(params)
+    }
+}
+
 impl From<basic::Custom> for Material {
     fn from(params: basic::Custom) -> Self {
         Material::CustomBasic(params)
     }
 }
 
+impl From<Depth> for Material {
+    fn from(params: Depth) -> Self {
+        Material::Depth(params)
+    }
+}
+
 impl From<Lambert> for Material {
     fn from(params: Lambert) -> Self {
         Material::Lambert(params)
@@ -283,6 +628,18 @@ impl From<Line> for Material {
     }
 }
 
+impl From<Matcap> for Material {
+    fn from(params: Matcap) -> Self {
+        Material::Matcap(params)
+    }
+}
+
+impl From<Normal> for Material {
+    fn from(params: Normal) -> Self {
+        Material::Normal(params)
+    }
+}
+
 impl From<Phong> for Material {
     fn from(params: Phong) -> Self {
         Material::Phong(params)
@@ -301,8 +658,35 @@ impl From<Sprite> for Material {
     }
 }
 
+impl From<Toon> for Material {
+    fn from(params: Toon) -> Self {
+        Material::Toon(params)
+    }
+}
+
 impl From<Wireframe> for Material {
     fn from(params: Wireframe) -> Self {
         Material::Wireframe(params)
     }
 }
+
+impl Material {
+    /// Returns the render state of a built-in material, `None` for the
+    /// custom materials whose pipelines carry their own state.
+    pub fn state(&self) -> Option<&RenderState> {
+        match *self {
+            Material::Basic(ref params) => Some(&params.state),
+            Material::Custom(_) | Material::CustomBasic(_) => None,
+            Material::Line(ref params) => Some(&params.state),
+            Material::Lambert(ref params) => Some(&params.state),
+            Material::Phong(ref params) => Some(&params.state),
+            Material::Pbr(ref params) => Some(&params.state),
+            Material::Matcap(ref params) => Some(&params.state),
+            Material::Normal(ref params) => Some(&params.state),
+            Material::Depth(ref params) => Some(&params.state),
+            Material::Sprite(ref params) => Some(&params.state),
+            Material::Toon(ref params) => Some(&params.state),
+            Material::Wireframe(ref params) => Some(&params.state),
+        }
+    }
+}
@@ -24,7 +24,7 @@ use std::hash::{Hash, Hasher};
 ///     [ 0.5, -0.5, 0.0].into(),
 /// ];
 /// let geometry = three::Geometry::with_vertices(vertices);
-/// let red_material = three::material::Basic { color: three::color::RED, map: None };
+/// let red_material = three::material::Basic { color: three::color::RED, ..Default::default() };
 /// let mesh = factory.mesh(geometry, red_material);
 /// # let _ = mesh;
 /// ```
@@ -40,7 +40,7 @@ use std::hash::{Hash, Hasher};
 /// #     [ 0.5, -0.5, 0.0].into(),
 /// # ];
 /// # let geometry = three::Geometry::with_vertices(vertices);
-/// # let red_material = three::material::Basic { color: three::color::RED, map: None };
+/// # let red_material = three::material::Basic { color: three::color::RED, ..Default::default() };
 /// # let mesh = factory.mesh(geometry, red_material);
 /// use three::Object;
 /// let mut duplicate = factory.mesh_instance(&mesh);
@@ -59,9 +59,9 @@ use std::hash::{Hash, Hasher};
 /// #     [ 0.5, -0.5, 0.0].into(),
 /// # ];
 /// # let geometry = three::Geometry::with_vertices(vertices);
-/// # let red_material = three::material::Basic { color: three::color::RED, map: None };
+/// # let red_material = three::material::Basic { color: three::color::RED, ..Default::default() };
 /// # let mesh = factory.mesh(geometry, red_material);
-/// let yellow_material = three::material::Wireframe { color: three::color::YELLOW };
+/// let yellow_material = three::material::Wireframe { color: three::color::YELLOW, ..Default::default() };
 /// # use three::Object;
 /// let mut duplicate = factory.mesh_instance_with_material(&mesh, yellow_material);
 /// duplicate.set_position([1.2, 3.4, 5.6]);
@@ -1,3 +1,4 @@
+use color::Color;
 use hub::SubNode;
 use material::Material;
 
@@ -31,6 +32,11 @@ pub(crate) struct NodeInternal {
     /// Pointer to the next sibling.
     pub(crate) next_sibling: Option<NodePointer>,
 
+    /// Outline drawn around the node, see [`Object::set_highlight`].
+    ///
+    /// [`Object::set_highlight`]: ../object/trait.Object.html#method.set_highlight
+    pub(crate) highlight: Option<Highlight>,
+
     /// Context specific-data, for example, `UiText`, `Visual` or `Light`.
     pub(crate) sub_node: SubNode,
 }
@@ -52,7 +58,7 @@ impl NodeInternal {
 
 impl From<SubNode> for NodeInternal {
     fn from(sub: SubNode) -> Self {
-        NodeInternal { visible: true, name: None, transform: cgmath::Transform::one(), world_transform: cgmath::Transform::one(), next_sibling: None, sub_node: sub }
+        NodeInternal { visible: true, name: None, transform: cgmath::Transform::one(), world_transform: cgmath::Transform::one(), next_sibling: None, highlight: None, sub_node: sub }
     }
 }
 
@@ -95,6 +101,23 @@ impl From<TransformInternal> for Transform {
     }
 }
 
+/// Outline drawn around a selected node.
+///
+/// See [`Object::set_highlight`](../object/trait.Object.html#method.set_highlight).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Highlight {
+    /// Color of the outline.
+    pub color: Color,
+    /// Width of the outline, in pixels.
+    pub width: f32,
+}
+
+impl Default for Highlight {
+    fn default() -> Self {
+        Highlight { color: 0xFFA000, width: 2.0 }
+    }
+}
+
 /// Local space, defined relative to the parent node.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Local {}
@@ -12,7 +12,7 @@ use crate::{
     hub::{Hub, Message, Operation, SubLight, SubNode},
     light,
     mesh::Mesh,
-    node::NodePointer,
+    node::{Highlight, NodePointer},
     scene::SyncGuard,
     skeleton::{Bone, Skeleton},
     sprite::Sprite,
@@ -73,6 +73,17 @@ pub trait Object: AsRef<Base> {
         self.as_ref().send(Operation::SetVisible(visible));
     }
 
+    /// Outlines the object, e.g. to mark it as selected, or removes the
+    /// outline with `None`.
+    ///
+    /// The outline follows the silhouette on screen, including skinned and
+    /// morphed meshes, and stays visible behind other objects. Adjacent
+    /// highlighted objects share a single outline. Children of a group are
+    /// not affected, each mesh has to be highlighted on its own.
+    fn set_highlight(&self, highlight: Option<Highlight>) {
+        self.as_ref().send(Operation::SetHighlight(highlight));
+    }
+
     /// Sets the name of the object.
     fn set_name<S: Into<String>>(&self, name: S) {
         self.as_ref().send(Operation::SetName(name.into()));
@@ -198,7 +209,7 @@ impl Object for Base {
 
                 SubLight::Directional => ObjectType::DirectionalLight(light::Directional { object: self.clone() }),
 
-                SubLight::Point => ObjectType::PointLight(light::Point { object: self.clone() }),
+                SubLight::Point { .. } => ObjectType::PointLight(light::Point { object: self.clone() }),
 
                 SubLight::Hemisphere { .. } => ObjectType::HemisphereLight(light::Hemisphere { object: self.clone() }),
             },
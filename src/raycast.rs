@@ -0,0 +1,399 @@
+//! Ray queries against the meshes of a scene, computed on the CPU.
+//!
+//! Every mesh created from a [`Geometry`] keeps its positions and faces,
+//! together with a bounding volume hierarchy that is built on the first query.
+//! Meshes sharing geometry through instancing share it as well.
+//!
+//! Only the rest pose is considered: skinning and morph targets are ignored.
+//! Sprites, lines and dynamic meshes are never hit.
+//!
+//! [`Geometry`]: ../struct.Geometry.html
+
+use std::sync::OnceLock;
+
+use cgmath::prelude::*;
+use cgmath::{Point3, Vector3};
+
+use crate::camera::{self, Camera};
+use crate::geometry::Geometry;
+use crate::hub::SubNode;
+use crate::material::Material;
+use crate::object::Base;
+use crate::scene::Scene;
+
+/// Maximum number of triangles in a leaf of the hierarchy.
+const LEAF_SIZE: usize = 4;
+
+/// A half-line in world space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    /// Starting point.
+    pub origin: mint::Point3<f32>,
+    /// Direction, normalized by [`Raycaster::new`](struct.Raycaster.html#method.new).
+    pub direction: mint::Vector3<f32>,
+}
+
+/// Intersection of a ray with a mesh triangle.
+#[derive(Clone, Debug)]
+pub struct Hit {
+    /// The mesh that was hit.
+    pub object: Base,
+    /// Distance from the ray origin to `point`.
+    pub distance: f32,
+    /// Index of the triangle in `Geometry::faces`.
+    pub face: usize,
+    /// Weights of the three triangle vertices at `point`.
+    pub barycentric: [f32; 3],
+    /// World space position of the hit.
+    pub point: mint::Point3<f32>,
+    /// World space normal of the triangle, facing the ray origin.
+    pub normal: mint::Vector3<f32>,
+}
+
+/// Casts rays into a [`Scene`](../scene/struct.Scene.html).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Raycaster {
+    /// The ray to cast.
+    pub ray: Ray,
+    /// Hits closer than this distance are ignored. Default: `0.0`.
+    pub near: f32,
+    /// Hits further than this distance are ignored. Default: `f32::INFINITY`.
+    pub far: f32,
+}
+
+impl Raycaster {
+    /// Creates a raycaster from a ray origin and direction.
+    pub fn new<P, V>(origin: P, direction: V) -> Self
+    where
+        P: Into<mint::Point3<f32>>,
+        V: Into<mint::Vector3<f32>>,
+    {
+        let direction = Vector3::from(direction.into()).normalize();
+        Raycaster { ray: Ray { origin: origin.into(), direction: direction.into() }, near: 0.0, far: f32::INFINITY }
+    }
+
+    /// Creates a raycaster going through a point of the screen, e.g.
+    /// [`Input::mouse_pos_ndc`](../struct.Input.html#method.mouse_pos_ndc).
+    ///
+    /// The ray starts on the near plane of the camera, see
+    /// [`Camera::ray_from_ndc`](../camera/struct.Camera.html#method.ray_from_ndc).
+    pub fn from_camera<P: Into<mint::Point2<f32>>>(scene: &Scene, camera: &Camera, aspect_ratio: f32, ndc: P) -> Self {
+        let hub = scene.hub.lock().unwrap();
+        let (mx_view, mx_proj) = camera.matrices(&hub, &scene.first_child, aspect_ratio);
+        let (origin, direction) = camera::unproject_ray(mx_view, mx_proj, ndc.into());
+        Raycaster::new(origin, direction)
+    }
+
+    /// Returns the hits of the visible meshes in `scene`, closest first.
+    pub fn intersect(&self, scene: &Scene) -> Vec<Hit> {
+        let origin = Point3::from(self.ray.origin);
+        let direction = Vector3::from(self.ray.direction);
+        let hub = scene.hub.lock().unwrap();
+        let mut hits = Vec::new();
+        for w in hub.walk(&scene.first_child) {
+            let (material, gpu_data) = match w.node.sub_node {
+                SubNode::Visual(ref material, ref gpu_data, _) => (material, gpu_data),
+                _ => continue,
+            };
+            let collider = match (material, &gpu_data.collider) {
+                (&Material::Line(_), _) | (_, &None) => continue,
+                (_, &Some(ref collider)) => collider,
+            };
+            // the ray parameter is the same in local space, as long as the
+            // direction is not normalized again
+            let inverse = match w.world_transform.inverse_transform() {
+                Some(inverse) => inverse,
+                None => continue,
+            };
+            let local_origin = inverse.transform_point(origin);
+            let local_direction = inverse.transform_vector(direction);
+            collider.intersect(local_origin, local_direction, self.near, self.far, |face, distance, barycentric, normal| {
+                let point = origin + direction * distance;
+                let mut normal = w.world_transform.transform_vector(normal).normalize();
+                if normal.dot(direction) > 0.0 {
+                    normal = -normal;
+                }
+                hits.push(Hit { object: hub.upgrade_ptr(w.node_ptr.clone()), distance, face, barycentric, point: point.into(), normal: normal.into() });
+            });
+        }
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits
+    }
+}
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug)]
+struct Aabb {
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+}
+
+impl Aabb {
+    const EMPTY: Aabb = Aabb { min: Vector3 { x: f32::INFINITY, y: f32::INFINITY, z: f32::INFINITY }, max: Vector3 { x: f32::NEG_INFINITY, y: f32::NEG_INFINITY, z: f32::NEG_INFINITY } };
+
+    fn grow(&mut self, p: Vector3<f32>) {
+        self.min = Vector3::new(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z));
+        self.max = Vector3::new(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z));
+    }
+
+    /// Slab test, returns whether the ray enters the box within `[near, far]`.
+    fn hit(&self, origin: Point3<f32>, inv_direction: Vector3<f32>, near: f32, far: f32) -> bool {
+        let (mut t0, mut t1) = (near, far);
+        for axis in 0 .. 3 {
+            let a = (self.min[axis] - origin[axis]) * inv_direction[axis];
+            let b = (self.max[axis] - origin[axis]) * inv_direction[axis];
+            // `min` and `max` skip the NaN of a zero direction on the slab boundary
+            t0 = a.min(b).max(t0);
+            t1 = a.max(b).min(t1);
+        }
+        t0 <= t1
+    }
+}
+
+/// Node of the hierarchy, stored depth first so the left child follows its parent.
+#[derive(Clone, Debug)]
+struct Node {
+    bounds: Aabb,
+    /// First triangle of a leaf, or the index of the right child.
+    offset: u32,
+    /// Number of triangles of a leaf, `0` for inner nodes.
+    count: u32,
+}
+
+/// Bounding volume hierarchy over the triangles of a mesh.
+#[derive(Clone, Debug)]
+struct Bvh {
+    nodes: Vec<Node>,
+    /// Triangle indices, ordered so every leaf covers a contiguous range.
+    triangles: Vec<u32>,
+}
+
+impl Bvh {
+    fn new(positions: &[Vector3<f32>], faces: &[[u32; 3]]) -> Self {
+        let centroids: Vec<_> = faces.iter().map(|f| (positions[f[0] as usize] + positions[f[1] as usize] + positions[f[2] as usize]) / 3.0).collect();
+        let mut bvh = Bvh { nodes: Vec::with_capacity(2 * faces.len() / LEAF_SIZE + 1), triangles: (0 .. faces.len() as u32).collect() };
+        // an empty leaf would read as an inner node
+        if !faces.is_empty() {
+            bvh.build(0, faces.len(), positions, faces, &centroids);
+        }
+        bvh
+    }
+
+    /// Appends the subtree over `triangles[start .. end]`, splitting at the median
+    /// centroid along the longest axis.
+    fn build(&mut self, start: usize, end: usize, positions: &[Vector3<f32>], faces: &[[u32; 3]], centroids: &[Vector3<f32>]) {
+        let mut bounds = Aabb::EMPTY;
+        let mut centroid_bounds = Aabb::EMPTY;
+        for &triangle in &self.triangles[start .. end] {
+            for &index in &faces[triangle as usize] {
+                bounds.grow(positions[index as usize]);
+            }
+            centroid_bounds.grow(centroids[triangle as usize]);
+        }
+        let index = self.nodes.len();
+        self.nodes.push(Node { bounds, offset: start as u32, count: (end - start) as u32 });
+        if end - start <= LEAF_SIZE {
+            return;
+        }
+
+        let extent = centroid_bounds.max - centroid_bounds.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let middle = (start + end) / 2;
+        self.triangles[start .. end].select_nth_unstable_by(middle - start, |&a, &b| centroids[a as usize][axis].total_cmp(&centroids[b as usize][axis]));
+
+        self.build(start, middle, positions, faces, centroids);
+        let right = self.nodes.len() as u32;
+        self.build(middle, end, positions, faces, centroids);
+        self.nodes[index].offset = right;
+        self.nodes[index].count = 0;
+    }
+}
+
+/// CPU copy of a mesh geometry used for ray queries.
+#[derive(Debug)]
+pub(crate) struct Collider {
+    positions: Vec<Vector3<f32>>,
+    faces: Vec<[u32; 3]>,
+    bvh: OnceLock<Bvh>,
+    /// Center and radius of a sphere containing all the positions.
+    bounding_sphere: (Point3<f32>, f32),
+}
+
+impl Collider {
+    pub(crate) fn new(geometry: &Geometry) -> Self {
+        let positions: Vec<_> = geometry.base.vertices.iter().map(|&p| Vector3::new(p.x, p.y, p.z)).collect();
+        let faces = if geometry.faces.is_empty() {
+            (0 .. positions.len() as u32 / 3).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect()
+        } else {
+            geometry.faces.clone()
+        };
+        Self::with_faces(positions, faces)
+    }
+
+    pub(crate) fn with_faces(positions: Vec<Vector3<f32>>, faces: Vec<[u32; 3]>) -> Self {
+        let mut bounds = Aabb::EMPTY;
+        for &p in &positions {
+            bounds.grow(p);
+        }
+        let center = if positions.is_empty() { Vector3::zero() } else { (bounds.min + bounds.max) * 0.5 };
+        let radius = positions.iter().map(|&p| (p - center).magnitude()).fold(0.0, f32::max);
+        Collider { positions, faces, bvh: OnceLock::new(), bounding_sphere: (Point3::from_vec(center), radius) }
+    }
+
+    /// Sphere bounding the mesh in local space, used for frustum culling.
+    pub(crate) fn bounding_sphere(&self) -> (Point3<f32>, f32) {
+        self.bounding_sphere
+    }
+
+    /// Calls `hit(face, distance, barycentric, normal)` for every triangle crossed
+    /// by the ray within `[near, far]`, in local space.
+    fn intersect<F>(&self, origin: Point3<f32>, direction: Vector3<f32>, near: f32, far: f32, mut hit: F)
+    where
+        F: FnMut(usize, f32, [f32; 3], Vector3<f32>),
+    {
+        let bvh = self.bvh.get_or_init(|| Bvh::new(&self.positions, &self.faces));
+        if bvh.nodes.is_empty() {
+            return;
+        }
+        let inv_direction = Vector3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z);
+        let mut stack = vec![0u32];
+        while let Some(index) = stack.pop() {
+            let node = &bvh.nodes[index as usize];
+            if !node.bounds.hit(origin, inv_direction, near, far) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.offset);
+                stack.push(index + 1);
+                continue;
+            }
+            for &triangle in &bvh.triangles[node.offset as usize .. (node.offset + node.count) as usize] {
+                let face = self.faces[triangle as usize];
+                let [a, b, c] = face.map(|i| self.positions[i as usize]);
+                if let Some((distance, u, v)) = intersect_triangle(origin, direction, a, b, c) {
+                    if distance >= near && distance <= far {
+                        hit(triangle as usize, distance, [1.0 - u - v, u, v], (b - a).cross(c - a));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Möller-Trumbore intersection of a ray with a two-sided triangle,
+/// returns the ray parameter and the barycentric coordinates of `b` and `c`.
+fn intersect_triangle(origin: Point3<f32>, direction: Vector3<f32>, a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> Option<(f32, f32, f32)> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = direction.cross(edge2);
+    let det = edge1.dot(p);
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin.to_vec() - a;
+    let u = s.dot(p) * inv_det;
+    if u < 0.0 || u > 1.0 {
+        return None;
+    }
+    let q = s.cross(edge1);
+    let v = direction.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    Some((edge2.dot(q) * inv_det, u, v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns `(face, distance, barycentric)` of every hit, closest first.
+    fn cast(collider: &Collider, origin: [f32; 3], direction: [f32; 3], near: f32, far: f32) -> Vec<(usize, f32, [f32; 3])> {
+        let mut hits = Vec::new();
+        collider.intersect(Point3::from(origin), Vector3::from(direction), near, far, |face, distance, barycentric, _| hits.push((face, distance, barycentric)));
+        hits.sort_by(|a, b| a.1.total_cmp(&b.1));
+        hits
+    }
+
+    /// Unit quad in the `z = 0` plane.
+    fn quad() -> Collider {
+        let positions = vec![Vector3::new(-1.0, -1.0, 0.0), Vector3::new(1.0, -1.0, 0.0), Vector3::new(1.0, 1.0, 0.0), Vector3::new(-1.0, 1.0, 0.0)];
+        Collider::with_faces(positions, vec![[0, 1, 2], [0, 2, 3]])
+    }
+
+    /// Cube from `-1` to `1`, with more triangles than a leaf holds.
+    fn cube() -> Collider {
+        let positions = (0 .. 8).map(|i| Vector3::new(if i & 1 != 0 { 1.0 } else { -1.0 }, if i & 2 != 0 { 1.0 } else { -1.0 }, if i & 4 != 0 { 1.0 } else { -1.0 })).collect();
+        let faces = vec![[0, 2, 6], [0, 6, 4], [1, 5, 7], [1, 7, 3], [0, 4, 5], [0, 5, 1], [2, 3, 7], [2, 7, 6], [0, 1, 3], [0, 3, 2], [4, 6, 7], [4, 7, 5]];
+        Collider::with_faces(positions, faces)
+    }
+
+    #[test]
+    fn quad_hit() {
+        let collider = quad();
+        let hits = cast(&collider, [0.5, -0.5, 1.0], [0.0, 0.0, -1.0], 0.0, f32::INFINITY);
+        assert_eq!(hits.len(), 1);
+        let (face, distance, barycentric) = hits[0];
+        assert_eq!(face, 0);
+        assert!((distance - 1.0).abs() < 1e-6);
+        assert!((barycentric.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        let point = collider.faces[face].iter().zip(&barycentric).fold(Vector3::zero(), |sum, (&i, &w)| sum + collider.positions[i as usize] * w);
+        assert!((point - Vector3::new(0.5, -0.5, 0.0)).magnitude() < 1e-6);
+        // both sides are hit
+        assert_eq!(cast(&collider, [0.5, -0.5, -1.0], [0.0, 0.0, 1.0], 0.0, f32::INFINITY).len(), 1);
+    }
+
+    #[test]
+    fn quad_miss() {
+        let collider = quad();
+        assert!(cast(&collider, [2.0, 0.0, 1.0], [0.0, 0.0, -1.0], 0.0, f32::INFINITY).is_empty());
+        // the quad is behind the origin
+        assert!(cast(&collider, [0.5, -0.5, -1.0], [0.0, 0.0, -1.0], 0.0, f32::INFINITY).is_empty());
+        // the ray runs within the plane of the quad
+        assert!(cast(&collider, [-5.0, 0.5, 0.0], [1.0, 0.0, 0.0], 0.0, f32::INFINITY).is_empty());
+    }
+
+    #[test]
+    fn near_far() {
+        let collider = quad();
+        let origin = [0.5, -0.5, 1.0];
+        let direction = [0.0, 0.0, -1.0];
+        assert!(cast(&collider, origin, direction, 1.5, f32::INFINITY).is_empty());
+        assert!(cast(&collider, origin, direction, 0.0, 0.5).is_empty());
+        assert_eq!(cast(&collider, origin, direction, 0.5, 1.5).len(), 1);
+    }
+
+    #[test]
+    fn cube_axis_parallel() {
+        let collider = cube();
+        for (axis, origin) in [[-5.0, 0.25, 0.3], [0.3, -5.0, 0.25], [0.25, 0.3, -5.0]].iter().enumerate() {
+            let mut direction = [0.0; 3];
+            direction[axis] = 1.0;
+            let hits = cast(&collider, *origin, direction, 0.0, f32::INFINITY);
+            let distances: Vec<_> = hits.iter().map(|hit| hit.1).collect();
+            assert_eq!(distances.len(), 2, "axis {}", axis);
+            assert!((distances[0] - 4.0).abs() < 1e-5 && (distances[1] - 6.0).abs() < 1e-5, "axis {}: {:?}", axis, distances);
+        }
+    }
+
+    #[test]
+    fn cube_sort_order() {
+        let collider = cube();
+        let hits = cast(&collider, [0.2, 0.1, 5.0], [0.1, 0.05, -1.0], 0.0, f32::INFINITY);
+        assert_eq!(hits.len(), 2);
+        assert!(hits[0].1 < hits[1].1);
+        // entering through `+z`, leaving through `-z`
+        assert!(hits[0].0 >= 10 && hits[1].0 >= 8 && hits[1].0 < 10, "{:?}", hits);
+        // starting inside the cube only hits the exit face
+        let inside = cast(&collider, [0.0, 0.1, 0.2], [0.0, 0.0, -1.0], 0.0, f32::INFINITY);
+        assert_eq!(inside.len(), 1);
+        assert!((inside[0].1 - 1.2).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,175 @@
+//! Reading back rendered frames.
+
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::{mem, thread};
+
+use image::RgbaImage;
+
+use super::Renderer;
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+quick_error! {
+    #[doc = "Error encountered when capturing a frame."]
+    #[derive(Clone, Debug)]
+    pub enum CaptureError {
+        #[doc = "The frame has not been rendered and copied yet."]
+        Pending {
+            description("Frame capture is pending")
+            display("Frame capture is pending")
+        }
+
+        #[doc = "The renderer was dropped before the frame was captured."]
+        Cancelled {
+            description("Frame capture was cancelled")
+            display("Frame capture was cancelled")
+        }
+
+        #[doc = "The surface format can't be converted to RGBA."]
+        Format(format: wgpu::TextureFormat) {
+            description("Unsupported surface format")
+            display("Unsupported surface format: {:?}", format)
+        }
+
+        #[doc = "Mapping the read-back buffer failed."]
+        Map(err: wgpu::BufferAsyncError) {
+            from()
+            description("Buffer mapping error")
+            display("Buffer mapping error: {}", err)
+            cause(err)
+        }
+    }
+}
+
+/// Where a captured frame goes.
+pub(crate) enum Destination {
+    Image(mpsc::Sender<Result<RgbaImage, CaptureError>>),
+    File(PathBuf),
+}
+
+impl Destination {
+    fn deliver(self, result: Result<RgbaImage, CaptureError>) {
+        match self {
+            Destination::Image(sender) => {
+                // the handle may have been dropped, nothing to do then
+                let _ = sender.send(result);
+            }
+            Destination::File(path) => match result {
+                // PNG encoding is slow, keep it off the render thread
+                Ok(image) => {
+                    thread::spawn(move || {
+                        if let Err(err) = image.save(&path) {
+                            error!("Unable to save frame capture to {}: {}", path.display(), err);
+                        }
+                    });
+                }
+                Err(err) => error!("Unable to capture frame for {}: {}", path.display(), err),
+            },
+        }
+    }
+}
+
+/// Frame requested by [`Renderer::capture_frame`](struct.Renderer.html#method.capture_frame).
+///
+/// The pixels are copied asynchronously after the frame is rendered, so the
+/// image typically becomes available one or two frames later.
+pub struct FrameCapture {
+    device: wgpu::Device,
+    receiver: mpsc::Receiver<Result<RgbaImage, CaptureError>>,
+}
+
+impl FrameCapture {
+    /// Returns the captured image if the copy has finished, without blocking.
+    pub fn try_image(&self) -> Option<Result<RgbaImage, CaptureError>> {
+        let _ = self.device.poll(wgpu::PollType::Poll);
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Err(CaptureError::Cancelled)),
+        }
+    }
+
+    /// Blocks until the GPU has finished copying the frame.
+    ///
+    /// Returns `CaptureError::Pending` if the frame has not been rendered yet,
+    /// in which case the capture can be waited for again after
+    /// [`render`](struct.Renderer.html#method.render).
+    pub fn wait(&self) -> Result<RgbaImage, CaptureError> {
+        let _ = self.device.poll(wgpu::PollType::Wait);
+        self.try_image().unwrap_or(Err(CaptureError::Pending))
+    }
+}
+
+impl Renderer {
+    /// Requests a copy of the next frame drawn by [`render`](#method.render).
+    ///
+    /// The image has the physical size of the window, which differs from the
+    /// logical size on HiDPI screens.
+    pub fn capture_frame(&mut self) -> FrameCapture {
+        let (sender, receiver) = mpsc::channel();
+        self.capture_requests.push(Destination::Image(sender));
+        FrameCapture { device: self.device.clone(), receiver }
+    }
+
+    /// Saves the next frame drawn by [`render`](#method.render) to a file,
+    /// in the format given by the extension of `path`.
+    ///
+    /// Errors are logged, as they occur after this call has returned.
+    pub fn save_frame<P: Into<PathBuf>>(&mut self, path: P) {
+        self.capture_requests.push(Destination::File(path.into()));
+    }
+
+    /// Copies the window into a buffer and hands it to the pending requests
+    /// once mapped, without waiting for the GPU.
+    pub(super) fn read_back_frame(&mut self) {
+        let requests = mem::take(&mut self.capture_requests);
+        let swizzle = match self.surface_format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            format => {
+                for request in requests {
+                    request.deliver(Err(CaptureError::Format(format)));
+                }
+                return;
+            }
+        };
+
+        let size = self.physical_size();
+        let row_size = 4 * size[0];
+        // copied rows have to be aligned
+        let padded_row_size = row_size.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor { label: Some("frame capture"), size: padded_row_size as u64 * size[1] as u64, usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ, mapped_at_creation: false });
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("frame capture") });
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo { texture: &self.out_texture, mip_level: 0, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
+            wgpu::TexelCopyBufferInfo { buffer: &buffer, layout: wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(padded_row_size), rows_per_image: Some(size[1]) } },
+            wgpu::Extent3d { width: size[0], height: size[1], depth_or_array_layers: 1 },
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let mapped = buffer.clone();
+        buffer.map_async(wgpu::MapMode::Read, .., move |result| {
+            let image = result.map_err(CaptureError::from).map(|()| {
+                let mut pixels = Vec::with_capacity((row_size * size[1]) as usize);
+                {
+                    let data = mapped.get_mapped_range(..);
+                    for row in data.chunks(padded_row_size as usize) {
+                        pixels.extend_from_slice(&row[.. row_size as usize]);
+                    }
+                }
+                mapped.unmap();
+                for pixel in pixels.chunks_mut(4) {
+                    if swizzle {
+                        pixel.swap(0, 2);
+                    }
+                    // the window alpha is not meaningful
+                    pixel[3] = 0xFF;
+                }
+                RgbaImage::from_raw(size[0], size[1], pixels).expect("capture buffer size")
+            });
+            for request in requests {
+                request.deliver(image.clone());
+            }
+        });
+    }
+}
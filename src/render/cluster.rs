@@ -0,0 +1,185 @@
+//! Clustered light assignment.
+//!
+//! The view frustum is split into a grid of [`CLUSTER_DIMS`] cells, sliced
+//! exponentially along the depth axis for perspective projections. Every frame
+//! each light with a finite range is binned into the clusters its bounding
+//! sphere touches, so the lit shaders only loop over lights relevant to the
+//! fragment being shaded.
+//!
+//! Lights without a range (ambient, directional, hemisphere and unbounded
+//! point lights) affect every fragment. They are stored at the front of the
+//! light buffer and are not binned.
+//!
+//! [`CLUSTER_DIMS`]: constant.CLUSTER_DIMS.html
+
+use cgmath::{Matrix4, SquareMatrix, Vector3, Vector4};
+
+use crate::camera::{Projection, ZRange};
+
+/// Number of clusters along the X, Y and Z axes of the view frustum.
+///
+/// Note: has to match the size of the cluster buffer bound by the renderer.
+pub(crate) const CLUSTER_DIMS: [u32; 3] = [16, 9, 24];
+
+/// Far end of the cluster grid for projections with an infinite `ZRange`.
+///
+/// Everything further away falls into the last depth slice, which is
+/// extended up to `UNBOUNDED_DEPTH` in that case.
+const INFINITE_FAR: f32 = 1000.0;
+const UNBOUNDED_DEPTH: f32 = 1.0e6;
+
+/// A light with a finite range, in view space.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LocalLight {
+    /// Index of the light in the light buffer.
+    pub index: u32,
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Aabb {
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+}
+
+impl Aabb {
+    fn empty() -> Self {
+        Aabb { min: Vector3::new(f32::MAX, f32::MAX, f32::MAX), max: Vector3::new(f32::MIN, f32::MIN, f32::MIN) }
+    }
+
+    fn grow(&mut self, p: Vector3<f32>) {
+        self.min = Vector3::new(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z));
+        self.max = Vector3::new(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z));
+    }
+
+    fn intersects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        let mut dist_sq = 0.0;
+        for i in 0 .. 3 {
+            let d = if center[i] < self.min[i] {
+                self.min[i] - center[i]
+            } else if center[i] > self.max[i] {
+                center[i] - self.max[i]
+            } else {
+                0.0
+            };
+            dist_sq += d * d;
+        }
+        dist_sq <= radius * radius
+    }
+}
+
+/// Result of binning lights into clusters, uploaded to the GPU as-is.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ClusterData {
+    /// `[offset, count]` into `indices` for every cluster, X-major.
+    pub cells: Vec<[u32; 2]>,
+    /// Light buffer indices, grouped by cluster.
+    pub indices: Vec<u32>,
+}
+
+/// View-space bounds of every cluster for a given projection.
+#[derive(Clone, Debug)]
+pub(crate) struct ClusterGrid {
+    near: f32,
+    far: f32,
+    exponential: bool,
+    unbounded: bool,
+    bounds: Vec<Aabb>,
+}
+
+impl ClusterGrid {
+    /// Computes the cluster bounds of `projection` at the given aspect ratio.
+    pub fn new(projection: &Projection, aspect_ratio: f32) -> Self {
+        let (near, far, exponential, unbounded) = match *projection {
+            Projection::Orthographic(ref p) => (p.range.start, p.range.end, false, false),
+            Projection::Perspective(ref p) => match p.zrange {
+                ZRange::Finite(ref range) => (range.start, range.end, true, false),
+                ZRange::Infinite(ref range) => (range.start, INFINITE_FAR.max(2.0 * range.start), true, true),
+            },
+        };
+        let mx_inv_proj = Matrix4::from(projection.matrix(aspect_ratio)).invert().unwrap();
+        let mut grid = ClusterGrid { near, far, exponential, unbounded, bounds: Vec::with_capacity((CLUSTER_DIMS[0] * CLUSTER_DIMS[1] * CLUSTER_DIMS[2]) as usize) };
+
+        // Each tile corner is a line in view space, which gets clipped
+        // against the depth planes of the slice.
+        let corner = |x: u32, y: u32, depth: f32| {
+            let ndc_x = 2.0 * x as f32 / CLUSTER_DIMS[0] as f32 - 1.0;
+            let ndc_y = 2.0 * y as f32 / CLUSTER_DIMS[1] as f32 - 1.0;
+            let unproject = |z: f32| {
+                let p = mx_inv_proj * Vector4::new(ndc_x, ndc_y, z, 1.0);
+                p.truncate() / p.w
+            };
+            let (a, b) = (unproject(-1.0), unproject(0.0));
+            let t = (-depth - a.z) / (b.z - a.z);
+            a + (b - a) * t
+        };
+        for z in 0 .. CLUSTER_DIMS[2] {
+            let z0 = grid.slice_depth(z);
+            let z1 = if grid.unbounded && z + 1 == CLUSTER_DIMS[2] { UNBOUNDED_DEPTH } else { grid.slice_depth(z + 1) };
+            for y in 0 .. CLUSTER_DIMS[1] {
+                for x in 0 .. CLUSTER_DIMS[0] {
+                    let mut aabb = Aabb::empty();
+                    for &(cx, cy) in &[(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)] {
+                        aabb.grow(corner(cx, cy, z0));
+                        aabb.grow(corner(cx, cy, z1));
+                    }
+                    grid.bounds.push(aabb);
+                }
+            }
+        }
+        grid
+    }
+
+    /// View-space distance to the near plane of the given depth slice.
+    fn slice_depth(&self, slice: u32) -> f32 {
+        let t = slice as f32 / CLUSTER_DIMS[2] as f32;
+        if self.exponential {
+            self.near * (self.far / self.near).powf(t)
+        } else {
+            self.near + (self.far - self.near) * t
+        }
+    }
+
+    /// Depth slice containing the given view-space distance, clamped to the grid.
+    fn slice_index(&self, depth: f32) -> u32 {
+        let t = if self.exponential {
+            (depth.max(self.near) / self.near).ln() / (self.far / self.near).ln()
+        } else {
+            (depth - self.near) / (self.far - self.near)
+        };
+        ((t * CLUSTER_DIMS[2] as f32).max(0.0) as u32).min(CLUSTER_DIMS[2] - 1)
+    }
+
+    /// Parameters of the depth slicing, has to match `u_ClusterDepth` in `clusters.glsl`.
+    pub fn depth_params(&self) -> [f32; 4] {
+        [self.near, self.far, if self.exponential { 1.0 } else { 0.0 }, 0.0]
+    }
+
+    /// Bins the given lights into clusters.
+    pub fn bin(&self, lights: &[LocalLight]) -> ClusterData {
+        let mut lists = vec![Vec::new(); self.bounds.len()];
+        let slice_size = (CLUSTER_DIMS[0] * CLUSTER_DIMS[1]) as usize;
+        for light in lights {
+            // view space looks down the negative Z axis
+            let depth = -light.center.z;
+            if depth + light.radius < self.near {
+                continue;
+            }
+            let first = self.slice_index(depth - light.radius) as usize;
+            let last = self.slice_index(depth + light.radius) as usize;
+            for cell in first * slice_size .. (last + 1) * slice_size {
+                if self.bounds[cell].intersects_sphere(light.center, light.radius) {
+                    lists[cell].push(light.index);
+                }
+            }
+        }
+
+        let mut data = ClusterData { cells: Vec::with_capacity(lists.len()), indices: Vec::new() };
+        for list in lists {
+            data.cells.push([data.indices.len() as u32, list.len() as u32]);
+            data.indices.extend(list);
+        }
+        data
+    }
+}
@@ -0,0 +1,407 @@
+//! Programs of WGSL materials, see [`material::Custom`](../material/struct.Custom.html).
+//!
+//! The bindings declared by the shaders are found by reflection: group 0
+//! holds the engine resources declared by the `globals` and `lights` chunks,
+//! group 1 the uniforms and textures of the material.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::{fmt, mem};
+
+use wgpu::naga;
+use wgpu::util::DeviceExt;
+
+use crate::material::Custom;
+use crate::texture::Texture;
+
+use super::reload::{ShaderWatcher, WgslSource};
+use super::{custom_pipe, source, target, GpuData, Instance, PipelineCreationError, Renderer, Vertex};
+
+/// Engine bindings of group 0, has to match `globals.wgsl` and `lights.wgsl`.
+const GLOBALS_BINDING: u32 = 0;
+const LIGHTS_BINDING: u32 = 1;
+const LIGHT_CLUSTERS_BINDING: u32 = 2;
+const LIGHT_INDICES_BINDING: u32 = 3;
+
+/// Number of frames after which the bindings of a mesh that wasn't drawn
+/// are released, see `CustomBindings`.
+const BINDINGS_LIFETIME: u64 = 60;
+
+const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x2, 2 => Snorm8x4, 3 => Snorm8x4, 4 => Sint32x4, 5 => Float32x4];
+const INSTANCE_ATTRIBUTES: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![6 => Float32x4, 7 => Float32x4, 8 => Float32x4, 9 => Float32x4, 10 => Float32x4, 11 => Float32x4];
+
+/// Blending, depth and culling of a [`CustomShader`](struct.CustomShader.html).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShaderState {
+    /// Blending of the output with the target, `None` replaces it.
+    ///
+    /// Default: `None`.
+    pub blend: Option<wgpu::BlendState>,
+    /// Comparison against the depth buffer.
+    ///
+    /// Default: `CompareFunction::LessEqual`.
+    pub depth_compare: wgpu::CompareFunction,
+    /// Whether the depth of the mesh is written.
+    ///
+    /// Default: `true`.
+    pub depth_write: bool,
+    /// Faces that are not drawn, front faces being counter-clockwise.
+    ///
+    /// Default: `Some(Face::Back)`.
+    pub cull_mode: Option<wgpu::Face>,
+}
+
+impl Default for ShaderState {
+    fn default() -> Self {
+        ShaderState { blend: None, depth_compare: wgpu::CompareFunction::LessEqual, depth_write: true, cull_mode: Some(wgpu::Face::Back) }
+    }
+}
+
+/// Bind groups of a mesh drawn with a custom material, kept between frames.
+///
+/// The material group is rebuilt when the program or the textures change,
+/// while new uniforms of the same size are written into the existing buffer.
+pub(super) struct CustomBindings {
+    engine_layout: wgpu::BindGroupLayout,
+    /// Buffers bound by `engine`, which are recreated when they grow.
+    engine_buffers: Vec<wgpu::Buffer>,
+    engine: wgpu::BindGroup,
+    material_layout: wgpu::BindGroupLayout,
+    textures: BTreeMap<String, Texture<[f32; 4]>>,
+    uniforms: Vec<u8>,
+    uniform_buf: wgpu::Buffer,
+    material: wgpu::BindGroup,
+    /// Value of `Renderer::custom_frame` when last drawn.
+    last_used: u64,
+}
+
+/// Pads uniforms with zeros to the `size` of the block declared by the
+/// shader, and to a valid buffer size, as uniform buffers can't be empty
+/// and are padded to 16 bytes.
+fn padded_uniforms(uniforms: &[u8], size: u64) -> Vec<u8> {
+    let mut padded = uniforms.to_vec();
+    let len = uniforms.len().max(size as usize);
+    padded.resize(((len + 15) / 16).max(1) * 16, 0);
+    padded
+}
+
+/// Resource bound to a material binding.
+#[derive(Clone, Debug)]
+enum MaterialBinding {
+    /// `Custom::uniforms`.
+    Uniforms,
+    /// View of the texture `Custom::textures[name]`.
+    Texture(String),
+    /// Sampler of the texture `Custom::textures[name]`.
+    Sampler(String),
+}
+
+struct Program {
+    vertex_source: WgslSource,
+    fragment_source: WgslSource,
+    vertex: wgpu::ShaderModule,
+    fragment: wgpu::ShaderModule,
+    state: ShaderState,
+    engine_layout: wgpu::BindGroupLayout,
+    engine_bindings: Vec<u32>,
+    material_layout: wgpu::BindGroupLayout,
+    material_bindings: Vec<(u32, MaterialBinding)>,
+    /// Size of the uniform block of group 1, `0` without one.
+    uniform_size: u64,
+    layout: wgpu::PipelineLayout,
+    /// Pipelines by color target format, created on first use.
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl Program {
+    /// Compiles `vertex` and `fragment`, the code of the sources with their
+    /// chunks included.
+    fn new(device: &wgpu::Device, vertex_source: &WgslSource, vertex: String, fragment_source: &WgslSource, fragment: String, state: ShaderState) -> Result<Self, PipelineCreationError> {
+        let vertex_module = source::check_wgsl(&vertex)?;
+        let fragment_module = source::check_wgsl(&fragment)?;
+
+        // both stages see every binding, declared by either of them
+        let mut bindings = BTreeMap::new();
+        let mut uniform_size = 0;
+        for module in &[&vertex_module, &fragment_module] {
+            let mut layouter = naga::proc::Layouter::default();
+            layouter.update(module.to_ctx()).map_err(|err| PipelineCreationError::Compilation(err.to_string()))?;
+            for (_, var) in module.global_variables.iter() {
+                let binding = match var.binding {
+                    Some(ref binding) => binding,
+                    None => continue,
+                };
+                let name = var.name.clone().unwrap_or_default();
+                let ty = binding_type(module, var).ok_or_else(|| PipelineCreationError::Compilation(format!("unsupported binding type of {}", name)))?;
+                if binding.group == 1 && var.space == naga::AddressSpace::Uniform {
+                    uniform_size = uniform_size.max(layouter[var.ty].size as u64);
+                }
+                bindings.insert((binding.group, binding.binding), (name, ty));
+            }
+        }
+
+        let mut engine_entries = Vec::new();
+        let mut material_entries = Vec::new();
+        let mut material_bindings = Vec::new();
+        for (&(group, binding), &(ref name, ty)) in &bindings {
+            let entry = wgpu::BindGroupLayoutEntry { binding, visibility: wgpu::ShaderStages::VERTEX_FRAGMENT, ty, count: None };
+            match group {
+                0 if binding <= LIGHT_INDICES_BINDING => engine_entries.push(entry),
+                1 => {
+                    let resource = match ty {
+                        wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, .. } if !material_bindings.iter().any(|&(_, ref b)| matches!(*b, MaterialBinding::Uniforms)) => MaterialBinding::Uniforms,
+                        wgpu::BindingType::Texture { .. } if name.starts_with("t_") => MaterialBinding::Texture(name[2 ..].to_string()),
+                        wgpu::BindingType::Sampler(_) if name.starts_with("s_") => MaterialBinding::Sampler(name[2 ..].to_string()),
+                        _ => return Err(PipelineCreationError::Compilation(format!("unexpected material binding {}", name))),
+                    };
+                    material_entries.push(entry);
+                    material_bindings.push((binding, resource));
+                }
+                _ => return Err(PipelineCreationError::Compilation(format!("unexpected binding {} at group {}, binding {}", name, group, binding))),
+            }
+        }
+
+        let engine_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: Some("custom material engine"), entries: &engine_entries });
+        let material_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: Some("custom material"), entries: &material_entries });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { label: Some("custom material"), bind_group_layouts: &[&engine_layout, &material_layout], push_constant_ranges: &[] });
+        Ok(Program {
+            vertex_source: vertex_source.clone(),
+            fragment_source: fragment_source.clone(),
+            vertex: device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("custom material vertex"), source: wgpu::ShaderSource::Wgsl(vertex.into()) }),
+            fragment: device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("custom material fragment"), source: wgpu::ShaderSource::Wgsl(fragment.into()) }),
+            state,
+            engine_layout,
+            engine_bindings: engine_entries.iter().map(|entry| entry.binding).collect(),
+            material_layout,
+            material_bindings,
+            uniform_size,
+            layout,
+            pipelines: HashMap::new(),
+        })
+    }
+}
+
+/// Compiled shaders of a [`material::Custom`](../material/struct.Custom.html).
+///
+/// Created by [`Factory::custom_shader`](../struct.Factory.html#method.custom_shader).
+/// Cloning is cheap, and clones compare equal.
+#[derive(Clone)]
+pub struct CustomShader(Arc<Mutex<Program>>);
+
+impl CustomShader {
+    /// Takes the included chunks from `directory` when it has them.
+    pub(crate) fn new(device: &wgpu::Device, vertex: &str, fragment: &str, state: ShaderState, directory: Option<&Path>) -> Result<Self, PipelineCreationError> {
+        let mut vertex_source = WgslSource::new(vertex.to_string());
+        let mut fragment_source = WgslSource::new(fragment.to_string());
+        let vertex = vertex_source.process(directory)?;
+        let fragment = fragment_source.process(directory)?;
+        let program = Program::new(device, &vertex_source, vertex, &fragment_source, fragment, state)?;
+        Ok(CustomShader(Arc::new(Mutex::new(program))))
+    }
+
+    /// Compiles the shader again if the chunks it includes changed, keeping
+    /// the previous program if that fails.
+    fn reload(&self, device: &wgpu::Device, watcher: &ShaderWatcher) {
+        let mut program = self.0.lock().unwrap();
+        if !watcher.is_stale(&program.vertex_source) && !watcher.is_stale(&program.fragment_source) {
+            return;
+        }
+        let mut vertex_source = program.vertex_source.clone();
+        let mut fragment_source = program.fragment_source.clone();
+        let state = program.state;
+        let result = match (watcher.process(&mut vertex_source), watcher.process(&mut fragment_source)) {
+            (Ok(vertex), Ok(fragment)) => Program::new(device, &vertex_source, vertex, &fragment_source, fragment, state),
+            (Err(err), _) | (_, Err(err)) => Err(err),
+        };
+        match result {
+            Ok(new) => *program = new,
+            Err(err) => {
+                error!("Custom shader reload failed: {}", err);
+                program.vertex_source = vertex_source;
+                program.fragment_source = fragment_source;
+            }
+        }
+    }
+
+    /// Returns the blending, depth and culling of the shader.
+    pub fn state(&self) -> ShaderState {
+        self.0.lock().unwrap().state
+    }
+
+    /// Address of the program, identifying the pipeline in the statistics.
+    pub(crate) fn id(&self) -> *const () {
+        Arc::as_ptr(&self.0) as *const ()
+    }
+
+    fn pipeline(program: &mut Program, device: &wgpu::Device, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+        program
+            .pipelines
+            .entry(format)
+            .or_insert_with(|| {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("custom material"),
+                    layout: Some(&program.layout),
+                    vertex: wgpu::VertexState {
+                        module: &program.vertex,
+                        entry_point: Some("vs_main"),
+                        compilation_options: Default::default(),
+                        buffers: &[
+                            wgpu::VertexBufferLayout { array_stride: mem::size_of::<Vertex>() as u64, step_mode: wgpu::VertexStepMode::Vertex, attributes: &VERTEX_ATTRIBUTES },
+                            wgpu::VertexBufferLayout { array_stride: mem::size_of::<Instance>() as u64, step_mode: wgpu::VertexStepMode::Instance, attributes: &INSTANCE_ATTRIBUTES },
+                        ],
+                    },
+                    primitive: wgpu::PrimitiveState { cull_mode: program.state.cull_mode, ..Default::default() },
+                    depth_stencil: Some(wgpu::DepthStencilState { format: target::DEPTH_FORMAT, depth_write_enabled: program.state.depth_write, depth_compare: program.state.depth_compare, stencil: Default::default(), bias: Default::default() }),
+                    multisample: wgpu::MultisampleState::default(),
+                    fragment: Some(wgpu::FragmentState { module: &program.fragment, entry_point: Some("fs_main"), compilation_options: Default::default(), targets: &[Some(wgpu::ColorTargetState { format, blend: program.state.blend, write_mask: wgpu::ColorWrites::ALL })] }),
+                    multiview: None,
+                    cache: None,
+                })
+            })
+            .clone()
+    }
+}
+
+impl fmt::Debug for CustomShader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("CustomShader").field(&self.id()).finish()
+    }
+}
+
+impl PartialEq for CustomShader {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CustomShader {}
+
+impl Hash for CustomShader {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.id() as usize).hash(state);
+    }
+}
+
+/// Layout of a global variable of a shader, if it can be bound.
+fn binding_type(module: &naga::Module, var: &naga::GlobalVariable) -> Option<wgpu::BindingType> {
+    match var.space {
+        naga::AddressSpace::Uniform => Some(wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None }),
+        naga::AddressSpace::Storage { access } => Some(wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Storage { read_only: !access.contains(naga::StorageAccess::STORE) }, has_dynamic_offset: false, min_binding_size: None }),
+        naga::AddressSpace::Handle => match module.types[var.ty].inner {
+            naga::TypeInner::Image { dim: naga::ImageDimension::D2, arrayed: false, class } => {
+                let sample_type = match class {
+                    naga::ImageClass::Sampled { kind: naga::ScalarKind::Sint, multi: false } => wgpu::TextureSampleType::Sint,
+                    naga::ImageClass::Sampled { kind: naga::ScalarKind::Uint, multi: false } => wgpu::TextureSampleType::Uint,
+                    naga::ImageClass::Sampled { kind: naga::ScalarKind::Float, multi: false } => wgpu::TextureSampleType::Float { filterable: true },
+                    naga::ImageClass::Depth { multi: false } => wgpu::TextureSampleType::Depth,
+                    _ => return None,
+                };
+                Some(wgpu::BindingType::Texture { sample_type, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false })
+            }
+            naga::TypeInner::Sampler { comparison } => Some(wgpu::BindingType::Sampler(if comparison { wgpu::SamplerBindingType::Comparison } else { wgpu::SamplerBindingType::Filtering })),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl Renderer {
+    /// Draws a mesh with a custom material, expecting its transform in
+    /// `gpu_data.instances` and the globals of the camera in `globals_buf`.
+    pub(super) fn custom_draw(&mut self, material: &Custom, gpu_data: &GpuData, out_color: wgpu::TextureView, out_depth: wgpu::TextureView, format: wgpu::TextureFormat) {
+        let shader = &material.shader;
+        if let Some(ref watcher) = self.shader_watcher {
+            shader.reload(&self.device, watcher);
+        }
+        let mut program = shader.0.lock().unwrap();
+        let pipeline = CustomShader::pipeline(&mut program, &self.device, format);
+
+        let engine_buffers: Vec<_> = program
+            .engine_bindings
+            .iter()
+            .map(|&binding| match binding {
+                GLOBALS_BINDING => self.globals_buf.clone(),
+                LIGHTS_BINDING => self.light_buf.clone(),
+                LIGHT_CLUSTERS_BINDING => self.light_clusters_buf.clone(),
+                _ => self.light_indices_buf.clone(),
+            })
+            .collect();
+        let uniforms = padded_uniforms(&material.uniforms, program.uniform_size);
+
+        // the instance buffer is unique to the mesh
+        let key = gpu_data.instances.clone();
+        let stale = match self.custom_bindings.get(&key) {
+            Some(cached) => cached.engine_layout != program.engine_layout || cached.material_layout != program.material_layout || cached.textures != material.textures || cached.uniform_buf.size() != uniforms.len() as u64,
+            None => true,
+        };
+        if stale {
+            let engine = Self::create_engine_group(&self.device, &program, &engine_buffers);
+            let uniform_buf = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some("custom material uniforms"), contents: &uniforms, usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST });
+            let params: Vec<_> = program
+                .material_bindings
+                .iter()
+                .map(|&(binding, ref resource)| {
+                    let texture = match *resource {
+                        MaterialBinding::Texture(ref name) | MaterialBinding::Sampler(ref name) => material.textures.get(name).unwrap_or(&self.map_default).to_param(),
+                        MaterialBinding::Uniforms => self.map_default.to_param(),
+                    };
+                    (binding, resource, texture)
+                })
+                .collect();
+            let material_entries: Vec<_> = params
+                .iter()
+                .map(|&(binding, resource, ref texture)| {
+                    let resource = match *resource {
+                        MaterialBinding::Uniforms => uniform_buf.as_entire_binding(),
+                        MaterialBinding::Texture(_) => wgpu::BindingResource::TextureView(&texture.0),
+                        MaterialBinding::Sampler(_) => wgpu::BindingResource::Sampler(&texture.1),
+                    };
+                    wgpu::BindGroupEntry { binding, resource }
+                })
+                .collect();
+            let material_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor { label: Some("custom material"), layout: &program.material_layout, entries: &material_entries });
+            let cached = CustomBindings {
+                engine_layout: program.engine_layout.clone(),
+                engine_buffers: engine_buffers.clone(),
+                engine,
+                material_layout: program.material_layout.clone(),
+                textures: material.textures.clone(),
+                uniforms: material.uniforms.clone(),
+                uniform_buf,
+                material: material_group,
+                last_used: self.custom_frame,
+            };
+            self.custom_bindings.insert(key.clone(), cached);
+        }
+
+        let cached = self.custom_bindings.get_mut(&key).unwrap();
+        if cached.engine_buffers != engine_buffers {
+            cached.engine = Self::create_engine_group(&self.device, &program, &engine_buffers);
+            cached.engine_buffers = engine_buffers;
+        }
+        if cached.uniforms != material.uniforms {
+            self.queue.write_buffer(&cached.uniform_buf, 0, &uniforms);
+            cached.uniforms = material.uniforms.clone();
+        }
+        cached.last_used = self.custom_frame;
+        let (engine, material_group) = (cached.engine.clone(), cached.material.clone());
+
+        let data = custom_pipe::Data { vbuf: gpu_data.vertices.clone(), inst_buf: gpu_data.instances.clone(), engine, material: material_group, target: out_color, depth_target: out_depth };
+        self.encoder.draw(&gpu_data.slice, &pipeline, &data);
+    }
+
+    fn create_engine_group(device: &wgpu::Device, program: &Program, buffers: &[wgpu::Buffer]) -> wgpu::BindGroup {
+        let entries: Vec<_> = program.engine_bindings.iter().zip(buffers).map(|(&binding, buffer)| wgpu::BindGroupEntry { binding, resource: buffer.as_entire_binding() }).collect();
+        device.create_bind_group(&wgpu::BindGroupDescriptor { label: Some("custom material engine"), layout: &program.engine_layout, entries: &entries })
+    }
+
+    /// Starts a frame of custom draws, releasing the bindings of the meshes
+    /// that weren't drawn for a while.
+    pub(super) fn begin_custom_frame(&mut self) {
+        self.custom_frame += 1;
+        let frame = self.custom_frame;
+        self.custom_bindings.retain(|_, cached| frame - cached.last_used < BINDINGS_LIFETIME);
+    }
+}
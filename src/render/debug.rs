@@ -0,0 +1,324 @@
+//! Immediate-mode debug drawing, see [`Renderer::debug`](../struct.Renderer.html#method.debug).
+
+use std::f32::consts::PI;
+use std::mem;
+use std::time::{Duration, Instant};
+
+use cgmath::prelude::*;
+use cgmath::{Matrix4, Point3, Quaternion, Vector3, Vector4};
+
+use crate::color::{self, Color};
+use crate::hub::{Hub, SubNode};
+use crate::mesh::Mesh;
+use crate::node::NodePointer;
+use crate::scene::Scene;
+
+use super::{debug_pipe, vertex_lines_pipe, DebugVertex, Renderer, Vertex, VertexLinesParams};
+
+/// Number of segments approximating a circle of `DebugDraw::sphere`.
+const CIRCLE_SEGMENTS: usize = 32;
+/// Draw vertex tangents instead of normals, see `vertex_lines_vs.glsl`.
+const TANGENTS: i32 = 1 << 0;
+
+/// How a debug shape is drawn.
+///
+/// Plain colors convert into a style lasting a single frame and hidden
+/// behind the scene geometry:
+///
+/// ```rust,no_run
+/// # let mut window = three::Window::new("Example");
+/// use std::time::Duration;
+/// use three::render::DebugStyle;
+///
+/// window.renderer.debug().line([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], three::color::RED);
+/// let style = DebugStyle { color: three::color::GREEN, depth_test: false, duration: Some(Duration::from_secs(2)) };
+/// window.renderer.debug().sphere([0.0, 1.0, 0.0], 0.5, style);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebugStyle {
+    /// Line color.
+    pub color: Color,
+    /// Hide the parts behind the scene geometry.
+    pub depth_test: bool,
+    /// How long the shape stays, or `None` for the next rendered frame only.
+    pub duration: Option<Duration>,
+}
+
+impl Default for DebugStyle {
+    fn default() -> Self {
+        DebugStyle { color: color::WHITE, depth_test: true, duration: None }
+    }
+}
+
+impl From<Color> for DebugStyle {
+    fn from(color: Color) -> Self {
+        DebugStyle { color, ..DebugStyle::default() }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Batch {
+    vertices: Vec<DebugVertex>,
+    depth_test: bool,
+    expires: Option<Instant>,
+}
+
+/// Normals or tangents of a mesh, generated on the GPU from its vertices.
+#[derive(Clone, Debug)]
+struct VertexLines {
+    node: NodePointer,
+    tangents: bool,
+    length: f32,
+    style: DebugStyle,
+    expires: Option<Instant>,
+}
+
+/// Collects debug shapes as lines in world space.
+///
+/// All the shapes are batched into a single vertex buffer and drawn on top
+/// of the scene by every [`render`](../struct.Renderer.html#method.render)
+/// and [`render_viewport`](../struct.Renderer.html#method.render_viewport)
+/// call. Shapes without a duration are dropped at the end of `render`, use
+/// [`clear`](#method.clear) when only drawing viewports.
+#[derive(Debug, Default)]
+pub struct DebugDraw {
+    batches: Vec<Batch>,
+    vertex_lines: Vec<VertexLines>,
+}
+
+impl DebugDraw {
+    fn push<S: Into<DebugStyle>>(&mut self, style: S, points: &[Point3<f32>]) {
+        let style = style.into();
+        let rgb = color::to_linear_rgb(style.color);
+        let vertices = points.iter().map(|p| DebugVertex { pos: [p.x, p.y, p.z], color: [rgb[0], rgb[1], rgb[2], 1.0] }).collect();
+        self.batches.push(Batch { vertices, depth_test: style.depth_test, expires: style.duration.map(|d| Instant::now() + d) });
+    }
+
+    /// Draws a line segment.
+    pub fn line<P: Into<mint::Point3<f32>>, S: Into<DebugStyle>>(&mut self, start: P, end: P, style: S) {
+        self.push(style, &[start.into().into(), end.into().into()]);
+    }
+
+    /// Draws a line segment with an arrow head at `end`.
+    pub fn arrow<P: Into<mint::Point3<f32>>, S: Into<DebugStyle>>(&mut self, start: P, end: P, style: S) {
+        let (start, end): (Point3<f32>, Point3<f32>) = (start.into().into(), end.into().into());
+        let dir = end - start;
+        let length = dir.magnitude();
+        if length == 0.0 {
+            return;
+        }
+        let (u, v) = perpendicular(dir / length);
+        let head = 0.2 * length;
+        let base = end - dir * 0.2;
+        let mut points = vec![start, end];
+        for side in &[u, -u, v, -v] {
+            points.push(end);
+            points.push(base + *side * 0.5 * head);
+        }
+        self.push(style, &points);
+    }
+
+    /// Draws an axis aligned box between two corners.
+    pub fn aabb<P: Into<mint::Point3<f32>>, S: Into<DebugStyle>>(&mut self, min: P, max: P, style: S) {
+        let (a, b): (Point3<f32>, Point3<f32>) = (min.into().into(), max.into().into());
+        let corner = |i: usize| Point3::new(if i & 1 == 0 { a.x } else { b.x }, if i & 2 == 0 { a.y } else { b.y }, if i & 4 == 0 { a.z } else { b.z });
+        let mut points = Vec::with_capacity(24);
+        for i in 0 .. 8 {
+            // connect each corner to its neighbours with a greater index
+            for bit in &[1, 2, 4] {
+                if i & bit == 0 {
+                    points.push(corner(i));
+                    points.push(corner(i | bit));
+                }
+            }
+        }
+        self.push(style, &points);
+    }
+
+    /// Draws a sphere as three circles around the principal axes.
+    pub fn sphere<P: Into<mint::Point3<f32>>, S: Into<DebugStyle>>(&mut self, center: P, radius: f32, style: S) {
+        let center: Point3<f32> = center.into().into();
+        let mut points = Vec::with_capacity(6 * CIRCLE_SEGMENTS);
+        for &(u, v) in &[(Vector3::unit_x(), Vector3::unit_y()), (Vector3::unit_y(), Vector3::unit_z()), (Vector3::unit_z(), Vector3::unit_x())] {
+            let point = |i: usize| {
+                let angle = 2.0 * PI * i as f32 / CIRCLE_SEGMENTS as f32;
+                center + (u * angle.cos() + v * angle.sin()) * radius
+            };
+            for i in 0 .. CIRCLE_SEGMENTS {
+                points.push(point(i));
+                points.push(point(i + 1));
+            }
+        }
+        self.push(style, &points);
+    }
+
+    /// Draws the volume seen through a view projection matrix, e.g. from
+    /// [`Camera::view_projection_matrix`](../camera/struct.Camera.html#method.view_projection_matrix).
+    ///
+    /// Nothing is drawn for an infinite far plane.
+    pub fn frustum<M: Into<mint::ColumnMatrix4<f32>>, S: Into<DebugStyle>>(&mut self, view_projection: M, style: S) {
+        let mx_inv = match Matrix4::from(view_projection.into()).invert() {
+            Some(mx) => mx,
+            None => return,
+        };
+        let mut corners = [Point3::origin(); 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let ndc = Vector4::new(if i & 1 == 0 { -1.0 } else { 1.0 }, if i & 2 == 0 { -1.0 } else { 1.0 }, if i & 4 == 0 { -1.0 } else { 1.0 }, 1.0);
+            let p = mx_inv * ndc;
+            if p.w.abs() <= f32::EPSILON {
+                return;
+            }
+            *corner = Point3::from_homogeneous(p);
+        }
+        let mut points = Vec::with_capacity(24);
+        for i in 0 .. 8 {
+            for bit in &[1, 2, 4] {
+                if i & bit == 0 {
+                    points.push(corners[i]);
+                    points.push(corners[i | bit]);
+                }
+            }
+        }
+        self.push(style, &points);
+    }
+
+    /// Draws a square grid in the XZ plane, with `divisions` cells along each side.
+    pub fn grid<P: Into<mint::Point3<f32>>, S: Into<DebugStyle>>(&mut self, center: P, size: f32, divisions: u32, style: S) {
+        let center: Point3<f32> = center.into().into();
+        let divisions = divisions.max(1);
+        let half = 0.5 * size;
+        let mut points = Vec::with_capacity(4 * (divisions as usize + 1));
+        for i in 0 ..= divisions {
+            let offset = -half + size * i as f32 / divisions as f32;
+            points.push(center + Vector3::new(offset, 0.0, -half));
+            points.push(center + Vector3::new(offset, 0.0, half));
+            points.push(center + Vector3::new(-half, 0.0, offset));
+            points.push(center + Vector3::new(half, 0.0, offset));
+        }
+        self.push(style, &points);
+    }
+
+    /// Draws the X, Y and Z axes of a frame in red, green and blue.
+    ///
+    /// The color of `style` is ignored.
+    pub fn axes<P, Q, S>(&mut self, position: P, orientation: Q, length: f32, style: S)
+    where
+        P: Into<mint::Point3<f32>>,
+        Q: Into<mint::Quaternion<f32>>,
+        S: Into<DebugStyle>,
+    {
+        let (origin, rot): (Point3<f32>, Quaternion<f32>) = (position.into().into(), orientation.into().into());
+        let style = style.into();
+        for &(axis, color) in &[(Vector3::unit_x(), color::RED), (Vector3::unit_y(), color::GREEN), (Vector3::unit_z(), color::BLUE)] {
+            self.push(DebugStyle { color, ..style }, &[origin, origin + rot.rotate_vector(axis) * length]);
+        }
+    }
+
+    /// Draws the vertex normals of a mesh, as posed for rendering.
+    pub fn vertex_normals<S: Into<DebugStyle>>(&mut self, mesh: &Mesh, length: f32, style: S) {
+        self.push_vertex_lines(mesh, false, length, style.into());
+    }
+
+    /// Draws the vertex tangents of a mesh, as posed for rendering.
+    pub fn vertex_tangents<S: Into<DebugStyle>>(&mut self, mesh: &Mesh, length: f32, style: S) {
+        self.push_vertex_lines(mesh, true, length, style.into());
+    }
+
+    fn push_vertex_lines(&mut self, mesh: &Mesh, tangents: bool, length: f32, style: DebugStyle) {
+        let node = mesh.as_ref().node.clone();
+        self.vertex_lines.push(VertexLines { node, tangents, length, style, expires: style.duration.map(|d| Instant::now() + d) });
+    }
+
+    /// Removes all the shapes, including the ones with a duration.
+    pub fn clear(&mut self) {
+        self.batches.clear();
+        self.vertex_lines.clear();
+    }
+
+    /// Drops the single frame shapes and the expired ones.
+    pub(super) fn end_frame(&mut self) {
+        let now = Instant::now();
+        self.batches.retain(|batch| batch.expires.map_or(false, |expires| expires > now));
+        self.vertex_lines.retain(|lines| lines.expires.map_or(false, |expires| expires > now));
+    }
+
+    /// Returns the vertices to draw, depth tested ones first, and their number.
+    fn vertices(&self) -> (Vec<DebugVertex>, usize) {
+        let mut vertices = Vec::new();
+        for batch in self.batches.iter().filter(|batch| batch.depth_test) {
+            vertices.extend_from_slice(&batch.vertices);
+        }
+        let num_tested = vertices.len();
+        for batch in self.batches.iter().filter(|batch| !batch.depth_test) {
+            vertices.extend_from_slice(&batch.vertices);
+        }
+        (vertices, num_tested)
+    }
+}
+
+/// Two unit vectors perpendicular to `dir` and to each other.
+fn perpendicular(dir: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let other = if dir.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+    let u = dir.cross(other).normalize();
+    (u, dir.cross(u))
+}
+
+impl Renderer {
+    /// Debug shapes drawn over the next frames, see [`DebugDraw`](struct.DebugDraw.html).
+    pub fn debug(&mut self) -> &mut DebugDraw {
+        &mut self.debug_draw
+    }
+
+    /// Draws the debug shapes, expecting the globals of the camera in `const_buf`.
+    pub(super) fn debug_pass(&mut self, hub: &Hub, scene: &Scene, out_color: wgpu::TextureView, out_depth: wgpu::TextureView) {
+        self.vertex_lines_pass(hub, scene, out_color.clone(), out_depth.clone());
+        let (vertices, num_tested) = self.debug_draw.vertices();
+        if vertices.is_empty() {
+            return;
+        }
+        Self::upload_buffer(&self.device, &self.queue, &mut self.debug_buf, "debug lines", wgpu::BufferUsages::VERTEX, &vertices);
+
+        let ranges = [(0, num_tested, true), (num_tested, vertices.len(), false)];
+        for &(start, end, depth_test) in &ranges {
+            if start == end {
+                continue;
+            }
+            let slice = gfx::Slice { start: start as u32, end: end as u32, base_vertex: 0, instances: None, buffer: gfx::IndexBuffer::Auto };
+            let pso = if depth_test { &self.pso.debug_lines } else { &self.pso.debug_lines_overlay };
+            let data = debug_pipe::Data { vbuf: self.debug_buf.clone(), globals: self.const_buf.clone(), target: out_color.clone(), depth_target: out_depth.clone() };
+            self.encoder.draw(&slice, pso, &data);
+        }
+    }
+    /// Draws a line per vertex of the meshes passed to `DebugDraw::vertex_normals`
+    /// and `DebugDraw::vertex_tangents`, using the vertex buffer as instances.
+    ///
+    /// Only the visible meshes of `scene` are drawn, as the world transforms
+    /// are computed while walking its nodes.
+    fn vertex_lines_pass(&mut self, hub: &Hub, scene: &Scene, out_color: wgpu::TextureView, out_depth: wgpu::TextureView) {
+        if self.debug_draw.vertex_lines.is_empty() {
+            return;
+        }
+        let vertex_lines = self.debug_draw.vertex_lines.clone();
+        for w in hub.walk(&scene.first_child) {
+            let (material, gpu_data, skeleton) = match w.node.sub_node {
+                SubNode::Visual(ref material, ref gpu_data, ref skeleton) => (material, gpu_data, skeleton),
+                _ => continue,
+            };
+            let mx_world = Matrix4::from(w.world_transform);
+            for lines in vertex_lines.iter().filter(|lines| lines.node == w.node_ptr) {
+                let (joint_transforms, displacements, mut flags) = self.pose_bindings(hub, material, gpu_data, skeleton);
+                if lines.tangents {
+                    flags |= TANGENTS;
+                }
+                let rgb = color::to_linear_rgb(lines.style.color);
+
+                let num_vertices = (gpu_data.vertices.size() / mem::size_of::<Vertex>() as u64) as u32;
+                let slice = gfx::Slice { start: 0, end: 2, base_vertex: 0, instances: Some((num_vertices, 0)), buffer: gfx::IndexBuffer::Auto };
+                self.encoder.update_constant_buffer(&self.vertex_lines_buf, &VertexLinesParams { world: mx_world.into(), color: [rgb[0], rgb[1], rgb[2], 1.0], length: lines.length, flags, _padding0: [0; 2] });
+                let data = vertex_lines_pipe::Data { inst_buf: gpu_data.vertices.clone(), globals: self.const_buf.clone(), params: self.vertex_lines_buf.clone(), displacement_contributions: self.displacement_contributions_buf.clone(), joint_transforms, displacements: (displacements, self.map_default.to_param().1), target: out_color.clone(), depth_target: out_depth.clone() };
+                let pso = if lines.style.depth_test { &self.pso.vertex_lines } else { &self.pso.vertex_lines_overlay };
+                self.encoder.draw(&slice, pso, &data);
+            }
+        }
+    }
+}
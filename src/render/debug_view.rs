@@ -0,0 +1,97 @@
+//! Replacing the material output with mesh attributes, see
+//! [`Renderer::debug_view`](../struct.Renderer.html#structfield.debug_view).
+
+use cgmath::Matrix4;
+
+use crate::hub::{Hub, SubNode};
+use crate::material::Material;
+use crate::scene::Scene;
+
+use super::pso_data::PsoData;
+use super::{debug_view_pipe, DebugViewParams, Instance, PipelineStates, Renderer};
+
+/// Shows an attribute of every mesh instead of its shaded color.
+///
+/// Skinned and morphed meshes are posed as usual. Lighting, the scene
+/// background and post-processing are skipped while a view is active.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugView {
+    /// World space normals, mapped from `[-1, 1]` to `[0, 1]`.
+    Normals,
+    /// World space tangents, mapped from `[-1, 1]` to `[0, 1]`.
+    Tangents,
+    /// Texture coordinates, red for U and green for V, wrapped into `[0, 1]`.
+    TexCoords,
+    /// Distance from the camera along the view direction, black at `near`
+    /// and white at `far`.
+    Depth {
+        /// Distance shown as black.
+        near: f32,
+        /// Distance shown as white.
+        far: f32,
+    },
+    /// Number of fragments drawn per pixel, from dark red to white.
+    Overdraw,
+    /// Mip level sampled from the color map of the material, going from
+    /// blue for the full resolution through green to red for level five
+    /// and above. Meshes without a map are shown in gray.
+    MipLevel,
+}
+
+impl DebugView {
+    /// Has to match the modes of `debug_view_ps.glsl`.
+    fn mode(&self) -> i32 {
+        match *self {
+            DebugView::Normals => 0,
+            DebugView::Tangents => 1,
+            DebugView::TexCoords => 2,
+            DebugView::Depth { .. } => 3,
+            DebugView::Overdraw => 4,
+            DebugView::MipLevel => 5,
+        }
+    }
+}
+
+impl PipelineStates {
+    fn debug_view_by_material(&self, material: &Material, view: DebugView) -> &gfx::PipelineState<back::Resources, debug_view_pipe::Meta> {
+        match (material, view) {
+            (&Material::Line(_), _) => &self.debug_view_line,
+            (&Material::Wireframe(_), _) => &self.debug_view_wireframe,
+            (&Material::Sprite(_), DebugView::Overdraw) => &self.debug_view_overdraw_sprite,
+            (&Material::Sprite(_), _) => &self.debug_view_sprite,
+            (_, DebugView::Overdraw) => &self.debug_view_overdraw,
+            (_, _) => &self.debug_view,
+        }
+    }
+}
+
+impl Renderer {
+    /// Draws the visible meshes of `scene` with `view` instead of their
+    /// materials, expecting the globals of the camera in `const_buf`.
+    pub(super) fn debug_view_pass(&mut self, hub: &Hub, scene: &Scene, view: DebugView, out_color: wgpu::TextureView, out_depth: wgpu::TextureView) {
+        let depth_range = match view {
+            DebugView::Depth { near, far } => [near, far, 0.0, 0.0],
+            _ => [0.0; 4],
+        };
+        for w in hub.walk(&scene.first_child) {
+            let (material, gpu_data, skeleton) = match w.node.sub_node {
+                SubNode::Visual(ref material, ref gpu_data, ref skeleton) => (material, gpu_data, skeleton),
+                _ => continue,
+            };
+            let (joint_transforms, displacements, flags) = self.pose_bindings(hub, material, gpu_data, skeleton);
+            let map = match material.to_pso_data() {
+                // the gradient ramp and sphere image do not cover the surface
+                PsoData::Toon { .. } => None,
+                PsoData::Basic { .. } if matches!(*material, Material::Matcap(_)) => None,
+                PsoData::Basic { map, .. } => map,
+                PsoData::Pbr { maps, .. } => maps.base_color(),
+            };
+
+            let mx_world: mint::ColumnMatrix4<_> = Matrix4::from(w.world_transform).into();
+            self.encoder.update_buffer(&gpu_data.instances, &[Instance::pbr(mx_world.into())], 0).unwrap();
+            self.encoder.update_constant_buffer(&self.debug_view_buf, &DebugViewParams { mode: view.mode(), flags, has_map: map.is_some() as i32, _padding0: 0, depth_range });
+            let data = debug_view_pipe::Data { vbuf: gpu_data.vertices.clone(), inst_buf: gpu_data.instances.clone(), globals: self.const_buf.clone(), params: self.debug_view_buf.clone(), displacement_contributions: self.displacement_contributions_buf.clone(), joint_transforms, displacements: (displacements, self.map_default.to_param().1), map: map.as_ref().unwrap_or(&self.map_default).to_param(), target: out_color.clone(), depth_target: out_depth.clone() };
+            self.encoder.draw(&gpu_data.slice, self.pso.debug_view_by_material(material, view), &data);
+        }
+    }
+}
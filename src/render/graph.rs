@@ -0,0 +1,728 @@
+//! Ordering of the passes drawing a frame, see
+//! [`Renderer::graph`](../struct.Renderer.html#structfield.graph).
+//!
+//! The stages of the renderer are the built-in nodes of the graph, in a
+//! fixed order. User passes are inserted between them and declare the
+//! textures they read and write, so that the graph can reject a pass reading
+//! a texture no earlier pass writes, and let transient textures whose uses
+//! don't overlap share the same memory.
+//!
+//! Adding a decal pass that draws into the scene before the background and
+//! the post-processing, with the scene depth as input:
+//!
+//! ```rust,no_run
+//! # let mut window = three::Window::new("Example");
+//! # let code = "";
+//! use three::render::graph::{Resource, WgslPass};
+//!
+//! let decals: WgslPass = window.factory.graph_pass(code, &[Resource::Depth], Resource::SceneColor).unwrap();
+//! window.renderer.graph.add_pass_after("main", "decals", decals).unwrap();
+//! ```
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::fmt;
+
+use cgmath::{Matrix4, SquareMatrix};
+
+use super::reload::{ShaderWatcher, WgslSource};
+use super::{source, target, PipelineCreationError, Renderer, FULLSCREEN_SLICE, HDR_FORMAT};
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+quick_error! {
+    #[doc = "Error encountered when changing a `RenderGraph`."]
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum GraphError {
+        #[doc = "A pass with the same name is already in the graph."]
+        DuplicatePass(name: String) {
+            description("Duplicate pass")
+            display("Duplicate pass: {}", name)
+        }
+
+        #[doc = "A texture with the same name is already declared."]
+        DuplicateTexture(name: String) {
+            description("Duplicate texture")
+            display("Duplicate texture: {}", name)
+        }
+
+        #[doc = "No pass has the given name."]
+        UnknownPass(name: String) {
+            description("Unknown pass")
+            display("Unknown pass: {}", name)
+        }
+
+        #[doc = "A pass uses a texture that was not declared with `RenderGraph::add_texture`."]
+        UnknownTexture(pass: String, texture: String) {
+            description("Unknown texture")
+            display("Pass {} uses unknown texture {}", pass, texture)
+        }
+
+        #[doc = "A pass reads a texture that no earlier pass writes."]
+        Unwritten(pass: String, texture: String) {
+            description("Texture read before being written")
+            display("Pass {} reads texture {} before any pass writes it", pass, texture)
+        }
+
+        #[doc = "Built-in passes can't be removed."]
+        Builtin(name: String) {
+            description("Built-in pass")
+            display("Can't remove built-in pass {}", name)
+        }
+    }
+}
+
+/// Texture read or written by a pass.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Resource {
+    /// Color the scene is drawn into. This is an offscreen `Rgba16Float`
+    /// target when HDR or post-processing is enabled, the window otherwise.
+    SceneColor,
+    /// Depth and stencil of the window.
+    Depth,
+    /// Ambient occlusion in the red channel, white while SSAO is disabled.
+    Ssao,
+    /// Color of the window.
+    Output,
+    /// Texture declared with [`RenderGraph::add_texture`](struct.RenderGraph.html#method.add_texture).
+    Transient(String),
+}
+
+/// Stage of the renderer, in the order they are run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BuiltinPass {
+    /// Renders the shadow maps, `"shadows"`.
+    Shadows,
+    /// Computes the ambient occlusion, `"ssao"`.
+    Ssao,
+    /// Clears the scene color and the depth, `"clear"`.
+    Clear,
+    /// Draws the meshes of the scene, `"main"`.
+    Main,
+    /// Draws the scene background, `"background"`.
+    Background,
+    /// Applies post-processing and resolves the scene into the window, `"post"`.
+    Post,
+    /// Draws highlight outlines and debug shapes, `"overlays"`.
+    Overlays,
+    /// Draws UI text and debug quads, `"ui"`.
+    Ui,
+}
+
+impl BuiltinPass {
+    const ALL: [BuiltinPass; 8] = [BuiltinPass::Shadows, BuiltinPass::Ssao, BuiltinPass::Clear, BuiltinPass::Main, BuiltinPass::Background, BuiltinPass::Post, BuiltinPass::Overlays, BuiltinPass::Ui];
+
+    /// Name of the node in the graph.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            BuiltinPass::Shadows => "shadows",
+            BuiltinPass::Ssao => "ssao",
+            BuiltinPass::Clear => "clear",
+            BuiltinPass::Main => "main",
+            BuiltinPass::Background => "background",
+            BuiltinPass::Post => "post",
+            BuiltinPass::Overlays => "overlays",
+            BuiltinPass::Ui => "ui",
+        }
+    }
+
+    /// Textures written by the stage.
+    pub fn writes(&self) -> Vec<Resource> {
+        match *self {
+            BuiltinPass::Shadows => vec![],
+            BuiltinPass::Ssao => vec![Resource::Ssao, Resource::Depth],
+            BuiltinPass::Clear | BuiltinPass::Main => vec![Resource::SceneColor, Resource::Depth],
+            BuiltinPass::Background => vec![Resource::SceneColor],
+            BuiltinPass::Post => vec![Resource::Output],
+            BuiltinPass::Overlays => vec![Resource::Output, Resource::Depth],
+            BuiltinPass::Ui => vec![Resource::Output],
+        }
+    }
+}
+
+/// Format and size of a transient texture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureDesc {
+    /// Format of the texture, it is created with render attachment and
+    /// texture binding usages.
+    pub format: wgpu::TextureFormat,
+    /// Size relative to the window.
+    ///
+    /// Default: `1.0`.
+    pub scale: f32,
+}
+
+impl Default for TextureDesc {
+    fn default() -> Self {
+        TextureDesc { format: HDR_FORMAT, scale: 1.0 }
+    }
+}
+
+/// Access to a boxed pass as its concrete type, implemented for every `'static` type.
+pub trait AsAny: Any {
+    /// Returns `self` as `Any`, to be downcast.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Work inserted into a [`RenderGraph`](struct.RenderGraph.html).
+///
+/// `reads` and `writes` are queried once, when the pass is added.
+pub trait Pass: AsAny {
+    /// Textures sampled or loaded by `execute`.
+    fn reads(&self) -> Vec<Resource>;
+
+    /// Textures drawn into by `execute`.
+    fn writes(&self) -> Vec<Resource>;
+
+    /// Records the commands of the pass.
+    fn execute(&mut self, context: &mut PassContext);
+}
+
+/// Textures and camera of the window output being drawn.
+pub(crate) struct Frame {
+    pub scene_color: wgpu::TextureView,
+    pub scene_format: wgpu::TextureFormat,
+    pub depth: wgpu::TextureView,
+    /// Depth aspect of `depth`, for sampling.
+    pub depth_sampled: wgpu::TextureView,
+    pub output: wgpu::TextureView,
+    pub output_format: wgpu::TextureFormat,
+    pub ssao: wgpu::TextureView,
+    pub size: [u32; 2],
+    pub viewport: [u32; 4],
+    pub mx_vp: Matrix4<f32>,
+}
+
+/// Memory backing transient textures of the same description.
+struct Allocation {
+    view: wgpu::TextureView,
+    size: [u32; 2],
+}
+
+/// Everything a pass needs to record its commands.
+pub struct PassContext<'a> {
+    /// Device to create resources with.
+    pub device: &'a wgpu::Device,
+    /// Queue to upload data with, the writes happen before the commands of
+    /// the pass are executed.
+    pub queue: &'a wgpu::Queue,
+    /// Encoder to record the commands into.
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    frame: &'a Frame,
+    textures: &'a HashMap<String, TextureDesc>,
+    assignment: &'a HashMap<String, usize>,
+    allocations: &'a [Option<Allocation>],
+}
+
+impl<'a> PassContext<'a> {
+    /// Returns the view of `resource` to bind as a texture.
+    ///
+    /// The view of `Resource::Depth` only covers the depth aspect, draw into
+    /// it through [`attachment`](#method.attachment) instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `resource` is a transient texture not used by any pass.
+    pub fn view(&self, resource: &Resource) -> &wgpu::TextureView {
+        match *resource {
+            Resource::Depth => &self.frame.depth_sampled,
+            _ => self.attachment(resource),
+        }
+    }
+
+    /// Returns the view of `resource` to draw into.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `resource` is a transient texture not used by any pass.
+    pub fn attachment(&self, resource: &Resource) -> &wgpu::TextureView {
+        match *resource {
+            Resource::SceneColor => &self.frame.scene_color,
+            Resource::Depth => &self.frame.depth,
+            Resource::Ssao => &self.frame.ssao,
+            Resource::Output => &self.frame.output,
+            Resource::Transient(ref name) => &self.allocations[self.assignment[name]].as_ref().unwrap().view,
+        }
+    }
+
+    /// Returns the format of `resource`.
+    pub fn format(&self, resource: &Resource) -> wgpu::TextureFormat {
+        match *resource {
+            Resource::SceneColor => self.frame.scene_format,
+            Resource::Depth => target::DEPTH_FORMAT,
+            Resource::Ssao => HDR_FORMAT,
+            Resource::Output => self.frame.output_format,
+            Resource::Transient(ref name) => self.textures[name].format,
+        }
+    }
+
+    /// Returns the width and height of `resource`, in pixels.
+    pub fn size(&self, resource: &Resource) -> [u32; 2] {
+        match *resource {
+            Resource::Transient(ref name) => scaled_size(self.frame.size, self.textures[name].scale),
+            _ => self.frame.size,
+        }
+    }
+
+    /// Returns the pixel rectangle the camera draws into, `[x, y, width, height]`
+    /// from the top left corner of the window.
+    pub fn viewport(&self) -> [u32; 4] {
+        self.frame.viewport
+    }
+
+    /// Returns the world to clip space transform of the camera.
+    pub fn view_projection(&self) -> mint::ColumnMatrix4<f32> {
+        self.frame.mx_vp.into()
+    }
+}
+
+fn scaled_size(size: [u32; 2], scale: f32) -> [u32; 2] {
+    [((size[0] as f32 * scale).round() as u32).max(1), ((size[1] as f32 * scale).round() as u32).max(1)]
+}
+
+enum Node {
+    Builtin(BuiltinPass),
+    User { name: String, reads: Vec<Resource>, writes: Vec<Resource>, pass: Box<dyn Pass> },
+}
+
+impl Node {
+    fn name(&self) -> &str {
+        match *self {
+            Node::Builtin(builtin) => builtin.name(),
+            Node::User { ref name, .. } => name,
+        }
+    }
+
+    fn builtin(&self) -> Option<BuiltinPass> {
+        match *self {
+            Node::Builtin(builtin) => Some(builtin),
+            Node::User { .. } => None,
+        }
+    }
+}
+
+/// Transient textures assigned to shared allocations.
+#[derive(Default)]
+struct Plan {
+    slots: Vec<TextureDesc>,
+    assignment: HashMap<String, usize>,
+}
+
+/// Passes drawing a frame into the window, in order.
+///
+/// User passes run for every camera drawn into the window, i.e. by
+/// [`Renderer::render`](../struct.Renderer.html#method.render) and
+/// [`Renderer::render_viewport`](../struct.Renderer.html#method.render_viewport).
+/// They are not run when rendering into a `RenderTarget`.
+///
+/// The content of transient textures is undefined at the start of a frame,
+/// and after the last pass using them.
+pub struct RenderGraph {
+    nodes: Vec<Node>,
+    textures: HashMap<String, TextureDesc>,
+    plan: Plan,
+    allocations: Vec<Option<Allocation>>,
+}
+
+impl Default for RenderGraph {
+    fn default() -> Self {
+        RenderGraph { nodes: BuiltinPass::ALL.iter().map(|&builtin| Node::Builtin(builtin)).collect(), textures: HashMap::new(), plan: Plan::default(), allocations: Vec::new() }
+    }
+}
+
+impl fmt::Debug for RenderGraph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RenderGraph").field("passes", &self.passes().collect::<Vec<_>>()).field("textures", &self.textures).finish()
+    }
+}
+
+impl RenderGraph {
+    /// Names of the passes, in the order they are run.
+    pub fn passes(&self) -> impl Iterator<Item = &str> {
+        self.nodes.iter().map(Node::name)
+    }
+
+    /// Declares a transient texture, to be used as `Resource::Transient(name)`.
+    pub fn add_texture<S: Into<String>>(&mut self, name: S, desc: TextureDesc) -> Result<(), GraphError> {
+        let name = name.into();
+        if self.textures.contains_key(&name) {
+            return Err(GraphError::DuplicateTexture(name));
+        }
+        self.textures.insert(name, desc);
+        Ok(())
+    }
+
+    /// Inserts `pass` right before the pass called `anchor`.
+    pub fn add_pass_before<S: Into<String>, P: Pass + 'static>(&mut self, anchor: &str, name: S, pass: P) -> Result<(), GraphError> {
+        let index = self.position(anchor)?;
+        self.insert(index, name.into(), Box::new(pass))
+    }
+
+    /// Inserts `pass` right after the pass called `anchor`.
+    pub fn add_pass_after<S: Into<String>, P: Pass + 'static>(&mut self, anchor: &str, name: S, pass: P) -> Result<(), GraphError> {
+        let index = self.position(anchor)?;
+        self.insert(index + 1, name.into(), Box::new(pass))
+    }
+
+    /// Removes the user pass called `name`.
+    ///
+    /// Fails if a later pass reads a transient texture only this pass writes.
+    pub fn remove_pass(&mut self, name: &str) -> Result<Box<dyn Pass>, GraphError> {
+        let index = self.position(name)?;
+        if let Node::Builtin(builtin) = self.nodes[index] {
+            return Err(GraphError::Builtin(builtin.name().to_string()));
+        }
+        let node = self.nodes.remove(index);
+        match self.compile() {
+            Ok(plan) => self.set_plan(plan),
+            Err(err) => {
+                self.nodes.insert(index, node);
+                return Err(err);
+            }
+        }
+        match node {
+            Node::User { pass, .. } => Ok(pass),
+            Node::Builtin(_) => unreachable!(),
+        }
+    }
+
+    /// Returns the user pass called `name`, if it has type `P`.
+    pub fn pass_mut<P: Pass + 'static>(&mut self, name: &str) -> Option<&mut P> {
+        match self.nodes.iter_mut().find(|node| node.name() == name) {
+            Some(&mut Node::User { ref mut pass, .. }) => (**pass).as_any_mut().downcast_mut(),
+            _ => None,
+        }
+    }
+
+    fn position(&self, name: &str) -> Result<usize, GraphError> {
+        self.nodes.iter().position(|node| node.name() == name).ok_or_else(|| GraphError::UnknownPass(name.to_string()))
+    }
+
+    fn insert(&mut self, index: usize, name: String, pass: Box<dyn Pass>) -> Result<(), GraphError> {
+        if self.position(&name).is_ok() {
+            return Err(GraphError::DuplicatePass(name));
+        }
+        self.nodes.insert(index, Node::User { reads: pass.reads(), writes: pass.writes(), name, pass });
+        match self.compile() {
+            Ok(plan) => {
+                self.set_plan(plan);
+                Ok(())
+            }
+            Err(err) => {
+                self.nodes.remove(index);
+                Err(err)
+            }
+        }
+    }
+
+    fn set_plan(&mut self, plan: Plan) {
+        self.plan = plan;
+        self.allocations.clear();
+    }
+
+    /// Checks the uses of transient textures and assigns them to allocations.
+    fn compile(&self) -> Result<Plan, GraphError> {
+        // first and last node using each transient, in order of first use
+        let mut lifetimes: Vec<(&str, usize, usize)> = Vec::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let (name, reads, writes) = match *node {
+                Node::User { ref name, ref reads, ref writes, .. } => (name, reads, writes),
+                Node::Builtin(_) => continue,
+            };
+            for (resource, is_write) in reads.iter().map(|r| (r, false)).chain(writes.iter().map(|w| (w, true))) {
+                let texture = match *resource {
+                    Resource::Transient(ref texture) => texture,
+                    _ => continue,
+                };
+                if !self.textures.contains_key(texture) {
+                    return Err(GraphError::UnknownTexture(name.clone(), texture.clone()));
+                }
+                match lifetimes.iter_mut().find(|&&mut (t, _, _)| t == texture.as_str()) {
+                    Some(lifetime) => lifetime.2 = index,
+                    None if is_write => lifetimes.push((texture.as_str(), index, index)),
+                    None => return Err(GraphError::Unwritten(name.clone(), texture.clone())),
+                }
+            }
+        }
+
+        let mut plan = Plan::default();
+        // last node using each slot
+        let mut busy_until = Vec::new();
+        for (texture, first, last) in lifetimes {
+            let desc = self.textures[texture];
+            let free = (0 .. plan.slots.len()).find(|&slot| plan.slots[slot] == desc && busy_until[slot] < first);
+            let slot = match free {
+                Some(slot) => slot,
+                None => {
+                    plan.slots.push(desc);
+                    busy_until.push(0);
+                    plan.slots.len() - 1
+                }
+            };
+            busy_until[slot] = last;
+            plan.assignment.insert(texture.to_string(), slot);
+        }
+        Ok(plan)
+    }
+
+    /// (Re)creates the allocations that don't match the window size.
+    fn allocate(&mut self, device: &wgpu::Device, window_size: [u32; 2]) {
+        self.allocations.resize_with(self.plan.slots.len(), || None);
+        for (allocation, desc) in self.allocations.iter_mut().zip(&self.plan.slots) {
+            let size = scaled_size(window_size, desc.scale);
+            match *allocation {
+                Some(ref existing) if existing.size == size => continue,
+                _ => {}
+            }
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("render graph transient"),
+                size: wgpu::Extent3d { width: size[0], height: size[1], depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: desc.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            *allocation = Some(Allocation { view: texture.create_view(&wgpu::TextureViewDescriptor::default()), size });
+        }
+    }
+
+    /// Range of the user nodes run after `stage`.
+    fn after(&self, stage: BuiltinPass) -> Range<usize> {
+        let index = self.nodes.iter().position(|node| node.builtin() == Some(stage)).unwrap();
+        // the shadow maps are not exposed to user passes,
+        // so the passes before them can run after them
+        let start = if stage == BuiltinPass::Shadows { 0 } else { index + 1 };
+        let end = self.nodes[index + 1 ..].iter().position(|node| node.builtin().is_some()).map_or(self.nodes.len(), |offset| index + 1 + offset);
+        start .. end
+    }
+}
+
+/// Parameters of a `WgslPass`, has to match `PassParams` in `graph_pass.wgsl`.
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct PassParams {
+    user: [[f32; 4]; 4],
+    mx_vp: [[f32; 4]; 4],
+    mx_inv_vp: [[f32; 4]; 4],
+    resolution: [f32; 4],
+}
+
+/// Fullscreen pass written in WGSL, drawing into a single color texture.
+///
+/// The source is appended to `data/shaders/graph_pass.wgsl`, which provides
+/// a vertex stage covering the output along with these bindings:
+///
+/// * `u_Pass.user`: the values of [`params`](#structfield.params),
+/// * `u_Pass.mx_vp` and `u_Pass.mx_inv_vp`: the camera transform and its inverse,
+/// * `u_Pass.resolution`: width, height and their inverses of the output,
+/// * `s_Linear`: a linear clamping sampler.
+///
+/// The inputs have to be declared by the shader in group 1, in order, as
+/// `texture_depth_2d` for `Resource::Depth` and `texture_2d<f32>` otherwise.
+/// The fragment stage has to be named `fs_main`:
+///
+/// ```wgsl
+/// @group(1) @binding(0) var t_Depth: texture_depth_2d;
+///
+/// @fragment
+/// fn fs_main(in: PassOutput) -> @location(0) vec4<f32> {
+///     let depth = textureLoad(t_Depth, vec2<i32>(in.position.xy), 0);
+///     return vec4<f32>(0.0, 0.0, 0.0, select(0.0, 0.2, depth < 1.0));
+/// }
+/// ```
+///
+/// The output is blended with premultiplied alpha. Passes drawing into the
+/// scene or the window are restricted to the viewport of the camera.
+///
+/// See [`Factory::graph_pass`](../../struct.Factory.html#method.graph_pass).
+pub struct WgslPass {
+    source: WgslSource,
+    module: wgpu::ShaderModule,
+    params_layout: wgpu::BindGroupLayout,
+    params_buf: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    /// Input bindings and pipeline for every combination of output and input formats.
+    pipelines: HashMap<Vec<wgpu::TextureFormat>, (wgpu::BindGroupLayout, wgpu::RenderPipeline)>,
+    inputs: Vec<Resource>,
+    output: Resource,
+    /// Values passed to the shader as `u_Pass.user`.
+    pub params: [[f32; 4]; 4],
+}
+
+impl fmt::Debug for WgslPass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WgslPass").field("inputs", &self.inputs).field("output", &self.output).field("params", &self.params).finish()
+    }
+}
+
+impl WgslPass {
+    /// Fails if `output` is `Resource::Depth`, or one of the `inputs`. Takes
+    /// the included chunks from `directory` when it has them.
+    pub(crate) fn new(device: &wgpu::Device, code: &str, inputs: &[Resource], output: Resource, directory: Option<&Path>) -> Result<Self, PipelineCreationError> {
+        if output == Resource::Depth {
+            return Err(PipelineCreationError::DepthOutput);
+        }
+        if inputs.contains(&output) {
+            return Err(PipelineCreationError::ReadsOutput(output));
+        }
+        let mut source = WgslSource::new(format!("#include <graph_pass>\n{}", code));
+        let module = Self::create_module(device, source.process(directory)?)?;
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("graph pass params"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry { binding: 0, visibility: wgpu::ShaderStages::VERTEX_FRAGMENT, ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None }, count: None },
+                wgpu::BindGroupLayoutEntry { binding: 1, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering), count: None },
+            ],
+        });
+        Ok(WgslPass {
+            source,
+            module,
+            params_layout,
+            params_buf: device.create_buffer(&wgpu::BufferDescriptor { label: Some("graph pass params"), size: std::mem::size_of::<PassParams>() as u64, usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST, mapped_at_creation: false }),
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor { label: Some("graph pass"), mag_filter: wgpu::FilterMode::Linear, min_filter: wgpu::FilterMode::Linear, ..Default::default() }),
+            pipelines: HashMap::new(),
+            inputs: inputs.to_vec(),
+            output,
+            params: [[0.0; 4]; 4],
+        })
+    }
+
+    /// Compiles `code`, the code of the pass with its chunks included.
+    fn create_module(device: &wgpu::Device, code: String) -> Result<wgpu::ShaderModule, PipelineCreationError> {
+        source::check_wgsl(&code)?;
+        Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("graph pass"), source: wgpu::ShaderSource::Wgsl(code.into()) }))
+    }
+
+    /// Compiles the pass again if the chunks it includes changed, keeping
+    /// the previous pipelines if that fails.
+    fn reload(&mut self, device: &wgpu::Device, watcher: &ShaderWatcher) {
+        if !watcher.is_stale(&self.source) {
+            return;
+        }
+        match watcher.process(&mut self.source).and_then(|code| Self::create_module(device, code)) {
+            Ok(module) => {
+                self.module = module;
+                self.pipelines.clear();
+            }
+            Err(err) => error!("Graph pass reload failed: {}", err),
+        }
+    }
+
+    fn create_pipeline(&self, device: &wgpu::Device, formats: &[wgpu::TextureFormat]) -> (wgpu::BindGroupLayout, wgpu::RenderPipeline) {
+        let entries: Vec<_> = formats[1 ..]
+            .iter()
+            .enumerate()
+            .map(|(binding, format)| {
+                let aspect = if format.has_depth_aspect() { wgpu::TextureAspect::DepthOnly } else { wgpu::TextureAspect::All };
+                let sample_type = format.sample_type(Some(aspect), Some(device.features())).unwrap_or(wgpu::TextureSampleType::Float { filterable: false });
+                wgpu::BindGroupLayoutEntry { binding: binding as u32, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Texture { sample_type, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false }, count: None }
+            })
+            .collect();
+        let input_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label: Some("graph pass inputs"), entries: &entries });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { label: Some("graph pass"), bind_group_layouts: &[&self.params_layout, &input_layout], push_constant_ranges: &[] });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("graph pass"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState { module: &self.module, entry_point: Some("vs_fullscreen"), compilation_options: Default::default(), buffers: &[] },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState { module: &self.module, entry_point: Some("fs_main"), compilation_options: Default::default(), targets: &[Some(wgpu::ColorTargetState { format: formats[0], blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING), write_mask: wgpu::ColorWrites::ALL })] }),
+            multiview: None,
+            cache: None,
+        });
+        (input_layout, pipeline)
+    }
+}
+
+impl Pass for WgslPass {
+    fn reads(&self) -> Vec<Resource> {
+        self.inputs.clone()
+    }
+
+    fn writes(&self) -> Vec<Resource> {
+        vec![self.output.clone()]
+    }
+
+    fn execute(&mut self, context: &mut PassContext) {
+        let formats: Vec<_> = Some(&self.output).into_iter().chain(&self.inputs).map(|resource| context.format(resource)).collect();
+        if !self.pipelines.contains_key(&formats) {
+            let pipeline = self.create_pipeline(context.device, &formats);
+            self.pipelines.insert(formats.clone(), pipeline);
+        }
+        let (ref input_layout, ref pipeline) = self.pipelines[&formats];
+
+        let size = context.size(&self.output);
+        let mx_vp = context.frame.mx_vp;
+        let mx_inv_vp = mx_vp.invert().unwrap_or_else(Matrix4::identity);
+        let params = PassParams { user: self.params, mx_vp: mx_vp.into(), mx_inv_vp: mx_inv_vp.into(), resolution: [size[0] as f32, size[1] as f32, 1.0 / size[0] as f32, 1.0 / size[1] as f32] };
+        context.queue.write_buffer(&self.params_buf, 0, bytemuck::bytes_of(&params));
+
+        let params_group = context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("graph pass params"),
+            layout: &self.params_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: self.params_buf.as_entire_binding() }, wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&self.sampler) }],
+        });
+        let entries: Vec<_> = self.inputs.iter().enumerate().map(|(binding, input)| wgpu::BindGroupEntry { binding: binding as u32, resource: wgpu::BindingResource::TextureView(context.view(input)) }).collect();
+        let input_group = context.device.create_bind_group(&wgpu::BindGroupDescriptor { label: Some("graph pass inputs"), layout: input_layout, entries: &entries });
+
+        let view = context.attachment(&self.output).clone();
+        let viewport = match self.output {
+            Resource::Transient(_) => [0, 0, size[0], size[1]],
+            _ => context.viewport(),
+        };
+        let mut pass = context.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("graph pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment { view: &view, depth_slice: None, resolve_target: None, ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store } })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_viewport(viewport[0] as f32, viewport[1] as f32, viewport[2] as f32, viewport[3] as f32, 0.0, 1.0);
+        pass.set_scissor_rect(viewport[0], viewport[1], viewport[2], viewport[3]);
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &params_group, &[]);
+        pass.set_bind_group(1, &input_group, &[]);
+        pass.draw(FULLSCREEN_SLICE, 0 .. 1);
+    }
+}
+
+impl Renderer {
+    /// Runs the user passes of `graph` that follow `stage`, for the window
+    /// output described by `frame`.
+    pub(super) fn run_passes(&mut self, stage: BuiltinPass, frame: Option<&Frame>) {
+        let frame = match frame {
+            Some(frame) => frame,
+            None => return,
+        };
+        let range = self.graph.after(stage);
+        if range.is_empty() {
+            return;
+        }
+        // the built-in passes recorded so far have to be submitted first
+        self.encoder.flush(&mut self.device);
+        self.graph.allocate(&self.device, frame.size);
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("render graph") });
+        let RenderGraph { ref mut nodes, ref textures, ref plan, ref allocations } = self.graph;
+        for node in &mut nodes[range] {
+            if let Node::User { pass, .. } = node {
+                if let (Some(watcher), Some(pass)) = (self.shader_watcher.as_ref(), (**pass).as_any_mut().downcast_mut::<WgslPass>()) {
+                    pass.reload(&self.device, watcher);
+                }
+                let mut context = PassContext { device: &self.device, queue: &self.queue, encoder: &mut encoder, frame, textures, assignment: &plan.assignment, allocations };
+                pass.execute(&mut context);
+            }
+        }
+        self.queue.submit(Some(encoder.finish()));
+    }
+}
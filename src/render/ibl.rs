@@ -0,0 +1,247 @@
+//! Image-based lighting.
+//!
+//! An [`Environment`] is precomputed on the GPU from a cube map, following the
+//! split-sum approximation: a diffuse irradiance map, a specular map whose mip
+//! levels are prefiltered for increasing roughness, and a lookup table of the
+//! BRDF scale and bias to apply to the specular reflectance.
+//!
+//! The same compute setup converts equirectangular panoramas into cube maps.
+//!
+//! [`Environment`]: struct.Environment.html
+
+use std::borrow::Borrow;
+use std::str;
+
+use wgpu::util::DeviceExt;
+
+use crate::data;
+use crate::texture::{CubeMap, Texture};
+
+/// Size of a face of the irradiance map.
+const IRRADIANCE_SIZE: u32 = 32;
+/// Size of a face of the first mip level of the specular map.
+const SPECULAR_SIZE: u32 = 128;
+/// Number of mip levels of the specular map, from roughness `0` to `1`.
+const SPECULAR_MIPS: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 256;
+const IRRADIANCE_SAMPLES: u32 = 1024;
+const SPECULAR_SAMPLES: u32 = 512;
+const BRDF_SAMPLES: u32 = 1024;
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Lighting from a cube map surrounding the scene, used by `Material::Pbr`.
+///
+/// See [`Factory::environment`](../../struct.Factory.html#method.environment)
+/// and [`Scene::environment`](../../scene/struct.Scene.html#structfield.environment).
+#[derive(Clone, Debug)]
+pub struct Environment {
+    cube_map: CubeMap<[f32; 4]>,
+    pub(crate) irradiance: CubeMap<[f32; 4]>,
+    pub(crate) specular: CubeMap<[f32; 4]>,
+    pub(crate) brdf_lut: Texture<[f32; 4]>,
+    /// Scale applied to the light received from the environment.
+    ///
+    /// Default: `1.0`.
+    pub intensity: f32,
+}
+
+impl Environment {
+    /// Returns the cube map this environment was generated from, to be used
+    /// as [`Background::Skybox`](../../scene/enum.Background.html#variant.Skybox).
+    pub fn cube_map(&self) -> CubeMap<[f32; 4]> {
+        self.cube_map.clone()
+    }
+
+    /// Parameters of the environment, has to match `u_Environment` in `globals.glsl`.
+    pub(crate) fn params(&self) -> [f32; 4] {
+        [self.intensity, (SPECULAR_MIPS - 1) as f32, 1.0, 0.0]
+    }
+
+    /// Black environment bound when the scene has none.
+    pub(crate) fn black(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        let cube = create_cube(device, 1, 1, "default environment");
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo { texture: &cube, mip_level: 0, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
+            &[0; 6 * 8],
+            wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(8), rows_per_image: Some(1) },
+            wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 6 },
+        );
+        let view = cube.create_view(&wgpu::TextureViewDescriptor { dimension: Some(wgpu::TextureViewDimension::Cube), ..Default::default() });
+        let cube_map = CubeMap::new(view, sampler.clone());
+        let lut = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("default brdf lut"),
+            size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let brdf_lut = Texture::new(lut.create_view(&wgpu::TextureViewDescriptor::default()), sampler, [1, 1]);
+        Environment { cube_map: cube_map.clone(), irradiance: cube_map.clone(), specular: cube_map, brdf_lut, intensity: 0.0 }
+    }
+}
+
+fn create_cube(device: &wgpu::Device, size: u32, mip_level_count: u32, label: &str) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d { width: size, height: size, depth_or_array_layers: 6 },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+
+/// Has to match `IblParams` in `ibl.wgsl`.
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct IblParams {
+    roughness: f32,
+    size: u32,
+    sample_count: u32,
+    _padding0: u32,
+}
+
+fn create_pipeline(device: &wgpu::Device, name: &str) -> wgpu::ComputePipeline {
+    let load = |path: &str| {
+        let file = data::FILES.get(path).unwrap();
+        str::from_utf8(file.borrow()).unwrap().to_owned()
+    };
+    let code = format!("{}\n{}", load("data/shaders/ibl.wgsl"), load(&format!("data/shaders/ibl_{}.wgsl", name)));
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some(name), source: wgpu::ShaderSource::Wgsl(code.into()) });
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor { label: Some(name), layout: None, module: &module, entry_point: Some("cs_main"), compilation_options: Default::default(), cache: None })
+}
+
+/// Compute pipelines generating an `Environment`.
+pub(crate) struct Generator {
+    equirect: wgpu::ComputePipeline,
+    irradiance: wgpu::ComputePipeline,
+    prefilter: wgpu::ComputePipeline,
+    /// The BRDF lookup table doesn't depend on the environment and is
+    /// shared by all of them.
+    brdf_lut: Texture<[f32; 4]>,
+    sampler: wgpu::Sampler,
+}
+
+impl Generator {
+    pub(crate) fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("environment"),
+            // wraps around the seam of equirectangular panoramas
+            address_mode_u: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let lut = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("brdf lut"),
+            size: wgpu::Extent3d { width: BRDF_LUT_SIZE, height: BRDF_LUT_SIZE, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        });
+        let lut_view = lut.create_view(&wgpu::TextureViewDescriptor::default());
+        let brdf = create_pipeline(device, "brdf");
+        let params = IblParams { roughness: 0.0, size: BRDF_LUT_SIZE, sample_count: BRDF_SAMPLES, _padding0: 0 };
+        let params_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some("brdf lut"), contents: bytemuck::bytes_of(&params), usage: wgpu::BufferUsages::UNIFORM });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("brdf lut"),
+            layout: &brdf.get_bind_group_layout(0),
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&lut_view) }, wgpu::BindGroupEntry { binding: 1, resource: params_buf.as_entire_binding() }],
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("brdf lut") });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("brdf lut"), timestamp_writes: None });
+            pass.set_pipeline(&brdf);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let groups = BRDF_LUT_SIZE.div_ceil(WORKGROUP_SIZE);
+            pass.dispatch_workgroups(groups, groups, 1);
+        }
+        queue.submit(Some(encoder.finish()));
+
+        let lut_sampler = device.create_sampler(&wgpu::SamplerDescriptor { label: Some("brdf lut"), mag_filter: wgpu::FilterMode::Linear, min_filter: wgpu::FilterMode::Linear, ..Default::default() });
+        Generator {
+            equirect: create_pipeline(device, "equirect"),
+            irradiance: create_pipeline(device, "irradiance"),
+            prefilter: create_pipeline(device, "prefilter"),
+            brdf_lut: Texture::new(lut_view, lut_sampler, [BRDF_LUT_SIZE, BRDF_LUT_SIZE]),
+            sampler,
+        }
+    }
+
+    /// Resamples an equirectangular panorama into a cube map with faces of `size` texels.
+    pub(crate) fn equirect_to_cube(&self, device: &wgpu::Device, queue: &wgpu::Queue, texture: &Texture<[f32; 4]>, size: u32) -> CubeMap<[f32; 4]> {
+        let cube = create_cube(device, size, 1, "equirectangular cube map");
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("equirectangular cube map") });
+        let (input, _) = texture.to_param();
+        let params = IblParams { roughness: 0.0, size, sample_count: 1, _padding0: 0 };
+        self.dispatch(device, &mut encoder, &self.equirect, &input, &cube, 0, params);
+        queue.submit(Some(encoder.finish()));
+        CubeMap::new(cube_view(&cube), self.sampler.clone())
+    }
+
+    /// Convolves `cube_map` into the irradiance and specular maps of a new `Environment`.
+    pub(crate) fn generate(&self, device: &wgpu::Device, queue: &wgpu::Queue, cube_map: &CubeMap<[f32; 4]>) -> Environment {
+        let (source, _) = cube_map.to_param();
+        let irradiance = create_cube(device, IRRADIANCE_SIZE, 1, "irradiance");
+        let specular = create_cube(device, SPECULAR_SIZE, SPECULAR_MIPS, "specular");
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("environment") });
+        // (pipeline, target, mip level, parameters) of every dispatch
+        let mut jobs = vec![(&self.irradiance, &irradiance, 0, IblParams { roughness: 0.0, size: IRRADIANCE_SIZE, sample_count: IRRADIANCE_SAMPLES, _padding0: 0 })];
+        for mip in 0 .. SPECULAR_MIPS {
+            let roughness = mip as f32 / (SPECULAR_MIPS - 1) as f32;
+            // a perfect mirror doesn't need more than one sample
+            let sample_count = if mip == 0 { 1 } else { SPECULAR_SAMPLES };
+            jobs.push((&self.prefilter, &specular, mip, IblParams { roughness, size: SPECULAR_SIZE >> mip, sample_count, _padding0: 0 }));
+        }
+        for (pipeline, target, mip, params) in jobs {
+            self.dispatch(device, &mut encoder, pipeline, &source, target, mip, params);
+        }
+        queue.submit(Some(encoder.finish()));
+
+        Environment {
+            cube_map: cube_map.clone(),
+            irradiance: CubeMap::new(cube_view(&irradiance), self.sampler.clone()),
+            specular: CubeMap::new(cube_view(&specular), self.sampler.clone()),
+            brdf_lut: self.brdf_lut.clone(),
+            intensity: 1.0,
+        }
+    }
+
+    /// Records a dispatch of `pipeline` writing all faces of a mip level of `target`.
+    fn dispatch(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, pipeline: &wgpu::ComputePipeline, input: &wgpu::TextureView, target: &wgpu::Texture, mip: u32, params: IblParams) {
+        let params_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some("environment"), contents: bytemuck::bytes_of(&params), usage: wgpu::BufferUsages::UNIFORM });
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor { dimension: Some(wgpu::TextureViewDimension::D2Array), base_mip_level: mip, mip_level_count: Some(1), ..Default::default() });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("environment"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(input) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&target_view) },
+                wgpu::BindGroupEntry { binding: 3, resource: params_buf.as_entire_binding() },
+            ],
+        });
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("environment"), timestamp_writes: None });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        let groups = params.size.div_ceil(WORKGROUP_SIZE);
+        pass.dispatch_workgroups(groups, groups, 6);
+    }
+}
+
+fn cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor { dimension: Some(wgpu::TextureViewDimension::Cube), ..Default::default() })
+}
@@ -15,12 +15,29 @@ use cgmath::{Matrix as Matrix_, Matrix4, SquareMatrix, Transform as Transform_,
 
 use wgpu::*;
 
+mod capture;
+mod cluster;
+mod custom;
+mod debug;
+mod debug_view;
+pub mod graph;
+mod outline;
+mod pick;
+pub mod ibl;
+pub mod post;
 mod pso_data;
+mod reload;
 pub mod source;
+pub mod ssao;
+mod stats;
+mod target;
+mod variants;
+mod viewport;
 
 use crate::color;
 
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::{io, str};
 
 // pub use self::back::CommandBuffer as BackendCommandBuffer;
@@ -29,20 +46,39 @@ use std::{io, str};
 // pub use self::source::Source;
 // use glutin::{ContextCurrentState, ContextWrapper, NotCurrent, PossiblyCurrent, Window};
 
-use pso_data::{PbrFlags, PsoData};
+pub use capture::{CaptureError, FrameCapture};
+pub use custom::{CustomShader, ShaderState};
+pub use debug::{DebugDraw, DebugStyle};
+pub use debug_view::DebugView;
+use graph::BuiltinPass;
+pub use stats::RenderStats;
+use cluster::{ClusterGrid, LocalLight, CLUSTER_DIMS};
+pub use pick::Pick;
+use post::PostEffect;
+pub use target::{RenderTarget, TargetFormat};
+pub use viewport::Viewport;
+
+pub(crate) use reload::ShaderDirectory;
+use pso_data::PsoData;
+use source::Features;
+use variants::Variants;
 use crate::{
-    camera::Camera,
+    camera::{Camera, Frustum, Projection},
     factory::Factory,
-    hub::{SubLight, SubNode},
+    hub::{Hub, SubLight, SubNode},
     light::{ShadowMap, ShadowProjection},
-    material::Material,
+    material::{ColorMask, Material, RenderState, Side},
+    raycast::Collider,
     scene::{Background, Scene},
-    text::Font,
+    skeleton::Skeleton,
+    text::{Font, Text},
     texture::Texture,
 };
 
 // /// The format of the back buffer color requested from the windowing system.
 // pub type ColorFormat = gfx::format::Rgba8;
+// /// The format of the offscreen targets, see `HDR_FORMAT`.
+// pub type HdrFormat = (gfx::format::R16_G16_B16_A16, gfx::format::Float);
 // /// The format of the depth stencil buffer requested from the windowing system.
 // pub type DepthFormat = gfx::format::DepthStencil;
 // /// The format of the shadow buffer.
@@ -50,11 +86,100 @@ use crate::{
 // /// The concrete type of a basic pipeline.
 // pub type BasicPipelineState = gfx::PipelineState<back::Resources, basic_pipe::Meta>;
 
-pub(crate) const MAX_LIGHTS: usize = 4;
+/// Maximum number of shadow-casting lights per frame, one per bound shadow
+/// map. Has to match `MAX_SHADOWS` in the shaders.
+///
+/// The number of lights itself is not limited, see `render::cluster`.
+pub(crate) const MAX_SHADOWS: usize = 2;
 pub(crate) const MAX_TARGETS: usize = 8;
+/// Flag of the picking, outline and debug shaders telling that the morph
+/// targets apply, has to match `DISPLACEMENT_BUFFER` in their vertex stages.
+pub(crate) const DISPLACEMENT_BUFFER: i32 = 1 << 5;
+/// Format of the intermediate color targets used for HDR and post-processing.
+pub(crate) const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
 pub(crate) const VECS_PER_BONE: usize = 3;
 
+/// Vertices of the single triangle covering the whole target, generated from
+/// the vertex index by `fullscreen.glsl` and `graph_pass.wgsl`.
+pub(crate) const FULLSCREEN_SLICE: std::ops::Range<u32> = 0 .. 3;
+/// Vertices of the triangle strip drawing a `QuadParams::rect`.
+pub(crate) const QUAD_SLICE: std::ops::Range<u32> = 0 .. 4;
+
 // const STENCIL_SIDE: gfx::state::StencilSide = gfx::state::StencilSide { fun: gfx::state::Comparison::Always, mask_read: 0, mask_write: 0, op_fail: gfx::state::StencilOp::Keep, op_depth_fail: gfx::state::StencilOp::Keep, op_pass: gfx::state::StencilOp::Keep };
+/// Writes the reference value everywhere a highlighted node covers, see `render::outline`.
+// const STENCIL_MARK_SIDE: gfx::state::StencilSide = gfx::state::StencilSide { fun: gfx::state::Comparison::Always, mask_read: 0, mask_write: 0xFF, op_fail: gfx::state::StencilOp::Replace, op_depth_fail: gfx::state::StencilOp::Replace, op_pass: gfx::state::StencilOp::Replace };
+/// Passes outside of the nodes marked by `STENCIL_MARK_SIDE`.
+// const STENCIL_OUTLINE_SIDE: gfx::state::StencilSide = gfx::state::StencilSide { fun: gfx::state::Comparison::NotEqual, mask_read: 0xFF, mask_write: 0, op_fail: gfx::state::StencilOp::Keep, op_depth_fail: gfx::state::StencilOp::Keep, op_pass: gfx::state::StencilOp::Keep };
+
+/// Primitive assembly of a material pipeline drawing `topology` with `state`,
+/// culling the faces that are not drawn.
+//TODO: Remove dead_code lint once the material pipelines are created with wgpu
+#[allow(dead_code)]
+fn material_primitive(topology: wgpu::PrimitiveTopology, polygon_mode: wgpu::PolygonMode, state: &RenderState) -> wgpu::PrimitiveState {
+    let cull_mode = match state.side {
+        Side::Front => Some(wgpu::Face::Back),
+        Side::Back => Some(wgpu::Face::Front),
+        Side::Double => None,
+    };
+    wgpu::PrimitiveState { topology, front_face: wgpu::FrontFace::Ccw, cull_mode, polygon_mode, ..Default::default() }
+}
+
+/// Depth test, write and bias of a material pipeline drawing with `state`.
+//TODO: Remove dead_code lint once the material pipelines are created with wgpu
+#[allow(dead_code)]
+fn material_depth_stencil(state: &RenderState) -> wgpu::DepthStencilState {
+    let depth_compare = if state.depth_test { wgpu::CompareFunction::LessEqual } else { wgpu::CompareFunction::Always };
+    let bias = wgpu::DepthBiasState { constant: state.depth_bias, slope_scale: state.slope_scale_bias, clamp: 0.0 };
+    wgpu::DepthStencilState { format: target::DEPTH_FORMAT, depth_write_enabled: state.depth_write, depth_compare, stencil: wgpu::StencilState::default(), bias }
+}
+
+/// Color channels written by a material pipeline drawing with `state`.
+//TODO: Remove dead_code lint once the material pipelines are created with wgpu
+#[allow(dead_code)]
+fn material_color_writes(state: &RenderState) -> wgpu::ColorWrites {
+    let mut writes = wgpu::ColorWrites::empty();
+    writes.set(wgpu::ColorWrites::RED, state.color_mask.contains(ColorMask::RED));
+    writes.set(wgpu::ColorWrites::GREEN, state.color_mask.contains(ColorMask::GREEN));
+    writes.set(wgpu::ColorWrites::BLUE, state.color_mask.contains(ColorMask::BLUE));
+    writes.set(wgpu::ColorWrites::ALPHA, state.color_mask.contains(ColorMask::ALPHA));
+    writes
+}
+
+// // Culling of a material pipeline drawing with `state`, `rast` giving the fill mode.
+// // The depth bias is set by `material_depth_stencil`, as `gfx::state::Offset`
+// // can't express a fractional slope scale.
+// fn material_rasterizer(rast: gfx::state::Rasterizer, state: &RenderState) -> gfx::state::Rasterizer {
+//     let cull_face = match state.side {
+//         Side::Front => gfx::state::CullFace::Back,
+//         Side::Back => gfx::state::CullFace::Front,
+//         Side::Double => gfx::state::CullFace::Nothing,
+//     };
+//     gfx::state::Rasterizer { cull_face, ..rast }
+// }
+//
+// // Depth test and write of a material pipeline drawing with `state`.
+// fn material_depth(state: &RenderState) -> gfx::state::Depth {
+//     let fun = if state.depth_test { gfx::state::Comparison::LessEqual } else { gfx::state::Comparison::Always };
+//     gfx::state::Depth { fun, write: state.depth_write }
+// }
+//
+// // Color channels written by a material pipeline drawing with `state`.
+// fn material_color_mask(state: &RenderState) -> gfx::state::ColorMask {
+//     let mut mask = gfx::state::ColorMask::empty();
+//     mask.set(gfx::state::ColorMask::RED, state.color_mask.contains(ColorMask::RED));
+//     mask.set(gfx::state::ColorMask::GREEN, state.color_mask.contains(ColorMask::GREEN));
+//     mask.set(gfx::state::ColorMask::BLUE, state.color_mask.contains(ColorMask::BLUE));
+//     mask.set(gfx::state::ColorMask::ALPHA, state.color_mask.contains(ColorMask::ALPHA));
+//     mask
+// }
+//
+// fn basic_init(state: &RenderState, blend: gfx::state::Blend) -> basic_pipe::Init<'static> {
+//     basic_pipe::Init { out_color: ("Target0", material_color_mask(state), blend), out_depth: (material_depth(state), gfx::state::Stencil { front: STENCIL_SIDE, back: STENCIL_SIDE }), ..basic_pipe::new() }
+// }
+//
+// fn pbr_init(state: &RenderState) -> pbr_pipe::Init<'static> {
+//     pbr_pipe::Init { color_target: ("Target0", material_color_mask(state), gfx::preset::blend::REPLACE), depth_target: material_depth(state), ..pbr_pipe::new() }
+// }
 
 #[cfg_attr(rustfmt, rustfmt_skip)]
 quick_error! {
@@ -62,11 +187,9 @@ quick_error! {
     #[derive(Debug)]
     pub enum PipelineCreationError {
         #[doc = "WGSL compiler/linker error."]
-        Compilation(err: ()) {
-            from()
+        Compilation(log: String) {
             description("WGSL program compilation error")
-            display("WGSL program compilation error")
-            cause(err)
+            display("WGSL program compilation error: {}", log)
         }
 
         #[doc = "Pipeline state error."]
@@ -84,6 +207,18 @@ quick_error! {
             display("I/O error")
             cause(err)
         }
+
+        #[doc = "A render graph pass would draw into `Resource::Depth`."]
+        DepthOutput {
+            description("Graph pass drawing into the depth")
+            display("Graph pass can only draw into color textures")
+        }
+
+        #[doc = "A render graph pass would read the texture it draws into."]
+        ReadsOutput(resource: graph::Resource) {
+            description("Graph pass reading its output")
+            display("Graph pass can't read its output {:?}", resource)
+        }
     }
 }
 
@@ -121,6 +256,11 @@ pub const ZEROED_DISPLACEMENT_CONTRIBUTION: [DisplacementContribution; MAX_TARGE
 //         uv_range: [f32; 4] = "i_UvRange",
 //     }
 //
+//     vertex DebugVertex {
+//         pos: [f32; 3] = "a_Position",
+//         color: [f32; 4] = "a_Color",
+//     }
+//
 //     constant LightParam {
 //         projection: [[f32; 4]; 4] = "projection",
 //         pos: [f32; 4] = "pos",
@@ -130,6 +270,7 @@ pub const ZEROED_DISPLACEMENT_CONTRIBUTION: [DisplacementContribution; MAX_TARGE
 //         color_back: [f32; 4] = "color_back",
 //         intensity: [f32; 4] = "intensity",
 //         shadow_params: [i32; 4] = "shadow_params",
+//         shadow_bias: [f32; 4] = "shadow_bias",
 //     }
 //
 //     constant Globals {
@@ -137,16 +278,29 @@ pub const ZEROED_DISPLACEMENT_CONTRIBUTION: [DisplacementContribution; MAX_TARGE
 //         mx_inv_proj: [[f32; 4]; 4] = "u_InverseProj",
 //         mx_view: [[f32; 4]; 4] = "u_View",
 //         num_lights: u32 = "u_NumLights",
+//         num_global_lights: u32 = "u_NumGlobalLights",
+//         _padding0: [u32; 2] = "_padding0",
+//         cluster_dims: [u32; 4] = "u_ClusterDims",
+//         cluster_depth: [f32; 4] = "u_ClusterDepth",
+//         environment: [f32; 4] = "u_Environment",
+//         fog_color: [f32; 4] = "u_FogColor",
+//         fog_params: [f32; 4] = "u_FogParams",
+//         ssao: [f32; 4] = "u_Ssao",
 //     }
 //
 //     pipeline basic_pipe {
 //         vbuf: gfx::VertexBuffer<Vertex> = (),
 //         inst_buf: gfx::InstanceBuffer<Instance> = (),
-//         cb_lights: gfx::ConstantBuffer<LightParam> = "b_Lights",
+//         cb_lights: gfx::ShaderResource<LightParam> = "b_Lights",
+//         light_clusters: gfx::ShaderResource<[u32; 2]> = "b_LightClusters",
+//         light_indices: gfx::ShaderResource<u32> = "b_LightIndices",
 //         cb_globals: gfx::ConstantBuffer<Globals> = "b_Globals",
 //         tex_map: gfx::TextureSampler<[f32; 4]> = "t_Map",
 //         shadow_map0: gfx::TextureSampler<f32> = "t_Shadow0",
 //         shadow_map1: gfx::TextureSampler<f32> = "t_Shadow1",
+//         shadow_depth0: gfx::TextureSampler<f32> = "t_ShadowDepth0",
+//         shadow_depth1: gfx::TextureSampler<f32> = "t_ShadowDepth1",
+//         ssao: gfx::TextureSampler<[f32; 4]> = "t_Ssao",
 //         out_color: gfx::BlendTarget<ColorFormat> =
 //             ("Target0", gfx::state::ColorMask::all(), gfx::preset::blend::REPLACE),
 //         out_depth: gfx::DepthStencilTarget<DepthFormat> =
@@ -163,6 +317,42 @@ pub const ZEROED_DISPLACEMENT_CONTRIBUTION: [DisplacementContribution; MAX_TARGE
 //             gfx::preset::depth::LESS_EQUAL_WRITE,
 //     }
 //
+//     pipeline depth_normal_pipe {
+//         vbuf: gfx::VertexBuffer<Vertex> = (),
+//         inst_buf: gfx::InstanceBuffer<Instance> = (),
+//         globals: gfx::ConstantBuffer<Globals> = "b_Globals",
+//         target: gfx::RenderTarget<HdrFormat> = "Target0",
+//         depth_target: gfx::DepthTarget<DepthFormat> =
+//             gfx::preset::depth::LESS_EQUAL_WRITE,
+//     }
+//
+//     constant SsaoParams {
+//         kernel: [[f32; 4]; 64] = "u_Kernel",
+//         proj: [[f32; 4]; 4] = "u_Proj",
+//         params: [f32; 4] = "u_SsaoParams",
+//         viewport: [f32; 4] = "u_Viewport",
+//     }
+//
+//     pipeline ssao_pipe {
+//         globals: gfx::ConstantBuffer<Globals> = "b_Globals",
+//         params: gfx::ConstantBuffer<SsaoParams> = "b_SsaoParams",
+//         normal_depth: gfx::TextureSampler<[f32; 4]> = "t_NormalDepth",
+//         target: gfx::RenderTarget<HdrFormat> = "Target0",
+//     }
+//
+//     constant ClearParams {
+//         color: [f32; 4] = "u_Color",
+//     }
+//
+//     pipeline clear_pipe {
+//         params: gfx::ConstantBuffer<ClearParams> = "b_ClearParams",
+//         target: gfx::RenderTarget<ColorFormat> = "Target0",
+//         depth_target: gfx::DepthStencilTarget<DepthFormat> =
+//             (gfx::preset::depth::PASS_WRITE, gfx::state::Stencil {
+//                 front: STENCIL_MARK_SIDE, back: STENCIL_MARK_SIDE,
+//             }),
+//     }
+//
 //     constant QuadParams {
 //         rect: [f32; 4] = "u_Rect",
 //         depth: f32 = "u_Depth",
@@ -178,6 +368,57 @@ pub const ZEROED_DISPLACEMENT_CONTRIBUTION: [DisplacementContribution; MAX_TARGE
 //             gfx::preset::depth::LESS_EQUAL_TEST,
 //     }
 //
+//     constant TonemapParams {
+//         exposure: f32 = "u_Exposure",
+//         operator: i32 = "u_Operator",
+//         output_srgb: i32 = "u_OutputSrgb",
+//         _padding0: f32 = "_padding0",
+//     }
+//
+//     pipeline tonemap_pipe {
+//         params: gfx::ConstantBuffer<TonemapParams> = "b_TonemapParams",
+//         input: gfx::TextureSampler<[f32; 4]> = "t_Input",
+//         target: gfx::RenderTarget<ColorFormat> = "Target0",
+//     }
+//
+//     constant PostParams {
+//         params0: [f32; 4] = "u_Params0",
+//         params1: [f32; 4] = "u_Params1",
+//         resolution: [f32; 4] = "u_Resolution",
+//         mode: i32 = "u_Mode",
+//         _padding0: [i32; 3] = "_padding0",
+//     }
+//
+//     pipeline post_pipe {
+//         params: gfx::ConstantBuffer<PostParams> = "b_PostParams",
+//         input: gfx::TextureSampler<[f32; 4]> = "t_Input",
+//         source: gfx::TextureSampler<[f32; 4]> = "t_Source",
+//         lut: gfx::TextureSampler<[f32; 4]> = "t_Lut",
+//         target: gfx::RenderTarget<HdrFormat> = "Target0",
+//     }
+//
+//     constant CustomPostParams {
+//         user: [[f32; 4]; 4] = "user",
+//         resolution: [f32; 4] = "resolution",
+//     }
+//
+//     pipeline custom_pipe {
+//         vbuf: gfx::VertexBuffer<Vertex> = (),
+//         inst_buf: gfx::InstanceBuffer<Instance> = (),
+//         engine: gfx::BindGroup = 0,
+//         material: gfx::BindGroup = 1,
+//         target: gfx::RenderTarget<ColorFormat> = "Target0",
+//         depth_target: gfx::DepthTarget<DepthFormat> = (),
+//     }
+//
+//     pipeline custom_post_pipe {
+//         quad: gfx::ConstantBuffer<QuadParams> = "u_Quad",
+//         post: gfx::ConstantBuffer<CustomPostParams> = "u_Post",
+//         input: gfx::TextureSampler<[f32; 4]> = "t_Input",
+//         depth: gfx::TextureSampler<f32> = "t_Depth",
+//         target: gfx::RenderTarget<HdrFormat> = "Target0",
+//     }
+//
 //     constant PbrParams {
 //         base_color_factor: [f32; 4] = "u_BaseColorFactor",
 //         camera: [f32; 3] = "u_Camera",
@@ -187,7 +428,6 @@ pub const ZEROED_DISPLACEMENT_CONTRIBUTION: [DisplacementContribution; MAX_TARGE
 //         metallic_roughness: [f32; 2] = "u_MetallicRoughnessValues",
 //         normal_scale: f32 = "u_NormalScale",
 //         occlusion_strength: f32 = "u_OcclusionStrength",
-//         pbr_flags: i32 = "u_PbrFlags",
 //     }
 //
 //     constant DisplacementContribution {
@@ -203,7 +443,9 @@ pub const ZEROED_DISPLACEMENT_CONTRIBUTION: [DisplacementContribution; MAX_TARGE
 //
 //         globals: gfx::ConstantBuffer<Globals> = "b_Globals",
 //         params: gfx::ConstantBuffer<PbrParams> = "b_PbrParams",
-//         lights: gfx::ConstantBuffer<LightParam> = "b_Lights",
+//         lights: gfx::ShaderResource<LightParam> = "b_Lights",
+//         light_clusters: gfx::ShaderResource<[u32; 2]> = "b_LightClusters",
+//         light_indices: gfx::ShaderResource<u32> = "b_LightIndices",
 //         displacement_contributions: gfx::ConstantBuffer<DisplacementContribution> = "b_DisplacementContributions",
 //         joint_transforms: gfx::ShaderResource<[f32; 4]> = "b_JointTransforms",
 //         displacements: gfx::TextureSampler<[f32; 4]> = "u_Displacements",
@@ -217,11 +459,125 @@ pub const ZEROED_DISPLACEMENT_CONTRIBUTION: [DisplacementContribution; MAX_TARGE
 //
 //         occlusion_map: gfx::TextureSampler<[f32; 4]> = "u_OcclusionSampler",
 //
-//         color_target: gfx::RenderTarget<ColorFormat> = "Target0",
+//         irradiance_map: gfx::TextureSampler<[f32; 4]> = "t_Irradiance",
+//
+//         specular_map: gfx::TextureSampler<[f32; 4]> = "t_Specular",
+//
+//         brdf_lut: gfx::TextureSampler<[f32; 4]> = "t_BrdfLut",
+//
+//         ssao: gfx::TextureSampler<[f32; 4]> = "t_Ssao",
+//
+//         color_target: gfx::BlendTarget<ColorFormat> = ("Target0", gfx::state::ColorMask::all(), gfx::preset::blend::REPLACE),
 //         depth_target: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
 //     }
+//
+//     constant PickParams {
+//         id: u32 = "u_PickId",
+//         flags: i32 = "u_PickFlags",
+//         _padding0: [u32; 2] = "_padding0",
+//     }
+//
+//     pipeline pick_pipe {
+//         vbuf: gfx::VertexBuffer<Vertex> = (),
+//         inst_buf: gfx::InstanceBuffer<Instance> = (),
+//         globals: gfx::ConstantBuffer<Globals> = "b_Globals",
+//         params: gfx::ConstantBuffer<PickParams> = "b_PickParams",
+//         displacement_contributions: gfx::ConstantBuffer<DisplacementContribution> = "b_DisplacementContributions",
+//         joint_transforms: gfx::ShaderResource<[f32; 4]> = "b_JointTransforms",
+//         displacements: gfx::TextureSampler<[f32; 4]> = "u_Displacements",
+//         id_target: gfx::RenderTarget<(gfx::format::R32, gfx::format::Uint)> = "Target0",
+//         depth_value_target: gfx::RenderTarget<(gfx::format::R32, gfx::format::Float)> = "Target1",
+//         depth_target: gfx::DepthTarget<gfx::format::Depth32F> = gfx::preset::depth::LESS_EQUAL_WRITE,
+//     }
+//
+//     pipeline debug_pipe {
+//         vbuf: gfx::VertexBuffer<DebugVertex> = (),
+//         globals: gfx::ConstantBuffer<Globals> = "b_Globals",
+//         target: gfx::RenderTarget<ColorFormat> = "Target0",
+//         depth_target: gfx::DepthTarget<DepthFormat> =
+//             gfx::preset::depth::LESS_EQUAL_TEST,
+//     }
+//
+//     constant DebugViewParams {
+//         mode: i32 = "u_DebugView",
+//         flags: i32 = "u_DebugFlags",
+//         has_map: i32 = "u_DebugHasMap",
+//         _padding0: i32 = "_padding0",
+//         depth_range: [f32; 4] = "u_DepthRange",
+//     }
+//
+//     pipeline debug_view_pipe {
+//         vbuf: gfx::VertexBuffer<Vertex> = (),
+//         inst_buf: gfx::InstanceBuffer<Instance> = (),
+//         globals: gfx::ConstantBuffer<Globals> = "b_Globals",
+//         params: gfx::ConstantBuffer<DebugViewParams> = "b_DebugViewParams",
+//         displacement_contributions: gfx::ConstantBuffer<DisplacementContribution> = "b_DisplacementContributions",
+//         joint_transforms: gfx::ShaderResource<[f32; 4]> = "b_JointTransforms",
+//         displacements: gfx::TextureSampler<[f32; 4]> = "u_Displacements",
+//         map: gfx::TextureSampler<[f32; 4]> = "t_Map",
+//         target: gfx::BlendTarget<ColorFormat> =
+//             ("Target0", gfx::state::ColorMask::all(), gfx::preset::blend::REPLACE),
+//         depth_target: gfx::DepthTarget<DepthFormat> =
+//             gfx::preset::depth::LESS_EQUAL_WRITE,
+//     }
+//
+//     constant VertexLinesParams {
+//         world: [[f32; 4]; 4] = "u_World",
+//         color: [f32; 4] = "u_LineColor",
+//         length: f32 = "u_LineLength",
+//         flags: i32 = "u_LineFlags",
+//         _padding0: [i32; 2] = "_padding0",
+//     }
+//
+//     pipeline vertex_lines_pipe {
+//         inst_buf: gfx::InstanceBuffer<Vertex> = (),
+//         globals: gfx::ConstantBuffer<Globals> = "b_Globals",
+//         params: gfx::ConstantBuffer<VertexLinesParams> = "b_VertexLinesParams",
+//         displacement_contributions: gfx::ConstantBuffer<DisplacementContribution> = "b_DisplacementContributions",
+//         joint_transforms: gfx::ShaderResource<[f32; 4]> = "b_JointTransforms",
+//         displacements: gfx::TextureSampler<[f32; 4]> = "u_Displacements",
+//         target: gfx::RenderTarget<ColorFormat> = "Target0",
+//         depth_target: gfx::DepthTarget<DepthFormat> =
+//             gfx::preset::depth::LESS_EQUAL_TEST,
+//     }
+//
+//     constant OutlineParams {
+//         color: [f32; 4] = "u_OutlineColor",
+//         extent: [f32; 4] = "u_OutlineExtent",
+//         flags: i32 = "u_OutlineFlags",
+//         _padding0: [i32; 3] = "_padding0",
+//     }
+//
+//     pipeline outline_pipe {
+//         vbuf: gfx::VertexBuffer<Vertex> = (),
+//         inst_buf: gfx::InstanceBuffer<Instance> = (),
+//         globals: gfx::ConstantBuffer<Globals> = "b_Globals",
+//         params: gfx::ConstantBuffer<OutlineParams> = "b_OutlineParams",
+//         displacement_contributions: gfx::ConstantBuffer<DisplacementContribution> = "b_DisplacementContributions",
+//         joint_transforms: gfx::ShaderResource<[f32; 4]> = "b_JointTransforms",
+//         displacements: gfx::TextureSampler<[f32; 4]> = "u_Displacements",
+//         target: gfx::BlendTarget<ColorFormat> =
+//             ("Target0", gfx::state::ColorMask::all(), gfx::preset::blend::REPLACE),
+//         depth_target: gfx::DepthStencilTarget<DepthFormat> =
+//             (gfx::preset::depth::PASS_TEST, gfx::state::Stencil {
+//                 front: STENCIL_OUTLINE_SIDE, back: STENCIL_OUTLINE_SIDE,
+//             }),
+//     }
 // }
 
+// `gfx_defines!` declares these `#[repr(C)]`, and their fields add up to a
+// multiple of 4 bytes with explicit padding only, so no byte is uninitialized.
+macro_rules! impl_pod {
+    ($($ty:ty),*) => {
+        $(
+            unsafe impl bytemuck::Zeroable for $ty {}
+            unsafe impl bytemuck::Pod for $ty {}
+        )*
+    };
+}
+
+impl_pod!(Vertex, Instance, DebugVertex, LightParam, Globals);
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub(crate) struct InstanceCacheKey {
     pub(crate) material: Material,
@@ -230,7 +586,7 @@ pub(crate) struct InstanceCacheKey {
 
 impl Instance {
     #[inline]
-    fn basic(mx_world: mint::RowMatrix4<f32>, color: u32, uv_range: [f32; 4], param: f32) -> Self {
+    fn basic(mx_world: mint::RowMatrix4<f32>, color: u32, uv_range: [f32; 4], param0: f32, param1: f32) -> Self {
         Instance {
             world0: mx_world.x.into(),
             world1: mx_world.y.into(),
@@ -240,11 +596,20 @@ impl Instance {
                 let rgb = color::to_linear_rgb(color);
                 [rgb[0], rgb[1], rgb[2], 0.0]
             },
-            mat_params: [param, 0.0, 0.0, 0.0],
+            mat_params: [param0, param1, 0.0, 0.0],
             uv_range,
         }
     }
 
+    /// The specular threshold goes in the color alpha, the rim color and
+    /// power in the material parameters.
+    #[inline]
+    fn toon(mx_world: mint::RowMatrix4<f32>, color: u32, uv_range: [f32; 4], specular_threshold: f32, rim_color: u32, rim_power: f32) -> Self {
+        let rgb = color::to_linear_rgb(color);
+        let rim = color::to_linear_rgb(rim_color);
+        Instance { world0: mx_world.x.into(), world1: mx_world.y.into(), world2: mx_world.z.into(), color: [rgb[0], rgb[1], rgb[2], specular_threshold], mat_params: [rim[0], rim[1], rim[2], rim_power], uv_range }
+    }
+
     #[inline]
     fn pbr(mx_world: mint::RowMatrix4<f32>) -> Self {
         Instance { world0: mx_world.x.into(), world1: mx_world.y.into(), world2: mx_world.z.into(), color: [0.0; 4], mat_params: [0.0; 4], uv_range: [0.0; 4] }
@@ -270,6 +635,8 @@ pub(crate) struct GpuData {
     pub pending: Option<DynamicData>,
     pub instance_cache_key: Option<InstanceCacheKey>,
     pub displacement_contributions: Vec<DisplacementContribution>,
+    /// CPU copy of the geometry for `Raycaster`, shared between instances.
+    pub collider: Option<Arc<Collider>>,
 }
 
 #[derive(Debug)]
@@ -289,13 +656,99 @@ pub(crate) struct DynamicData {
 }
 
 /// Shadow type is used to specify shadow's rendering algorithm.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ShadowType {
     /// Force no shadows.
     Off,
     /// Basic (and fast) single-sample shadows.
     Basic,
-    /// Percentage-closest filter (PCF).
+    /// Percentage-closest filter (PCF) over a regular 3x3 grid.
     Pcf,
+    /// Percentage-closest filter (PCF) over a rotated Poisson disk.
+    ///
+    /// Trades the banding of the regular grid for noise, which is usually
+    /// less noticeable.
+    PcfPoisson,
+    /// Percentage-closer soft shadows (PCSS).
+    ///
+    /// Penumbrae widen with the distance between the occluder and the
+    /// receiver, as they would for an area light. The penumbra of an occluder
+    /// at the near end of the shadow range is `light_size` wide on a receiver
+    /// at the far end.
+    Pcss {
+        /// Size of the emitting area, in world units.
+        light_size: f32,
+    },
+}
+
+impl Default for ShadowType {
+    fn default() -> Self {
+        ShadowType::Basic
+    }
+}
+
+impl ShadowType {
+    /// Filtering technique index, has to match `shadows.glsl`.
+    fn filter_index(&self) -> i32 {
+        match *self {
+            ShadowType::Off => 0,
+            ShadowType::Basic => 1,
+            ShadowType::Pcf => 2,
+            ShadowType::PcfPoisson => 3,
+            ShadowType::Pcss { .. } => 4,
+        }
+    }
+}
+
+/// Operator used to map HDR scene colors into the displayable range.
+///
+/// Only used when [`Renderer::hdr`](struct.Renderer.html#structfield.hdr) is enabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ToneMapping {
+    /// Clamp colors to `[0, 1]`.
+    None,
+    /// Reinhard's `x / (1 + x)` operator, keeps the hue but looks flat.
+    Reinhard,
+    /// Filmic curve fitted to the ACES reference rendering transform.
+    Aces,
+    /// Troy Sobotka's AgX, desaturates very bright colors instead of skewing their hue.
+    AgX,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping::Aces
+    }
+}
+
+impl ToneMapping {
+    /// Operator index, has to match `tonemap_ps.glsl`.
+    fn index(&self) -> i32 {
+        match *self {
+            ToneMapping::None => 0,
+            ToneMapping::Reinhard => 1,
+            ToneMapping::Aces => 2,
+            ToneMapping::AgX => 3,
+        }
+    }
+}
+
+/// Color and depth targets `Renderer::render_impl` draws into.
+struct Output {
+    color: wgpu::TextureView,
+    /// Format of `color`.
+    format: wgpu::TextureFormat,
+    depth: wgpu::TextureView,
+    size: [u32; 2],
+    /// Pixel rectangle drawn into, `[x, y, width, height]` from the top left corner.
+    viewport: [u32; 4],
+    /// Whether the viewport is cleared before drawing.
+    clear: bool,
+    /// Whether this is the window, the only output with SSAO
+    /// and post-processing.
+    window: bool,
+    /// Whether UI text is queued for drawing.
+    ui: bool,
 }
 
 struct DebugQuad {
@@ -309,22 +762,34 @@ struct DebugQuad {
 // pub struct PipelineStates<R: gfx::Resources> {
 pub struct PipelineStates {
     // /// Corresponds to `Material::Basic`.
-    // mesh_basic_fill: gfx::PipelineState<R, basic_pipe::Meta>,
+    // mesh_basic_fill: Variants<gfx::PipelineState<R, basic_pipe::Meta>>,
     //
     // /// Corresponds to `Material::Line`.
-    // line_basic: gfx::PipelineState<R, basic_pipe::Meta>,
+    // line_basic: Variants<gfx::PipelineState<R, basic_pipe::Meta>>,
     //
     // /// Corresponds to `Material::Wireframe`.
-    // mesh_basic_wireframe: gfx::PipelineState<R, basic_pipe::Meta>,
+    // mesh_basic_wireframe: Variants<gfx::PipelineState<R, basic_pipe::Meta>>,
+    //
+    // /// Corresponds to `Material::Gouraud`, with or without `Features::SHADOWS`.
+    // mesh_gouraud: Variants<gfx::PipelineState<R, basic_pipe::Meta>>,
+    //
+    // /// Corresponds to `Material::Phong`, with or without `Features::SHADOWS`.
+    // mesh_phong: Variants<gfx::PipelineState<R, basic_pipe::Meta>>,
+    //
+    // /// Corresponds to `Material::Toon`, with or without `Features::SHADOWS`.
+    // mesh_toon: Variants<gfx::PipelineState<R, basic_pipe::Meta>>,
+    //
+    // /// Corresponds to `Material::Matcap`.
+    // mesh_matcap: Variants<gfx::PipelineState<R, basic_pipe::Meta>>,
     //
-    // /// Corresponds to `Material::Gouraud`.
-    // mesh_gouraud: gfx::PipelineState<R, basic_pipe::Meta>,
+    // /// Corresponds to `Material::Normal`.
+    // mesh_normal: Variants<gfx::PipelineState<R, basic_pipe::Meta>>,
     //
-    // /// Corresponds to `Material::Phong`.
-    // mesh_phong: gfx::PipelineState<R, basic_pipe::Meta>,
+    // /// Corresponds to `Material::Depth`.
+    // mesh_depth: Variants<gfx::PipelineState<R, basic_pipe::Meta>>,
     //
     // /// Corresponds to `Material::Sprite`.
-    // sprite: gfx::PipelineState<R, basic_pipe::Meta>,
+    // sprite: Variants<gfx::PipelineState<R, basic_pipe::Meta>>,
     //
     // /// Used internally for shadow casting.
     // shadow: gfx::PipelineState<R, shadow_pipe::Meta>,
@@ -332,11 +797,101 @@ pub struct PipelineStates {
     // /// Used internally for rendering sprites.
     // quad: gfx::PipelineState<R, quad_pipe::Meta>,
     //
-    // /// Corresponds to `Material::Pbr`.
-    // pbr: gfx::PipelineState<R, pbr_pipe::Meta>,
+    // /// Used internally for clearing a `Viewport` smaller than the target.
+    // clear: gfx::PipelineState<R, clear_pipe::Meta>,
+    //
+    // /// Used internally for picking triangle meshes, see `Renderer::pick`.
+    // pick: gfx::PipelineState<R, pick_pipe::Meta>,
+    //
+    // /// Used internally for picking `Material::Line`.
+    // pick_line: gfx::PipelineState<R, pick_pipe::Meta>,
+    //
+    // /// Used internally for picking `Material::Wireframe`.
+    // pick_wireframe: gfx::PipelineState<R, pick_pipe::Meta>,
+    //
+    // /// Used internally for picking `Material::Sprite`.
+    // pick_sprite: gfx::PipelineState<R, pick_pipe::Meta>,
+    //
+    // /// Used internally for depth tested debug shapes, see `Renderer::debug`.
+    // debug_lines: gfx::PipelineState<R, debug_pipe::Meta>,
+    //
+    // /// Used internally for debug shapes drawn over the scene.
+    // debug_lines_overlay: gfx::PipelineState<R, debug_pipe::Meta>,
+    //
+    // /// Used internally for vertex normals and tangents, see `DebugDraw::vertex_normals`.
+    // vertex_lines: gfx::PipelineState<R, vertex_lines_pipe::Meta>,
+    //
+    // /// Used internally for vertex normals and tangents drawn over the scene.
+    // vertex_lines_overlay: gfx::PipelineState<R, vertex_lines_pipe::Meta>,
+    //
+    // /// Used internally for triangle meshes, see `Renderer::debug_view`.
+    // debug_view: gfx::PipelineState<R, debug_view_pipe::Meta>,
+    //
+    // /// Used internally for `Material::Line` in a debug view.
+    // debug_view_line: gfx::PipelineState<R, debug_view_pipe::Meta>,
+    //
+    // /// Used internally for `Material::Wireframe` in a debug view.
+    // debug_view_wireframe: gfx::PipelineState<R, debug_view_pipe::Meta>,
+    //
+    // /// Used internally for `Material::Sprite` in a debug view.
+    // debug_view_sprite: gfx::PipelineState<R, debug_view_pipe::Meta>,
+    //
+    // /// Used internally for `DebugView::Overdraw`.
+    // debug_view_overdraw: gfx::PipelineState<R, debug_view_pipe::Meta>,
+    //
+    // /// Used internally for `DebugView::Overdraw` of sprites.
+    // debug_view_overdraw_sprite: gfx::PipelineState<R, debug_view_pipe::Meta>,
+    //
+    // /// Used internally for marking highlighted meshes, see `Object::set_highlight`.
+    // outline_mark: gfx::PipelineState<R, outline_pipe::Meta>,
+    //
+    // /// Used internally for marking highlighted sprites.
+    // outline_mark_sprite: gfx::PipelineState<R, outline_pipe::Meta>,
+    //
+    // /// Used internally for outlining highlighted meshes.
+    // outline: gfx::PipelineState<R, outline_pipe::Meta>,
+    //
+    // /// Used internally for outlining highlighted sprites.
+    // outline_sprite: gfx::PipelineState<R, outline_pipe::Meta>,
+    //
+    // /// Used internally for recoloring highlighted `Material::Line`.
+    // outline_line: gfx::PipelineState<R, outline_pipe::Meta>,
+    //
+    // /// Corresponds to `Material::Pbr`, a variant per combination of maps,
+    // /// skinning and morph targets.
+    // ///
+    // /// Every material pipeline also has a variant per `RenderState`.
+    // pbr: Variants<gfx::PipelineState<R, pbr_pipe::Meta>>,
     //
     // /// Used internally for rendering `Background::Skybox`.
     // skybox: gfx::PipelineState<R, quad_pipe::Meta>,
+    //
+    // /// Used internally for rendering `Background::Equirectangular`.
+    // equirect: gfx::PipelineState<R, quad_pipe::Meta>,
+    //
+    // /// Used internally for resolving the HDR target, see `Renderer::hdr`.
+    // tonemap: gfx::PipelineState<R, tonemap_pipe::Meta>,
+    //
+    // /// Used internally for the SSAO prepass, see `Renderer::ssao`.
+    // depth_normal: gfx::PipelineState<R, depth_normal_pipe::Meta>,
+    //
+    // /// Used internally for computing ambient occlusion.
+    // ssao: gfx::PipelineState<R, ssao_pipe::Meta>,
+    //
+    // /// Used internally for blurring the ambient occlusion.
+    // ssao_blur: gfx::PipelineState<R, post_pipe::Meta>,
+    //
+    // /// Used internally for `PostEffect::Bloom`.
+    // bloom: gfx::PipelineState<R, post_pipe::Meta>,
+    //
+    // /// Used internally for `PostEffect::Fxaa`.
+    // fxaa: gfx::PipelineState<R, post_pipe::Meta>,
+    //
+    // /// Used internally for `PostEffect::Vignette`.
+    // vignette: gfx::PipelineState<R, post_pipe::Meta>,
+    //
+    // /// Used internally for `PostEffect::ColorGrading`.
+    // grading: gfx::PipelineState<R, post_pipe::Meta>,
 }
 
 // impl PipelineStates<back::Resources> {
@@ -346,17 +901,29 @@ impl PipelineStates {
         Self::init(src, &mut factory.backend)
     }
 
-    pub(crate) fn pso_by_material<'a>(&'a self, material: &'a Material) -> &'a BasicPipelineState {
-        match *material {
-            Material::Basic(_) => &self.mesh_basic_fill,
-            Material::CustomBasic(ref b) => &b.pipeline,
-            Material::Line(_) => &self.line_basic,
-            Material::Wireframe(_) => &self.mesh_basic_wireframe,
-            Material::Lambert(_) => &self.mesh_gouraud,
-            Material::Phong(_) => &self.mesh_phong,
-            Material::Sprite(_) => &self.sprite,
+    /// Returns the pipeline drawing `material` with `basic_pipe`, creating
+    /// the variant for its render state and `features` if needed.
+    ///
+    /// Returns `None` if the variant can't be created, see `Variants::get`.
+    pub(crate) fn basic_by_material<'a>(&'a mut self, device: &wgpu::Device, material: &'a Material, features: Features) -> Option<&'a BasicPipelineState> {
+        let state = material.state().cloned().unwrap_or_default();
+        // only the lit materials have shadowed variants
+        let (variants, features) = match *material {
+            Material::CustomBasic(ref b) => return Some(&b.pipeline),
+            Material::Basic(_) => (&mut self.mesh_basic_fill, Features::empty()),
+            Material::Line(_) => (&mut self.line_basic, Features::empty()),
+            Material::Wireframe(_) => (&mut self.mesh_basic_wireframe, Features::empty()),
+            Material::Lambert(_) => (&mut self.mesh_gouraud, features & Features::SHADOWS),
+            Material::Phong(_) => (&mut self.mesh_phong, features & Features::SHADOWS),
+            Material::Toon(ref toon) if toon.receive_shadows => (&mut self.mesh_toon, features & Features::SHADOWS),
+            Material::Toon(_) => (&mut self.mesh_toon, Features::empty()),
+            Material::Matcap(_) => (&mut self.mesh_matcap, Features::empty()),
+            Material::Normal(_) => (&mut self.mesh_normal, Features::empty()),
+            Material::Depth(_) => (&mut self.mesh_depth, Features::empty()),
+            Material::Sprite(_) => (&mut self.sprite, Features::empty()),
             _ => unreachable!(),
-        }
+        };
+        variants.get(device, features, &state)
     }
 }
 
@@ -365,32 +932,77 @@ impl PipelineStates {
     /// Implementation of `PipelineStates::new`.
     // pub(crate) fn init<F: gfx::Factory<R>>(src: &source::Set, backend: &mut F) -> Result<Self, PipelineCreationError> {
     pub(crate) fn init(src: &source::Set, backend: &mut F) -> Result<Self, PipelineCreationError> {
-        // let basic = backend.create_shader_set(&src.basic.vs, &src.basic.ps)?;
-        // let gouraud = backend.create_shader_set(&src.gouraud.vs, &src.gouraud.ps)?;
-        // let phong = backend.create_shader_set(&src.phong.vs, &src.phong.ps)?;
-        // let sprite = backend.create_shader_set(&src.sprite.vs, &src.sprite.ps)?;
-        // let shadow = backend.create_shader_set(&src.shadow.vs, &src.shadow.ps)?;
-        // let quad = backend.create_shader_set(&src.quad.vs, &src.quad.ps)?;
-        // let pbr = backend.create_shader_set(&src.pbr.vs, &src.pbr.ps)?;
-        // let skybox = backend.create_shader_set(&src.skybox.vs, &src.skybox.ps)?;
+        // let shadow = backend.create_shader_set(src.shadow.vs.variant(Features::empty())?.as_bytes(), src.shadow.ps.variant(Features::empty())?.as_bytes())?;
+        // let quad = backend.create_shader_set(src.quad.vs.variant(Features::empty())?.as_bytes(), src.quad.ps.variant(Features::empty())?.as_bytes())?;
+        // let skybox = backend.create_shader_set(src.skybox.vs.variant(Features::empty())?.as_bytes(), src.skybox.ps.variant(Features::empty())?.as_bytes())?;
+        // let equirect = backend.create_shader_set(src.equirect.vs.variant(Features::empty())?.as_bytes(), src.equirect.ps.variant(Features::empty())?.as_bytes())?;
+        // let tonemap = backend.create_shader_set(src.tonemap.vs.variant(Features::empty())?.as_bytes(), src.tonemap.ps.variant(Features::empty())?.as_bytes())?;
+        // let depth_normal = backend.create_shader_set(src.depth_normal.vs.variant(Features::empty())?.as_bytes(), src.depth_normal.ps.variant(Features::empty())?.as_bytes())?;
+        // let ssao = backend.create_shader_set(src.ssao.vs.variant(Features::empty())?.as_bytes(), src.ssao.ps.variant(Features::empty())?.as_bytes())?;
+        // let ssao_blur = backend.create_shader_set(src.ssao_blur.vs.variant(Features::empty())?.as_bytes(), src.ssao_blur.ps.variant(Features::empty())?.as_bytes())?;
+        // let clear = backend.create_shader_set(src.clear.vs.variant(Features::empty())?.as_bytes(), src.clear.ps.variant(Features::empty())?.as_bytes())?;
+        // let pick = backend.create_shader_set(src.pick.vs.variant(Features::empty())?.as_bytes(), src.pick.ps.variant(Features::empty())?.as_bytes())?;
+        // let debug_lines = backend.create_shader_set(src.debug_lines.vs.variant(Features::empty())?.as_bytes(), src.debug_lines.ps.variant(Features::empty())?.as_bytes())?;
+        // let vertex_lines = backend.create_shader_set(src.vertex_lines.vs.variant(Features::empty())?.as_bytes(), src.vertex_lines.ps.variant(Features::empty())?.as_bytes())?;
+        // let debug_view = backend.create_shader_set(src.debug_view.vs.variant(Features::empty())?.as_bytes(), src.debug_view.ps.variant(Features::empty())?.as_bytes())?;
+        // let outline = backend.create_shader_set(src.outline.vs.variant(Features::empty())?.as_bytes(), src.outline.ps.variant(Features::empty())?.as_bytes())?;
+        // let bloom = backend.create_shader_set(src.bloom.vs.variant(Features::empty())?.as_bytes(), src.bloom.ps.variant(Features::empty())?.as_bytes())?;
+        // let fxaa = backend.create_shader_set(src.fxaa.vs.variant(Features::empty())?.as_bytes(), src.fxaa.ps.variant(Features::empty())?.as_bytes())?;
+        // let vignette = backend.create_shader_set(src.vignette.vs.variant(Features::empty())?.as_bytes(), src.vignette.ps.variant(Features::empty())?.as_bytes())?;
+        // let grading = backend.create_shader_set(src.grading.vs.variant(Features::empty())?.as_bytes(), src.grading.ps.variant(Features::empty())?.as_bytes())?;
         //
         // let rast_quad = gfx::state::Rasterizer { samples: Some(gfx::state::MultiSample), ..gfx::state::Rasterizer::new_fill() };
         // let rast_fill = rast_quad.with_cull_back();
         // let rast_wire = gfx::state::Rasterizer { method: gfx::state::RasterMethod::Line(1), ..rast_fill };
         // let rast_shadow = gfx::state::Rasterizer { offset: Some(gfx::state::Offset(2, 2)), ..rast_fill };
         //
-        // let pso_mesh_basic_fill = backend.create_pipeline_state(&basic, gfx::Primitive::TriangleList, rast_fill, basic_pipe::new())?;
-        // let pso_line_basic = backend.create_pipeline_state(&basic, gfx::Primitive::LineStrip, rast_fill, basic_pipe::new())?;
-        // let pso_mesh_basic_wireframe = backend.create_pipeline_state(&basic, gfx::Primitive::TriangleList, rast_wire, basic_pipe::new())?;
-        // let pso_mesh_gouraud = backend.create_pipeline_state(&gouraud, gfx::Primitive::TriangleList, rast_fill, basic_pipe::new())?;
-        // let pso_mesh_phong = backend.create_pipeline_state(&phong, gfx::Primitive::TriangleList, rast_fill, basic_pipe::new())?;
-        // let pso_sprite = backend.create_pipeline_state(&sprite, gfx::Primitive::TriangleStrip, rast_fill, basic_pipe::Init { out_color: ("Target0", gfx::state::ColorMask::all(), gfx::preset::blend::ALPHA), ..basic_pipe::new() })?;
+        // let pso_mesh_basic_fill = Variants::new(src.basic.vs.clone(), src.basic.ps.clone(), move |device, vs, ps, state| device.create_pipeline_state(&device.create_shader_set(vs.as_bytes(), ps.as_bytes())?, gfx::Primitive::TriangleList, material_rasterizer(rast_fill, state), basic_init(state, gfx::preset::blend::REPLACE)));
+        // let pso_line_basic = Variants::new(src.basic.vs.clone(), src.basic.ps.clone(), move |device, vs, ps, state| device.create_pipeline_state(&device.create_shader_set(vs.as_bytes(), ps.as_bytes())?, gfx::Primitive::LineStrip, material_rasterizer(rast_fill, state), basic_init(state, gfx::preset::blend::REPLACE)));
+        // let pso_mesh_basic_wireframe = Variants::new(src.basic.vs.clone(), src.basic.ps.clone(), move |device, vs, ps, state| device.create_pipeline_state(&device.create_shader_set(vs.as_bytes(), ps.as_bytes())?, gfx::Primitive::TriangleList, material_rasterizer(rast_wire, state), basic_init(state, gfx::preset::blend::REPLACE)));
+        // let pso_mesh_gouraud = Variants::new(src.gouraud.vs.clone(), src.gouraud.ps.clone(), move |device, vs, ps, state| device.create_pipeline_state(&device.create_shader_set(vs.as_bytes(), ps.as_bytes())?, gfx::Primitive::TriangleList, material_rasterizer(rast_fill, state), basic_init(state, gfx::preset::blend::REPLACE)));
+        // let pso_mesh_phong = Variants::new(src.phong.vs.clone(), src.phong.ps.clone(), move |device, vs, ps, state| device.create_pipeline_state(&device.create_shader_set(vs.as_bytes(), ps.as_bytes())?, gfx::Primitive::TriangleList, material_rasterizer(rast_fill, state), basic_init(state, gfx::preset::blend::REPLACE)));
+        // let pso_mesh_toon = Variants::new(src.toon.vs.clone(), src.toon.ps.clone(), move |device, vs, ps, state| device.create_pipeline_state(&device.create_shader_set(vs.as_bytes(), ps.as_bytes())?, gfx::Primitive::TriangleList, material_rasterizer(rast_fill, state), basic_init(state, gfx::preset::blend::REPLACE)));
+        // let pso_mesh_matcap = Variants::new(src.matcap.vs.clone(), src.matcap.ps.clone(), move |device, vs, ps, state| device.create_pipeline_state(&device.create_shader_set(vs.as_bytes(), ps.as_bytes())?, gfx::Primitive::TriangleList, material_rasterizer(rast_fill, state), basic_init(state, gfx::preset::blend::REPLACE)));
+        // let pso_mesh_normal = Variants::new(src.normal.vs.clone(), src.normal.ps.clone(), move |device, vs, ps, state| device.create_pipeline_state(&device.create_shader_set(vs.as_bytes(), ps.as_bytes())?, gfx::Primitive::TriangleList, material_rasterizer(rast_fill, state), basic_init(state, gfx::preset::blend::REPLACE)));
+        // let pso_mesh_depth = Variants::new(src.depth.vs.clone(), src.depth.ps.clone(), move |device, vs, ps, state| device.create_pipeline_state(&device.create_shader_set(vs.as_bytes(), ps.as_bytes())?, gfx::Primitive::TriangleList, material_rasterizer(rast_fill, state), basic_init(state, gfx::preset::blend::REPLACE)));
+        // let pso_sprite = Variants::new(src.sprite.vs.clone(), src.sprite.ps.clone(), move |device, vs, ps, state| device.create_pipeline_state(&device.create_shader_set(vs.as_bytes(), ps.as_bytes())?, gfx::Primitive::TriangleStrip, material_rasterizer(rast_fill, state), basic_init(state, gfx::preset::blend::ALPHA)));
         // let pso_shadow = backend.create_pipeline_state(&shadow, gfx::Primitive::TriangleList, rast_shadow, shadow_pipe::new())?;
         // let pso_quad = backend.create_pipeline_state(&quad, gfx::Primitive::TriangleStrip, rast_quad, quad_pipe::new())?;
+        // let pso_clear = backend.create_pipeline_state(&clear, gfx::Primitive::TriangleList, rast_quad, clear_pipe::new())?;
+        // let pso_pick = backend.create_pipeline_state(&pick, gfx::Primitive::TriangleList, rast_fill, pick_pipe::new())?;
+        // let pso_pick_line = backend.create_pipeline_state(&pick, gfx::Primitive::LineStrip, rast_fill, pick_pipe::new())?;
+        // let pso_pick_wireframe = backend.create_pipeline_state(&pick, gfx::Primitive::TriangleList, rast_wire, pick_pipe::new())?;
+        // let pso_pick_sprite = backend.create_pipeline_state(&pick, gfx::Primitive::TriangleStrip, rast_fill, pick_pipe::new())?;
+        // let pso_debug_lines = backend.create_pipeline_state(&debug_lines, gfx::Primitive::LineList, rast_quad, debug_pipe::new())?;
+        // let pso_debug_lines_overlay = backend.create_pipeline_state(&debug_lines, gfx::Primitive::LineList, rast_quad, debug_pipe::Init { depth_target: gfx::preset::depth::PASS_TEST, ..debug_pipe::new() })?;
+        // let pso_vertex_lines = backend.create_pipeline_state(&vertex_lines, gfx::Primitive::LineList, rast_quad, vertex_lines_pipe::new())?;
+        // let pso_vertex_lines_overlay = backend.create_pipeline_state(&vertex_lines, gfx::Primitive::LineList, rast_quad, vertex_lines_pipe::Init { depth_target: gfx::preset::depth::PASS_TEST, ..vertex_lines_pipe::new() })?;
+        // let overdraw_init = debug_view_pipe::Init { target: ("Target0", gfx::state::ColorMask::all(), gfx::preset::blend::ADD), depth_target: gfx::preset::depth::PASS_TEST, ..debug_view_pipe::new() };
+        // let pso_debug_view = backend.create_pipeline_state(&debug_view, gfx::Primitive::TriangleList, rast_fill, debug_view_pipe::new())?;
+        // let pso_debug_view_line = backend.create_pipeline_state(&debug_view, gfx::Primitive::LineStrip, rast_fill, debug_view_pipe::new())?;
+        // let pso_debug_view_wireframe = backend.create_pipeline_state(&debug_view, gfx::Primitive::TriangleList, rast_wire, debug_view_pipe::new())?;
+        // let pso_debug_view_sprite = backend.create_pipeline_state(&debug_view, gfx::Primitive::TriangleStrip, rast_fill, debug_view_pipe::new())?;
+        // let pso_debug_view_overdraw = backend.create_pipeline_state(&debug_view, gfx::Primitive::TriangleList, rast_fill, overdraw_init.clone())?;
+        // let pso_debug_view_overdraw_sprite = backend.create_pipeline_state(&debug_view, gfx::Primitive::TriangleStrip, rast_fill, overdraw_init)?;
+        // let outline_mark_init = outline_pipe::Init { target: ("Target0", gfx::state::ColorMask::empty(), gfx::preset::blend::REPLACE), depth_target: (gfx::preset::depth::PASS_TEST, gfx::state::Stencil { front: STENCIL_MARK_SIDE, back: STENCIL_MARK_SIDE }), ..outline_pipe::new() };
+        // let pso_outline_mark = backend.create_pipeline_state(&outline, gfx::Primitive::TriangleList, rast_quad, outline_mark_init.clone())?;
+        // let pso_outline_mark_sprite = backend.create_pipeline_state(&outline, gfx::Primitive::TriangleStrip, rast_quad, outline_mark_init)?;
+        // let pso_outline = backend.create_pipeline_state(&outline, gfx::Primitive::TriangleList, rast_quad, outline_pipe::new())?;
+        // let pso_outline_sprite = backend.create_pipeline_state(&outline, gfx::Primitive::TriangleStrip, rast_quad, outline_pipe::new())?;
+        // let pso_outline_line = backend.create_pipeline_state(&outline, gfx::Primitive::LineStrip, rast_quad, outline_pipe::new())?;
         // let pso_skybox = backend.create_pipeline_state(&skybox, gfx::Primitive::TriangleStrip, rast_quad, quad_pipe::new())?;
-        // let pso_pbr = backend.create_pipeline_state(&pbr, gfx::Primitive::TriangleList, rast_fill, pbr_pipe::new())?;
+        // let pso_equirect = backend.create_pipeline_state(&equirect, gfx::Primitive::TriangleStrip, rast_quad, quad_pipe::new())?;
+        // let pso_pbr = Variants::new(src.pbr.vs.clone(), src.pbr.ps.clone(), move |device, vs, ps, state| device.create_pipeline_state(&device.create_shader_set(vs.as_bytes(), ps.as_bytes())?, gfx::Primitive::TriangleList, material_rasterizer(rast_fill, state), pbr_init(state)));
+        // let pso_tonemap = backend.create_pipeline_state(&tonemap, gfx::Primitive::TriangleList, rast_quad, tonemap_pipe::new())?;
+        // let pso_depth_normal = backend.create_pipeline_state(&depth_normal, gfx::Primitive::TriangleList, rast_fill, depth_normal_pipe::new())?;
+        // let pso_ssao = backend.create_pipeline_state(&ssao, gfx::Primitive::TriangleList, rast_quad, ssao_pipe::new())?;
+        // let pso_ssao_blur = backend.create_pipeline_state(&ssao_blur, gfx::Primitive::TriangleList, rast_quad, post_pipe::new())?;
+        // let pso_bloom = backend.create_pipeline_state(&bloom, gfx::Primitive::TriangleList, rast_quad, post_pipe::new())?;
+        // let pso_fxaa = backend.create_pipeline_state(&fxaa, gfx::Primitive::TriangleList, rast_quad, post_pipe::new())?;
+        // let pso_vignette = backend.create_pipeline_state(&vignette, gfx::Primitive::TriangleList, rast_quad, post_pipe::new())?;
+        // let pso_grading = backend.create_pipeline_state(&grading, gfx::Primitive::TriangleList, rast_quad, post_pipe::new())?;
         //
-        // // Ok(PipelineStates { mesh_basic_fill: pso_mesh_basic_fill, line_basic: pso_line_basic, mesh_basic_wireframe: pso_mesh_basic_wireframe, mesh_gouraud: pso_mesh_gouraud, mesh_phong: pso_mesh_phong, sprite: pso_sprite, shadow: pso_shadow, quad: pso_quad, pbr: pso_pbr, skybox: pso_skybox })
+        // // Ok(PipelineStates { mesh_basic_fill: pso_mesh_basic_fill, line_basic: pso_line_basic, mesh_basic_wireframe: pso_mesh_basic_wireframe, mesh_gouraud: pso_mesh_gouraud, mesh_phong: pso_mesh_phong, mesh_toon: pso_mesh_toon, mesh_matcap: pso_mesh_matcap, mesh_normal: pso_mesh_normal, mesh_depth: pso_mesh_depth, sprite: pso_sprite, shadow: pso_shadow, quad: pso_quad, clear: pso_clear, pick: pso_pick, pick_line: pso_pick_line, pick_wireframe: pso_pick_wireframe, pick_sprite: pso_pick_sprite, debug_lines: pso_debug_lines, debug_lines_overlay: pso_debug_lines_overlay, vertex_lines: pso_vertex_lines, vertex_lines_overlay: pso_vertex_lines_overlay, debug_view: pso_debug_view, debug_view_line: pso_debug_view_line, debug_view_wireframe: pso_debug_view_wireframe, debug_view_sprite: pso_debug_view_sprite, debug_view_overdraw: pso_debug_view_overdraw, debug_view_overdraw_sprite: pso_debug_view_overdraw_sprite, outline_mark: pso_outline_mark, outline_mark_sprite: pso_outline_mark_sprite, outline: pso_outline, outline_sprite: pso_outline_sprite, outline_line: pso_outline_line, pbr: pso_pbr, skybox: pso_skybox, equirect: pso_equirect, tonemap: pso_tonemap, depth_normal: pso_depth_normal, ssao: pso_ssao, ssao_blur: pso_ssao_blur, bloom: pso_bloom, fxaa: pso_fxaa, vignette: pso_vignette, grading: pso_grading })
         // Ok(PipelineStates {})
 
         todo!()
@@ -407,26 +1019,53 @@ pub struct DebugQuadHandle(froggy::Pointer<DebugQuad>);
 /// See [Window::render](struct.Window.html#method.render).
 pub struct Renderer {
     surface: wgpu::Surface<'static>,
+    surface_format: wgpu::TextureFormat,
     // device: back::Device,
     device: wgpu::Device,
+    queue: wgpu::Queue,
     // encoder: gfx::Encoder<back::Resources, back::CommandBuffer>,
     encoder: (),
     // factory: back::Factory,
     factory: (),
     // const_buf: h::Buffer<back::Resources, Globals>,
     const_buf: (),
+    /// Copy of `const_buf` bound to `Material::Custom` shaders.
+    globals_buf: wgpu::Buffer,
     // quad_buf: h::Buffer<back::Resources, QuadParams>,
     quad_buf: wgpu::Buffer,
+    clear_buf: wgpu::Buffer,
+    pick_buf: wgpu::Buffer,
+    outline_buf: wgpu::Buffer,
+    /// Vertices of `debug_draw`, grown as needed.
+    debug_buf: wgpu::Buffer,
+    debug_draw: debug::DebugDraw,
+    debug_view_buf: wgpu::Buffer,
+    vertex_lines_buf: wgpu::Buffer,
+    pick_targets: Option<pick::Targets>,
     // inst_buf: h::Buffer<back::Resources, Instance>,
     inst_buf: wgpu::Buffer,
     // light_buf: h::Buffer<back::Resources, LightParam>,
     light_buf: wgpu::Buffer,
+    /// `[offset, count]` into `light_indices_buf` for every cluster.
+    light_clusters_buf: wgpu::Buffer,
+    light_indices_buf: wgpu::Buffer,
+    /// Cluster bounds for the last used projection and aspect ratio.
+    cluster_grid: Option<(Projection, f32, ClusterGrid)>,
     pbr_buf: h::Buffer<back::Resources, PbrParams>,
+    tonemap_buf: wgpu::Buffer,
+    post_buf: wgpu::Buffer,
+    custom_post_buf: wgpu::Buffer,
     inst_buf: wgpu::Buffer,
     // out_color: h::RenderTargetView<back::Resources, ColorFormat>,
-    out_color: wgpu::Buffer,
+    out_color: wgpu::TextureView,
+    /// Texture behind `out_color`, the surface is configured with
+    /// `TextureUsages::COPY_SRC` so it can be read back.
+    out_texture: wgpu::Texture,
     // out_depth: h::DepthStencilView<back::Resources, DepthFormat>,
-    out_depth: wgpu::Buffer,
+    out_depth: wgpu::TextureView,
+    /// Depth aspect of `out_depth`, bound by render graph passes reading
+    /// `Resource::Depth`.
+    out_depth_sampled: wgpu::TextureView,
     // displacement_contributions_buf: gfx::handle::Buffer<back::Resources, DisplacementContribution>,
     displacement_contributions_buf: wgpu::Buffer,
     // default_joint_buffer_view: gfx::handle::ShaderResourceView<back::Resources, [f32; 4]>,
@@ -437,13 +1076,77 @@ pub struct Renderer {
     pso: PipelineStates,
     map_default: Texture<[f32; 4]>,
     shadow_default: Texture<f32>,
+    /// Bound to `Material::Pbr` when `Scene::environment` is `None`.
+    environment_default: ibl::Environment,
+    /// Non-comparison sampler for reading raw shadow map depth (PCSS blocker search).
+    shadow_depth_sampler: wgpu::Sampler,
     debug_quads: froggy::Storage<DebugQuad>,
     size: glutin::dpi::LogicalSize,
     dpi: f64,
     font_cache: HashMap<String, Font>,
     instance_cache: HashMap<InstanceCacheKey, InstanceData>,
+    /// Bindings of the meshes with a custom material, by instance buffer.
+    custom_bindings: HashMap<wgpu::Buffer, custom::CustomBindings>,
+    /// Number of frames drawn, to release unused `custom_bindings`.
+    custom_frame: u64,
+    /// Offscreen color target the scene is rendered into, see `Renderer::offscreen`.
+    scene_target: Option<post::Target>,
+    post_targets: Option<post::Targets>,
+    ssao_buf: wgpu::Buffer,
+    ssao_targets: Option<ssao::Targets>,
+    /// Statistics of the frame being rendered.
+    recorder: stats::Recorder,
+    /// Statistics of the last finished frame.
+    stats: RenderStats,
+    /// Created after the first frame, if timestamps are supported.
+    gpu_timer: Option<stats::GpuTimer>,
+    /// Requests waiting for the next frame, see `Renderer::capture_frame`.
+    capture_requests: Vec<capture::Destination>,
+    /// Identity lookup table bound when no `PostEffect::ColorGrading` is drawn.
+    lut_default: post::Lut,
     /// `ShadowType` of this `Renderer`.
     pub shadow: ShadowType,
+    /// Render into a floating point target and tone map the result.
+    ///
+    /// Lets bright highlights and emissive surfaces exceed `1.0` without
+    /// clipping. The background is tone mapped along with the scene.
+    ///
+    /// Default: `false`.
+    pub hdr: bool,
+    /// Operator used to resolve the HDR target. Default: `ToneMapping::Aces`.
+    pub tone_mapping: ToneMapping,
+    /// Scale applied to scene colors before tone mapping. Default: `1.0`.
+    pub exposure: f32,
+    /// Post-processing effects, applied in order.
+    ///
+    /// `PostEffect::Bloom` is always applied before tone mapping.
+    ///
+    /// Default: empty.
+    pub post_effects: Vec<PostEffect>,
+    /// Screen-space ambient occlusion darkening the ambient light in creases.
+    ///
+    /// Default: `None`.
+    pub ssao: Option<ssao::Ssao>,
+    /// Replaces the output of every material with a mesh attribute.
+    ///
+    /// Default: `None`.
+    pub debug_view: Option<DebugView>,
+    /// UI text showing the [`stats`](#method.stats) of the previous frame.
+    ///
+    /// Create it with [`Factory::ui_text`](struct.Factory.html#method.ui_text)
+    /// to pick the font, position and color.
+    ///
+    /// Default: `None`.
+    pub stats_overlay: Option<Text>,
+    /// Passes drawing the window, user passes can be inserted between the
+    /// built-in ones.
+    ///
+    /// Default: only the built-in passes.
+    pub graph: graph::RenderGraph,
+    /// Set by [`watch_shaders`](#method.watch_shaders).
+    shader_watcher: Option<reload::ShaderWatcher>,
+    /// Directory of `shader_watcher`, shared with the `Factory`.
+    shader_directory: ShaderDirectory,
 }
 
 impl Renderer {
@@ -493,12 +1196,20 @@ impl Renderer {
         }
 
         self.size = size;
+        self.scene_target = None;
+        self.post_targets = None;
+        self.ssao_targets = None;
         gfx_window_glutin::update_views(window, &mut self.out_color, &mut self.out_depth);
+        (self.out_depth, self.out_depth_sampled) = target::window_depth(&self.device, self.physical_size());
     }
 
     pub(crate) fn dpi_change(&mut self, window: &glutin::WindowedContext<PossiblyCurrent>, dpi: f64) {
         self.dpi = dpi;
+        self.scene_target = None;
+        self.post_targets = None;
+        self.ssao_targets = None;
         gfx_window_glutin::update_views(window, &mut self.out_color, &mut self.out_depth);
+        (self.out_depth, self.out_depth_sampled) = target::window_depth(&self.device, self.physical_size());
     }
 
     /// Returns current viewport aspect ratio, i.e. width / height.
@@ -516,46 +1227,52 @@ impl Renderer {
 
     /// See [`Window::render`](struct.Window.html#method.render).
     pub fn render(&mut self, scene: &Scene, camera: &Camera) {
+        self.poll_shaders();
+        let size = self.physical_size();
+        let output = Output { color: self.out_color.clone(), format: self.surface_format, depth: self.out_depth.clone(), size, viewport: [0, 0, size[0], size[1]], clear: true, window: true, ui: true };
+        let frame = self.render_impl(scene, camera, output);
+
+        // draw ui text
+        for (_, font) in &self.font_cache {
+            font.draw(&mut self.encoder, &self.out_color, &self.out_depth);
+        }
+
+        // draw debug quads
+        self.debug_quads.sync_pending();
+        for quad in self.debug_quads.iter() {
+            let pos = [if quad.pos[0] >= 0 { quad.pos[0] } else { self.size.to_physical(self.dpi).width as i32 + quad.pos[0] - quad.size[0] }, if quad.pos[1] >= 0 { quad.pos[1] } else { self.size.to_physical(self.dpi).height as i32 + quad.pos[1] - quad.size[1] }];
+            let p0 = self.map_to_ndc([pos[0] as f32, pos[1] as f32]);
+            let p1 = self.map_to_ndc([(pos[0] + quad.size[0]) as f32, (pos[1] + quad.size[1]) as f32]);
+            self.encoder.update_constant_buffer(&self.quad_buf, &QuadParams { rect: [p0.x, p0.y, p1.x, p1.y], depth: -1.0 });
+            let data = quad_pipe::Data { params: self.quad_buf.clone(), globals: self.const_buf.clone(), resource: quad.resource.clone(), sampler: self.map_default.to_param().1, target: self.out_color.clone(), depth_target: self.out_depth.clone() };
+            self.encoder.draw(&QUAD_SLICE, &self.pso.quad, &data);
+        }
+
+        self.gpu_mark("ui");
+        self.run_passes(BuiltinPass::Ui, frame.as_ref());
+        self.encoder.flush(&mut self.device);
+        self.debug_draw.end_frame();
+        self.end_frame_stats();
+        if !self.capture_requests.is_empty() {
+            self.read_back_frame();
+        }
+        // deliver the captures of previous frames
+        let _ = self.device.poll(wgpu::PollType::Poll);
+    }
+
+    /// Renders `scene` into the given output, without the UI.
+    ///
+    /// Returns the targets of the user passes of `graph` if `output` is the window.
+    fn render_impl(&mut self, scene: &Scene, camera: &Camera, output: Output) -> Option<graph::Frame> {
         {
             use gfx::Device;
             self.device.cleanup();
         }
 
+        self.gpu_mark("idle");
         let mut hub = scene.hub.lock().unwrap();
         hub.process_messages();
-        // update joint transforms of skeletons
-        {
-            use node::TransformInternal;
-
-            struct SkeletonTemp {
-                inverse_world_transform: TransformInternal,
-                cpu_buffer: Vec<[f32; 4]>,
-                gpu_buffer: gfx::handle::Buffer<BackendResources, [f32; 4]>,
-            }
-
-            let mut skeletons = Vec::new();
-            for w in hub.walk(&scene.first_child) {
-                match w.node.sub_node {
-                    SubNode::Skeleton(ref skeleton) => {
-                        skeletons.push(SkeletonTemp { inverse_world_transform: w.world_transform.inverse_transform().unwrap(), cpu_buffer: vec![[0.0; 4]; skeleton.bones.len() * VECS_PER_BONE], gpu_buffer: skeleton.gpu_buffer.clone() });
-                    }
-                    SubNode::Bone { index, inverse_bind_matrix } => {
-                        let skel = skeletons.last_mut().unwrap();
-                        let mx_base = Matrix4::from(skel.inverse_world_transform.concat(&w.world_transform));
-                        let mx = (mx_base * Matrix4::from(inverse_bind_matrix)).transpose();
-                        let buf = &mut skel.cpu_buffer[index * VECS_PER_BONE .. (index + 1) * VECS_PER_BONE];
-                        buf[0] = mx.x.into();
-                        buf[1] = mx.y.into();
-                        buf[2] = mx.z.into();
-                    }
-                    _ => {}
-                }
-            }
-
-            for skel in skeletons {
-                self.encoder.update_buffer(&skel.gpu_buffer, &skel.cpu_buffer, 0).expect("upload to GPU target buffer");
-            }
-        }
+        self.update_skeletons(&hub, scene);
 
         // update dynamic meshes
         // Note: mutable node access here
@@ -571,7 +1288,7 @@ impl Renderer {
                 }
                 // Note: UI text currently applies to all the scenes.
                 // We may want to make it scene-dependent at some point.
-                SubNode::UiText(ref text) => {
+                SubNode::UiText(ref text) if output.ui => {
                     text.font.queue(&text.section);
                     if !self.font_cache.contains_key(&text.font.id) {
                         self.font_cache.insert(text.font.id.clone(), text.font.clone());
@@ -588,7 +1305,9 @@ impl Renderer {
             mx_view: Matrix4<f32>,
             mx_proj: Matrix4<f32>,
         }
+        // Lights without a range come first, as they are not binned into clusters.
         let mut lights = Vec::new();
+        let mut ranged_lights = Vec::new();
         let mut shadow_requests = Vec::new();
         let mut mx_camera_transform = hub[&camera].transform;
 
@@ -601,23 +1320,30 @@ impl Renderer {
                 SubNode::Light(ref light) => light,
                 _ => continue,
             };
-            if lights.len() == MAX_LIGHTS {
-                error!("Max number of lights ({}) reached", MAX_LIGHTS);
-                break;
-            }
-
-            let shadow_index = if let Some((ref map, ref projection)) = light.shadow {
-                let target = map.to_target();
-                let dim = target.get_dimensions();
-                let aspect = dim.0 as f32 / dim.1 as f32;
-                let mx_proj = match projection {
-                    &ShadowProjection::Orthographic(ref p) => p.matrix(aspect),
-                };
-                let mx_view = Matrix4::from(w.world_transform.inverse_transform().unwrap());
-                shadow_requests.push(ShadowRequest { target, resource: map.to_resource(), mx_view, mx_proj: mx_proj.into() });
-                shadow_requests.len() as i32 - 1
-            } else {
-                -1
+            let mut shadow_softness = 0.0;
+            let shadow_index = match light.shadow {
+                Some((ref map, ref projection)) if self.shadow != ShadowType::Off && shadow_requests.len() < MAX_SHADOWS => {
+                    let target = map.to_target();
+                    let dim = target.get_dimensions();
+                    let aspect = dim.0 as f32 / dim.1 as f32;
+                    let mx_proj = match projection {
+                        &ShadowProjection::Orthographic(ref p) => {
+                            // PCSS works in shadow map UV space.
+                            if let ShadowType::Pcss { light_size } = self.shadow {
+                                shadow_softness = light_size / (2.0 * p.extent_y);
+                            }
+                            p.matrix(aspect)
+                        }
+                    };
+                    let mx_view = Matrix4::from(w.world_transform.inverse_transform().unwrap());
+                    shadow_requests.push(ShadowRequest { target, resource: map.to_resource(), mx_view, mx_proj: mx_proj.into() });
+                    shadow_requests.len() as i32 - 1
+                }
+                Some(_) if self.shadow != ShadowType::Off => {
+                    error!("Max number of shadow-casting lights ({}) reached", MAX_SHADOWS);
+                    -1
+                }
+                _ => -1,
             };
 
             let mut color_back = 0;
@@ -634,7 +1360,7 @@ impl Renderer {
                     p = d.extend(0.0);
                     [light.intensity, 0.0, 0.0, 0.0]
                 }
-                SubLight::Point => [0.0, light.intensity, 0.0, 0.0],
+                SubLight::Point { range } => [0.0, light.intensity, range.unwrap_or(0.0), 0.0],
             };
             let projection = if shadow_index >= 0 {
                 let request = &shadow_requests[shadow_index as usize];
@@ -644,7 +1370,7 @@ impl Renderer {
                 [[0.0; 4]; 4]
             };
 
-            lights.push(LightParam {
+            let param = LightParam {
                 projection,
                 pos: p.into(),
                 dir: d.extend(0.0).into(),
@@ -658,19 +1384,27 @@ impl Renderer {
                     [rgb[0], rgb[1], rgb[2], 0.0]
                 },
                 intensity,
-                shadow_params: [shadow_index, 0, 0, 0],
-            });
+                shadow_params: [shadow_index, self.shadow.filter_index(), 0, 0],
+                shadow_bias: [light.shadow_bias.depth, light.shadow_bias.normal_offset, shadow_softness, 0.0],
+            };
+            match light.sub_light {
+                SubLight::Point { range: Some(range) } => ranged_lights.push((param, w.world_transform.disp, range)),
+                _ => lights.push(param),
+            }
         }
 
         // render shadow maps
         for request in &shadow_requests {
             self.encoder.clear_depth(&request.target, 1.0);
             let mx_vp = request.mx_proj * request.mx_view;
-            self.encoder.update_constant_buffer(&self.const_buf, &Globals { mx_vp: mx_vp.into(), mx_view: request.mx_view.into(), mx_inv_proj: request.mx_proj.into(), num_lights: 0 });
+            self.encoder.update_constant_buffer(&self.const_buf, &Globals { mx_vp: mx_vp.into(), mx_view: request.mx_view.into(), mx_inv_proj: request.mx_proj.into(), num_lights: 0, num_global_lights: 0, _padding0: [0; 2], cluster_dims: [0; 4], cluster_depth: [0.0; 4], environment: [0.0; 4], fog_color: [0.0; 4], fog_params: [0.0; 4], ssao: [0.0; 4] });
+
+            self.recorder.stats.shadow_passes += 1;
+            self.recorder.stats.buffer_uploads += 1;
 
             for w in hub.walk(&scene.first_child) {
-                let gpu_data = match w.node.sub_node {
-                    SubNode::Visual(_, ref data, _) => data,
+                let (material, gpu_data) = match w.node.sub_node {
+                    SubNode::Visual(ref material, ref data, _) => (material, data),
                     _ => continue,
                 };
                 let mx_world: mint::ColumnMatrix4<_> = Matrix4::from(w.world_transform).into();
@@ -678,8 +1412,11 @@ impl Renderer {
                 //TODO: avoid excessive cloning
                 let data = shadow_pipe::Data { vbuf: gpu_data.vertices.clone(), inst_buf: gpu_data.instances.clone(), cb_globals: self.const_buf.clone(), target: request.target.clone() };
                 self.encoder.draw(&gpu_data.slice, &self.pso.shadow, &data);
+                self.recorder.stats.buffer_uploads += 1;
+                self.recorder.draw(&self.pso.shadow as *const _ as *const (), stats::triangles(material, gpu_data.slice.end - gpu_data.slice.start), 1);
             }
         }
+        self.gpu_mark("shadows");
 
         // prepare target and globals
         let mx_view = Matrix4::from(mx_camera_transform.inverse_transform().unwrap());
@@ -687,17 +1424,94 @@ impl Renderer {
             SubNode::Camera(ref projection) => projection.clone(),
             _ => panic!("Camera had incorrect sub node"),
         };
-        let mx_proj = Matrix4::from(projection.matrix(self.aspect_ratio()));
-        self.encoder.update_constant_buffer(&self.const_buf, &Globals { mx_vp: (mx_proj * mx_view).into(), mx_view: mx_view.into(), mx_inv_proj: mx_proj.invert().unwrap().into(), num_lights: lights.len() as u32 });
-        self.encoder.update_buffer(&self.light_buf, &lights, 0).unwrap();
-
-        self.encoder.clear_depth(&self.out_depth, 1.0);
-        self.encoder.clear_stencil(&self.out_depth, 0);
+        let viewport = output.viewport;
+        let aspect_ratio = viewport[2] as f32 / viewport[3] as f32;
+        let mx_proj = Matrix4::from(projection.matrix(aspect_ratio));
+
+        // bin the lights with a finite range into view-space clusters
+        match self.cluster_grid {
+            Some((ref p, aspect, _)) if *p == projection && aspect == aspect_ratio => {}
+            _ => self.cluster_grid = Some((projection.clone(), aspect_ratio, ClusterGrid::new(&projection, aspect_ratio))),
+        }
+        let grid = &self.cluster_grid.as_ref().unwrap().2;
+        let num_global_lights = lights.len() as u32;
+        let mut local_lights = Vec::with_capacity(ranged_lights.len());
+        for (param, pos, range) in ranged_lights {
+            let center = (mx_view * pos.extend(1.0)).truncate();
+            local_lights.push(LocalLight { index: lights.len() as u32, center, radius: range });
+            lights.push(param);
+        }
+        let clusters = grid.bin(&local_lights);
+
+        let environment = scene.environment.as_ref().unwrap_or(&self.environment_default).clone();
+        let (fog_color, fog_params) = Self::fog_params(scene);
+        let size = output.size;
+        // SSAO and post-processing use targets of the window size
+        let ssao_settings = if output.window && self.debug_view.is_none() { self.ssao } else { None };
+        let ssao_params = match ssao_settings {
+            Some(_) => [1.0, 1.0 / size[0] as f32, 1.0 / size[1] as f32, 0.0],
+            None => [0.0; 4],
+        };
+        let globals = Globals { mx_vp: (mx_proj * mx_view).into(), mx_view: mx_view.into(), mx_inv_proj: mx_proj.invert().unwrap().into(), num_lights: lights.len() as u32, num_global_lights, _padding0: [0; 2], cluster_dims: [CLUSTER_DIMS[0], CLUSTER_DIMS[1], CLUSTER_DIMS[2], 0], cluster_depth: grid.depth_params(), environment: environment.params(), fog_color, fog_params, ssao: ssao_params };
+        self.encoder.update_constant_buffer(&self.const_buf, &globals);
+        Self::upload_buffer(&self.device, &self.queue, &mut self.globals_buf, "globals", wgpu::BufferUsages::UNIFORM, &[globals]);
+        Self::upload_storage(&self.device, &self.queue, &mut self.light_buf, "lights", &lights);
+        Self::upload_storage(&self.device, &self.queue, &mut self.light_clusters_buf, "light clusters", &clusters.cells);
+        Self::upload_storage(&self.device, &self.queue, &mut self.light_indices_buf, "light indices", &clusters.indices);
+        self.recorder.stats.buffer_uploads += 5;
+        let frustum = Frustum::from_matrix(mx_proj * mx_view);
+
+        // the scene goes into an offscreen target, if needed, and gets resolved below
+        let offscreen = output.window && self.offscreen() && self.debug_view.is_none();
+        let out_color = if offscreen { self.scene_view() } else { output.color.clone() };
+        let out_depth = output.depth.clone();
+
+        let mut frame = if output.window {
+            let scene_format = if offscreen { HDR_FORMAT } else { output.format };
+            Some(graph::Frame { scene_color: out_color.clone(), scene_format, depth: out_depth.clone(), depth_sampled: self.out_depth_sampled.clone(), output: output.color.clone(), output_format: output.format, ssao: self.map_default.to_param().0, size, viewport, mx_vp: mx_proj * mx_view })
+        } else {
+            None
+        };
+        self.run_passes(BuiltinPass::Shadows, frame.as_ref());
 
-        if let Background::Color(color) = scene.background {
-            let rgb = color::to_linear_rgb(color);
-            self.encoder.clear(&self.out_color, [rgb[0], rgb[1], rgb[2], 0.0]);
+        let ssao = match ssao_settings {
+            Some(ssao) => {
+                let view = self.ssao_pass(&ssao, &hub, scene, mx_proj, size, viewport);
+                self.gpu_mark("ssao");
+                (view, self.map_default.to_param().1)
+            }
+            None => self.map_default.to_param(),
+        };
+        if let Some(ref mut frame) = frame {
+            frame.ssao = ssao.0.clone();
         }
+        self.run_passes(BuiltinPass::Ssao, frame.as_ref());
+
+        self.encoder.set_viewport(viewport);
+        self.encoder.set_scissor_rect(viewport);
+        if output.clear {
+            let clear_color = match scene.background {
+                _ if self.debug_view.is_some() => Some([0.0, 0.0, 0.0, 1.0]),
+                Background::Color(color) => {
+                    let rgb = color::to_linear_rgb(color);
+                    Some([rgb[0], rgb[1], rgb[2], 0.0])
+                }
+                _ => None,
+            };
+            if viewport == [0, 0, size[0], size[1]] {
+                self.encoder.clear_depth(&out_depth, 1.0);
+                self.encoder.clear_stencil(&out_depth, 0);
+                if let Some(color) = clear_color {
+                    self.encoder.clear(&out_color, color);
+                }
+            } else {
+                // target clears ignore the viewport, draw over it instead
+                self.encoder.update_constant_buffer(&self.clear_buf, &ClearParams { color: clear_color.unwrap_or([0.0; 4]) });
+                let data = clear_pipe::Data { params: self.clear_buf.clone(), target: out_color.clone(), depth_target: (out_depth.clone(), (0, 0)) };
+                self.encoder.draw(&FULLSCREEN_SLICE, &self.pso.clear, &data);
+            }
+        }
+        self.run_passes(BuiltinPass::Clear, frame.as_ref());
 
         // render everything
         let (shadow_default, shadow_sampler) = self.shadow_default.to_param();
@@ -709,105 +1523,218 @@ impl Renderer {
             Some(ref request) => request.resource.clone(),
             None => shadow_default.clone(),
         };
+        // shadow mapping is compiled out of the frames without shadows
+        let shadow_features = if shadow_requests.is_empty() { Features::empty() } else { Features::SHADOWS };
+
+        if let Some(view) = self.debug_view {
+            self.debug_view_pass(&hub, scene, view, out_color.clone(), out_depth.clone());
+        } else {
+            // clear instance cache
+            for instances in self.instance_cache.values_mut() {
+                instances.list.clear();
+            }
+            self.begin_custom_frame();
 
-        // clear instance cache
-        for instances in self.instance_cache.values_mut() {
-            instances.list.clear();
-        }
-
-        for w in hub.walk(&scene.first_child) {
-            let (material, gpu_data, skeleton) = match w.node.sub_node {
-                SubNode::Visual(ref material, ref gpu_data, ref skeleton) => (material, gpu_data, skeleton),
-                _ => continue,
-            };
-
-            let mx_world: mint::ColumnMatrix4<_> = Matrix4::from(w.world_transform).into();
-            let pso_data = material.to_pso_data();
+            for w in hub.walk(&scene.first_child) {
+                let (material, gpu_data, skeleton) = match w.node.sub_node {
+                    SubNode::Visual(ref material, ref gpu_data, ref skeleton) => (material, gpu_data, skeleton),
+                    _ => continue,
+                };
 
-            let instance = match pso_data {
-                PsoData::Basic { color, map, param0 } => {
-                    let uv_range = match map {
-                        Some(ref map) => map.uv_range(),
-                        None => [0.0; 4],
-                    };
-                    if let Some(ref key) = gpu_data.instance_cache_key {
-                        let data = self.instance_cache.entry(key.clone()).or_insert_with(|| InstanceData { slice: gpu_data.slice.clone(), vertices: gpu_data.vertices.clone(), material: material.clone(), list: Vec::new() });
-                        data.list.push(Instance::basic(mx_world.into(), color, uv_range, param0));
-                        // Create a new instance and defer the draw call.
+                // posed meshes may leave their rest bounds
+                if let (Some(collider), None, None) = (&gpu_data.collider, skeleton, &gpu_data.displacements) {
+                    let (center, radius) = collider.bounding_sphere();
+                    if !frustum.intersects_sphere(w.world_transform.transform_point(center), radius * w.world_transform.scale) {
+                        self.recorder.stats.culled_objects += 1;
                         continue;
                     }
-                    Instance::basic(mx_world.into(), color, uv_range, param0)
-                }
-                PsoData::Pbr { .. } => Instance::pbr(mx_world.into()),
-            };
-            let joint_buffer_view = if let Some(ref ptr) = *skeleton {
-                match hub[ptr].sub_node {
-                    SubNode::Skeleton(ref skeleton_data) => skeleton_data.gpu_buffer_view.clone(),
-                    _ => unreachable!(),
                 }
-            } else {
-                self.default_joint_buffer_view.clone()
-            };
-            let displacement_view = match gpu_data.displacements {
-                Some((_, ref view)) => view.clone(),
-                None => self.default_displacement_buffer_view.clone(),
-            };
 
-            Self::render_mesh(&mut self.encoder, self.const_buf.clone(), gpu_data.instances.clone(), self.light_buf.clone(), self.pbr_buf.clone(), self.displacement_contributions_buf.clone(), self.out_color.clone(), self.out_depth.clone(), &self.pso, &self.map_default, &[instance], gpu_data.vertices.clone(), gpu_data.slice.clone(), &material, &shadow_sampler, &shadow0, &shadow1, &gpu_data.displacement_contributions, (displacement_view, self.map_default.to_param().1), joint_buffer_view, gpu_data.displacements.is_some());
-        }
+                let mx_world: mint::ColumnMatrix4<_> = Matrix4::from(w.world_transform).into();
+                if let Material::Custom(ref custom) = *material {
+                    self.encoder.update_buffer(&gpu_data.instances, &[Instance::pbr(mx_world.into())], 0).unwrap();
+                    self.custom_draw(custom, gpu_data, out_color.clone(), out_depth.clone(), output.format);
+                    self.recorder.stats.buffer_uploads += 2;
+                    self.recorder.draw(self.pso.main_by_material(material), stats::triangles(material, gpu_data.slice.end - gpu_data.slice.start), 1);
+                    continue;
+                }
+                let pso_data = material.to_pso_data();
+
+                let instanced = !matches!(pso_data, PsoData::Pbr { .. });
+                let instance = match pso_data {
+                    PsoData::Basic { color, map, param0, param1 } => {
+                        let uv_range = match map {
+                            Some(ref map) => map.uv_range(),
+                            None => [0.0; 4],
+                        };
+                        Instance::basic(mx_world.into(), color, uv_range, param0, param1)
+                    }
+                    PsoData::Toon { color, gradient_map, specular_threshold, rim_color, rim_power } => {
+                        let uv_range = match gradient_map {
+                            Some(ref map) => map.uv_range(),
+                            None => [0.0; 4],
+                        };
+                        Instance::toon(mx_world.into(), color, uv_range, specular_threshold, rim_color, rim_power)
+                    }
+                    PsoData::Pbr { .. } => Instance::pbr(mx_world.into()),
+                };
+                if let (Some(key), true) = (&gpu_data.instance_cache_key, instanced) {
+                    let data = self.instance_cache.entry(key.clone()).or_insert_with(|| InstanceData { slice: gpu_data.slice.clone(), vertices: gpu_data.vertices.clone(), material: material.clone(), list: Vec::new() });
+                    data.list.push(instance);
+                    // Create a new instance and defer the draw call.
+                    continue;
+                }
+                let joint_buffer_view = if let Some(ref ptr) = *skeleton {
+                    match hub[ptr].sub_node {
+                        SubNode::Skeleton(ref skeleton_data) => skeleton_data.gpu_buffer_view.clone(),
+                        _ => unreachable!(),
+                    }
+                } else {
+                    self.default_joint_buffer_view.clone()
+                };
+                let displacement_view = match gpu_data.displacements {
+                    Some((_, ref view)) => view.clone(),
+                    None => self.default_displacement_buffer_view.clone(),
+                };
+                let mut features = shadow_features;
+                features.set(Features::SKINNING, skeleton.is_some());
+                features.set(Features::MORPH_TARGETS, gpu_data.displacements.is_some());
+
+                Self::render_mesh(&mut self.encoder, self.const_buf.clone(), gpu_data.instances.clone(), self.light_buf.clone(), self.light_clusters_buf.clone(), self.light_indices_buf.clone(), self.pbr_buf.clone(), self.displacement_contributions_buf.clone(), out_color.clone(), out_depth.clone(), &self.device, &mut self.pso, &self.map_default, &[instance], gpu_data.vertices.clone(), gpu_data.slice.clone(), &material, &shadow_sampler, &self.shadow_depth_sampler, &shadow0, &shadow1, &environment, &ssao, &gpu_data.displacement_contributions, (displacement_view, self.map_default.to_param().1), joint_buffer_view, features);
+                self.recorder.stats.buffer_uploads += match *material {
+                    Material::Pbr(_) if gpu_data.displacements.is_some() => 3,
+                    Material::Pbr(_) => 2,
+                    _ => 1,
+                };
+                self.recorder.draw(self.pso.main_by_material(material), stats::triangles(material, gpu_data.slice.end - gpu_data.slice.start), 1);
+            }
 
-        // render instanced meshes
-        for data in self.instance_cache.values() {
-            if data.list.len() > self.inst_buf.len() {
-                self.inst_buf = self
-                    .factory
-                    .create_buffer(data.list.len(), gfx::buffer::Role::Vertex, gfx::memory::Usage::Dynamic, gfx::memory::Bind::TRANSFER_DST)
-                    // TODO: Better error handling
-                    .unwrap();
+            // render instanced meshes
+            for data in self.instance_cache.values() {
+                if data.list.len() > self.inst_buf.len() {
+                    self.inst_buf = self
+                        .factory
+                        .create_buffer(data.list.len(), gfx::buffer::Role::Vertex, gfx::memory::Usage::Dynamic, gfx::memory::Bind::TRANSFER_DST)
+                        // TODO: Better error handling
+                        .unwrap();
+                }
+                Self::render_mesh(&mut self.encoder, self.const_buf.clone(), self.inst_buf.clone(), self.light_buf.clone(), self.light_clusters_buf.clone(), self.light_indices_buf.clone(), self.pbr_buf.clone(), self.displacement_contributions_buf.clone(), out_color.clone(), out_depth.clone(), &self.device, &mut self.pso, &self.map_default, &data.list, data.vertices.clone(), data.slice.clone(), &data.material, &shadow_sampler, &self.shadow_depth_sampler, &shadow0, &shadow1, &environment, &ssao, &ZEROED_DISPLACEMENT_CONTRIBUTION, (self.default_displacement_buffer_view.clone(), self.map_default.to_param().1), self.default_joint_buffer_view.clone(), shadow_features);
+                self.recorder.stats.buffer_uploads += 1;
+                self.recorder.draw(self.pso.main_by_material(&data.material), stats::triangles(&data.material, data.slice.end - data.slice.start), data.list.len() as u32);
             }
-            Self::render_mesh(&mut self.encoder, self.const_buf.clone(), self.inst_buf.clone(), self.light_buf.clone(), self.pbr_buf.clone(), self.displacement_contributions_buf.clone(), self.out_color.clone(), self.out_depth.clone(), &self.pso, &self.map_default, &data.list, data.vertices.clone(), data.slice.clone(), &data.material, &shadow_sampler, &shadow0, &shadow1, &ZEROED_DISPLACEMENT_CONTRIBUTION, (self.default_displacement_buffer_view.clone(), self.map_default.to_param().1), self.default_joint_buffer_view.clone(), false);
         }
-
-        let quad_slice = gfx::Slice { start: 0, end: 4, base_vertex: 0, instances: None, buffer: gfx::IndexBuffer::Auto };
+        self.run_passes(BuiltinPass::Main, frame.as_ref());
 
         // draw background (if any)
         match scene.background {
+            _ if self.debug_view.is_some() => {}
             Background::Texture(ref texture) => {
                 // TODO: Reduce code duplication (see drawing debug quads)
                 self.encoder.update_constant_buffer(&self.quad_buf, &QuadParams { rect: [-1.0, -1.0, 1.0, 1.0], depth: 1.0 });
-                let data = quad_pipe::Data { params: self.quad_buf.clone(), globals: self.const_buf.clone(), resource: texture.to_param().0.raw().clone(), sampler: texture.to_param().1, target: self.out_color.clone(), depth_target: self.out_depth.clone() };
-                self.encoder.draw(&quad_slice, &self.pso.quad, &data);
+                let data = quad_pipe::Data { params: self.quad_buf.clone(), globals: self.const_buf.clone(), resource: texture.to_param().0.raw().clone(), sampler: texture.to_param().1, target: out_color.clone(), depth_target: out_depth.clone() };
+                self.encoder.draw(&QUAD_SLICE, &self.pso.quad, &data);
             }
             Background::Skybox(ref cubemap) => {
                 self.encoder.update_constant_buffer(&self.quad_buf, &QuadParams { rect: [-1.0, -1.0, 1.0, 1.0], depth: 1.0 });
-                let data = quad_pipe::Data { params: self.quad_buf.clone(), resource: cubemap.to_param().0.raw().clone(), sampler: cubemap.to_param().1, globals: self.const_buf.clone(), target: self.out_color.clone(), depth_target: self.out_depth.clone() };
-                self.encoder.draw(&quad_slice, &self.pso.skybox, &data);
+                let data = quad_pipe::Data { params: self.quad_buf.clone(), resource: cubemap.to_param().0.raw().clone(), sampler: cubemap.to_param().1, globals: self.const_buf.clone(), target: out_color.clone(), depth_target: out_depth.clone() };
+                self.encoder.draw(&QUAD_SLICE, &self.pso.skybox, &data);
+            }
+            Background::Equirectangular(ref texture) => {
+                self.encoder.update_constant_buffer(&self.quad_buf, &QuadParams { rect: [-1.0, -1.0, 1.0, 1.0], depth: 1.0 });
+                let data = quad_pipe::Data { params: self.quad_buf.clone(), resource: texture.to_param().0.raw().clone(), sampler: texture.to_param().1, globals: self.const_buf.clone(), target: out_color.clone(), depth_target: out_depth.clone() };
+                self.encoder.draw(&QUAD_SLICE, &self.pso.equirect, &data);
             }
             Background::Color(_) => {}
         }
+        self.run_passes(BuiltinPass::Background, frame.as_ref());
 
-        // draw ui text
-        for (_, font) in &self.font_cache {
-            font.draw(&mut self.encoder, &self.out_color, &self.out_depth);
+        self.encoder.set_viewport([0, 0, size[0], size[1]]);
+        self.encoder.set_scissor_rect([0, 0, size[0], size[1]]);
+        self.gpu_mark("main");
+
+        // apply post-processing and resolve into the back buffer
+        if offscreen {
+            self.post_process(out_color.clone(), size);
+            self.gpu_mark("post");
         }
+        self.run_passes(BuiltinPass::Post, frame.as_ref());
+
+        // outlines keep their color, regardless of tone mapping and effects
+        self.encoder.set_viewport(viewport);
+        self.encoder.set_scissor_rect(viewport);
+        self.outline_pass(&hub, scene, output.color.clone(), out_depth.clone(), size);
+        self.debug_pass(&hub, scene, output.color.clone(), out_depth);
+        self.encoder.set_viewport([0, 0, size[0], size[1]]);
+        self.encoder.set_scissor_rect([0, 0, size[0], size[1]]);
+        self.gpu_mark("overlays");
+        self.run_passes(BuiltinPass::Overlays, frame.as_ref());
+        frame
+    }
 
-        // draw debug quads
-        self.debug_quads.sync_pending();
-        for quad in self.debug_quads.iter() {
-            let pos = [if quad.pos[0] >= 0 { quad.pos[0] } else { self.size.to_physical(self.dpi).width as i32 + quad.pos[0] - quad.size[0] }, if quad.pos[1] >= 0 { quad.pos[1] } else { self.size.to_physical(self.dpi).height as i32 + quad.pos[1] - quad.size[1] }];
-            let p0 = self.map_to_ndc([pos[0] as f32, pos[1] as f32]);
-            let p1 = self.map_to_ndc([(pos[0] + quad.size[0]) as f32, (pos[1] + quad.size[1]) as f32]);
-            self.encoder.update_constant_buffer(&self.quad_buf, &QuadParams { rect: [p0.x, p0.y, p1.x, p1.y], depth: -1.0 });
-            let data = quad_pipe::Data { params: self.quad_buf.clone(), globals: self.const_buf.clone(), resource: quad.resource.clone(), sampler: self.map_default.to_param().1, target: self.out_color.clone(), depth_target: self.out_depth.clone() };
-            self.encoder.draw(&quad_slice, &self.pso.quad, &data);
+    /// Uploads the joint transforms of all skeletons in `scene`.
+    fn update_skeletons(&mut self, hub: &Hub, scene: &Scene) {
+        use node::TransformInternal;
+
+        struct SkeletonTemp {
+            inverse_world_transform: TransformInternal,
+            cpu_buffer: Vec<[f32; 4]>,
+            gpu_buffer: gfx::handle::Buffer<BackendResources, [f32; 4]>,
         }
 
-        self.encoder.flush(&mut self.device);
+        let mut skeletons = Vec::new();
+        for w in hub.walk(&scene.first_child) {
+            match w.node.sub_node {
+                SubNode::Skeleton(ref skeleton) => {
+                    skeletons.push(SkeletonTemp { inverse_world_transform: w.world_transform.inverse_transform().unwrap(), cpu_buffer: vec![[0.0; 4]; skeleton.bones.len() * VECS_PER_BONE], gpu_buffer: skeleton.gpu_buffer.clone() });
+                }
+                SubNode::Bone { index, inverse_bind_matrix } => {
+                    let skel = skeletons.last_mut().unwrap();
+                    let mx_base = Matrix4::from(skel.inverse_world_transform.concat(&w.world_transform));
+                    let mx = (mx_base * Matrix4::from(inverse_bind_matrix)).transpose();
+                    let buf = &mut skel.cpu_buffer[index * VECS_PER_BONE .. (index + 1) * VECS_PER_BONE];
+                    buf[0] = mx.x.into();
+                    buf[1] = mx.y.into();
+                    buf[2] = mx.z.into();
+                }
+                _ => {}
+            }
+        }
+
+        for skel in skeletons {
+            self.encoder.update_buffer(&skel.gpu_buffer, &skel.cpu_buffer, 0).expect("upload to GPU target buffer");
+            self.recorder.stats.buffer_uploads += 1;
+        }
+    }
+
+    /// Returns the joint transforms and morph targets to bind for a visual node,
+    /// along with `DISPLACEMENT_BUFFER` if the morph targets apply.
+    ///
+    /// As in `render_mesh`, only PBR meshes are skinned and morphed.
+    fn pose_bindings(&mut self, hub: &Hub, material: &Material, gpu_data: &GpuData, skeleton: &Option<Skeleton>) -> (h::ShaderResourceView<back::Resources, [f32; 4]>, h::ShaderResourceView<back::Resources, [f32; 4]>, i32) {
+        let pbr = match *material {
+            Material::Pbr(_) => true,
+            _ => false,
+        };
+        let joint_transforms = match *skeleton {
+            Some(ref ptr) if pbr => match hub[ptr].sub_node {
+                SubNode::Skeleton(ref skeleton_data) => skeleton_data.gpu_buffer_view.clone(),
+                _ => unreachable!(),
+            },
+            _ => self.default_joint_buffer_view.clone(),
+        };
+        match gpu_data.displacements {
+            Some((_, ref view)) if pbr => {
+                let contributions = &gpu_data.displacement_contributions[.. gpu_data.displacement_contributions.len().min(MAX_TARGETS)];
+                self.encoder.update_buffer(&self.displacement_contributions_buf, contributions, 0).unwrap();
+                (joint_transforms, view.clone(), DISPLACEMENT_BUFFER)
+            }
+            _ => (joint_transforms, self.default_displacement_buffer_view.clone(), 0),
+        }
     }
 
     //TODO: make it generic over `gfx::Resources`
     #[inline]
-    fn render_mesh(encoder: &mut gfx::Encoder<back::Resources, back::CommandBuffer>, const_buf: h::Buffer<back::Resources, Globals>, inst_buf: h::Buffer<back::Resources, Instance>, light_buf: h::Buffer<back::Resources, LightParam>, pbr_buf: h::Buffer<back::Resources, PbrParams>, displacement_contributions_buf: h::Buffer<back::Resources, DisplacementContribution>, out_color: h::RenderTargetView<back::Resources, ColorFormat>, out_depth: h::DepthStencilView<back::Resources, DepthFormat>, pso: &PipelineStates<back::Resources>, map_default: &Texture<[f32; 4]>, instances: &[Instance], vertex_buf: h::Buffer<back::Resources, Vertex>, mut slice: gfx::Slice<back::Resources>, material: &Material, shadow_sampler: &h::Sampler<back::Resources>, shadow0: &h::ShaderResourceView<back::Resources, f32>, shadow1: &h::ShaderResourceView<back::Resources, f32>, displacement_contributions: &[DisplacementContribution], displacements: (h::ShaderResourceView<back::Resources, [f32; 4]>, h::Sampler<back::Resources>), joint_transform_buffer_view: h::ShaderResourceView<back::Resources, [f32; 4]>, displace: bool) {
+    fn render_mesh(encoder: &mut gfx::Encoder<back::Resources, back::CommandBuffer>, const_buf: h::Buffer<back::Resources, Globals>, inst_buf: h::Buffer<back::Resources, Instance>, light_buf: wgpu::Buffer, light_clusters_buf: wgpu::Buffer, light_indices_buf: wgpu::Buffer, pbr_buf: h::Buffer<back::Resources, PbrParams>, displacement_contributions_buf: h::Buffer<back::Resources, DisplacementContribution>, out_color: h::RenderTargetView<back::Resources, ColorFormat>, out_depth: h::DepthStencilView<back::Resources, DepthFormat>, device: &wgpu::Device, pso: &mut PipelineStates<back::Resources>, map_default: &Texture<[f32; 4]>, instances: &[Instance], vertex_buf: h::Buffer<back::Resources, Vertex>, mut slice: gfx::Slice<back::Resources>, material: &Material, shadow_sampler: &h::Sampler<back::Resources>, shadow_depth_sampler: &wgpu::Sampler, shadow0: &h::ShaderResourceView<back::Resources, f32>, shadow1: &h::ShaderResourceView<back::Resources, f32>, environment: &ibl::Environment, ssao: &(wgpu::TextureView, wgpu::Sampler), displacement_contributions: &[DisplacementContribution], displacements: (h::ShaderResourceView<back::Resources, [f32; 4]>, h::Sampler<back::Resources>), joint_transform_buffer_view: h::ShaderResourceView<back::Resources, [f32; 4]>, features: Features) {
         encoder.update_buffer(&inst_buf, instances, 0).unwrap();
 
         if instances.len() > 1 {
@@ -816,8 +1743,13 @@ impl Renderer {
 
         //TODO: batch per PSO
         match material.to_pso_data() {
-            PsoData::Pbr { maps, mut params } => {
-                if displace {
+            PsoData::Pbr { maps, params, features: map_features } => {
+                let state = material.state().cloned().unwrap_or_default();
+                let pipeline = match pso.pbr.get(device, features | map_features, &state) {
+                    Some(pipeline) => pipeline,
+                    None => return,
+                };
+                if features.contains(Features::MORPH_TARGETS) {
                     let data = if displacement_contributions.len() > MAX_TARGETS {
                         error!("Too many mesh targets ({})!", displacement_contributions.len());
                         &displacement_contributions[.. MAX_TARGETS]
@@ -825,21 +1757,76 @@ impl Renderer {
                         displacement_contributions
                     };
                     encoder.update_buffer(&displacement_contributions_buf, data, 0).unwrap();
-                    params.pbr_flags |= PbrFlags::DISPLACEMENT_BUFFER.bits();
                 }
                 encoder.update_constant_buffer(&pbr_buf, &params);
                 let map_params = maps.into_params(map_default);
-                let data = pbr_pipe::Data { vbuf: vertex_buf, inst_buf, globals: const_buf, lights: light_buf, params: pbr_buf, base_color_map: map_params.base_color, normal_map: map_params.normal, emissive_map: map_params.emissive, metallic_roughness_map: map_params.metallic_roughness, occlusion_map: map_params.occlusion, color_target: out_color, depth_target: out_depth, displacement_contributions: displacement_contributions_buf, displacements, joint_transforms: joint_transform_buffer_view };
-                encoder.draw(&slice, &pso.pbr, &data);
+                let data = pbr_pipe::Data { vbuf: vertex_buf, inst_buf, globals: const_buf, lights: light_buf, light_clusters: light_clusters_buf, light_indices: light_indices_buf, params: pbr_buf, base_color_map: map_params.base_color, normal_map: map_params.normal, emissive_map: map_params.emissive, metallic_roughness_map: map_params.metallic_roughness, occlusion_map: map_params.occlusion, irradiance_map: environment.irradiance.to_param(), specular_map: environment.specular.to_param(), brdf_lut: environment.brdf_lut.to_param(), ssao: ssao.clone(), color_target: out_color, depth_target: out_depth, displacement_contributions: displacement_contributions_buf, displacements, joint_transforms: joint_transform_buffer_view };
+                encoder.draw(&slice, pipeline, &data);
             }
-            PsoData::Basic { map, .. } => {
+            PsoData::Basic { map, .. } | PsoData::Toon { gradient_map: map, .. } => {
+                let pipeline = match pso.basic_by_material(device, material, features) {
+                    Some(pipeline) => pipeline,
+                    None => return,
+                };
                 //TODO: avoid excessive cloning
-                let data = basic_pipe::Data { vbuf: vertex_buf, inst_buf, cb_lights: light_buf, cb_globals: const_buf.clone(), tex_map: map.unwrap_or(map_default.clone()).to_param(), shadow_map0: (shadow0.clone(), shadow_sampler.clone()), shadow_map1: (shadow1.clone(), shadow_sampler.clone()), out_color, out_depth: (out_depth, (0, 0)) };
-                encoder.draw(&slice, pso.pso_by_material(&material), &data);
+                let data = basic_pipe::Data { vbuf: vertex_buf, inst_buf, cb_lights: light_buf, light_clusters: light_clusters_buf, light_indices: light_indices_buf, cb_globals: const_buf.clone(), tex_map: map.unwrap_or(map_default.clone()).to_param(), shadow_map0: (shadow0.clone(), shadow_sampler.clone()), shadow_map1: (shadow1.clone(), shadow_sampler.clone()), shadow_depth0: (shadow0.clone(), shadow_depth_sampler.clone()), shadow_depth1: (shadow1.clone(), shadow_depth_sampler.clone()), ssao: ssao.clone(), out_color, out_depth: (out_depth, (0, 0)) };
+                encoder.draw(&slice, pipeline, &data);
             }
         }
     }
 
+    /// Fog uniforms of the scene, has to match `u_FogColor` and `u_FogParams` in `globals.glsl`.
+    fn fog_params(scene: &Scene) -> ([f32; 4], [f32; 4]) {
+        let (mode, color, distance) = match scene.fog {
+            Some(ref fog) => fog.params(),
+            None => return ([0.0; 4], [0.0; 4]),
+        };
+        let color = match scene.background {
+            Background::Color(background) if scene.fog_blend_background => background,
+            _ => color,
+        };
+        let rgb = color::to_linear_rgb(color);
+        let (base, falloff) = match scene.fog_height {
+            Some(ref height) => (height.base, height.falloff),
+            None => (0.0, 0.0),
+        };
+        ([rgb[0], rgb[1], rgb[2], mode as f32], [distance[0], distance[1], base, falloff])
+    }
+
+    /// Whether the scene has to be rendered into an offscreen target.
+    fn offscreen(&self) -> bool {
+        self.hdr || !self.post_effects.is_empty()
+    }
+
+    fn physical_size(&self) -> [u32; 2] {
+        let physical = self.size.to_physical(self.dpi);
+        [physical.width as u32, physical.height as u32]
+    }
+
+    /// Returns the offscreen scene color target, (re)creating it to match the back buffer size.
+    fn scene_view(&mut self) -> wgpu::TextureView {
+        let size = self.physical_size();
+        match self.scene_target {
+            Some(ref target) if target.size == size => {}
+            _ => self.scene_target = Some(post::Target::new(&self.device, size, "scene color")),
+        }
+        self.scene_target.as_ref().unwrap().view.clone()
+    }
+
+    /// Uploads `data` into a storage buffer, growing the buffer when needed.
+    fn upload_storage<T: bytemuck::Pod>(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &mut wgpu::Buffer, label: &str, data: &[T]) {
+        Self::upload_buffer(device, queue, buffer, label, wgpu::BufferUsages::STORAGE, data);
+    }
+
+    /// Writes `data` into `buffer`, recreating it with the given usage if it's too small.
+    fn upload_buffer<T: bytemuck::Pod>(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &mut wgpu::Buffer, label: &str, usage: wgpu::BufferUsages, data: &[T]) {
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        if bytes.len() as u64 > buffer.size() {
+            *buffer = device.create_buffer(&wgpu::BufferDescriptor { label: Some(label), size: (bytes.len() as u64).next_power_of_two(), usage: usage | wgpu::BufferUsages::COPY_DST, mapped_at_creation: false });
+        }
+        queue.write_buffer(buffer, 0, bytes);
+    }
+
     /// Draw [`ShadowMap`](struct.ShadowMap.html) for debug purposes.
     pub fn debug_shadow_quad(&mut self, map: &ShadowMap, _num_components: u8, pos: [i16; 2], size: [u16; 2]) -> DebugQuadHandle {
         DebugQuadHandle(self.debug_quads.create(DebugQuad { resource: map.to_resource().raw().clone(), pos: [pos[0] as i32, pos[1] as i32], size: [size[0] as i32, size[1] as i32] }))
@@ -0,0 +1,75 @@
+//! Highlight outlines, see [`Object::set_highlight`](../object/trait.Object.html#method.set_highlight).
+//!
+//! Highlighted nodes are first drawn into the stencil buffer only. They are
+//! then drawn again, pushed outwards on screen by the outline width, where
+//! the stencil is not set, which leaves a band around the silhouette.
+
+use cgmath::Matrix4;
+
+use crate::color;
+use crate::hub::{Hub, SubNode};
+use crate::material::Material;
+use crate::scene::Scene;
+
+use super::{outline_pipe, Instance, OutlineParams, PipelineStates, Renderer};
+
+/// Expand away from the object origin instead of along the normals.
+const RADIAL: i32 = 1 << 0;
+
+impl PipelineStates {
+    fn outline_by_material(&self, material: &Material, mark: bool) -> &gfx::PipelineState<back::Resources, outline_pipe::Meta> {
+        match (material, mark) {
+            (&Material::Sprite(_), true) => &self.outline_mark_sprite,
+            (&Material::Sprite(_), false) => &self.outline_sprite,
+            (&Material::Line(_), false) => &self.outline_line,
+            (_, true) => &self.outline_mark,
+            (_, false) => &self.outline,
+        }
+    }
+}
+
+impl Renderer {
+    /// Outlines the visible highlighted nodes of `scene`.
+    ///
+    /// Expects the globals of the camera in `const_buf`, and draws over the
+    /// stencil of `out_depth`.
+    pub(super) fn outline_pass(&mut self, hub: &Hub, scene: &Scene, out_color: wgpu::TextureView, out_depth: wgpu::TextureView, size: [u32; 2]) {
+        let mut highlighted = Vec::new();
+        for w in hub.walk(&scene.first_child) {
+            if let (Some(highlight), SubNode::Visual(..)) = (w.node.highlight, &w.node.sub_node) {
+                highlighted.push((w.node_ptr.clone(), w.world_transform, highlight));
+            }
+        }
+        if highlighted.is_empty() {
+            return;
+        }
+
+        // mark all the nodes first, so that outlines don't cover other highlighted nodes
+        for &mark in &[true, false] {
+            for &(ref ptr, world_transform, highlight) in &highlighted {
+                let (material, gpu_data, skeleton) = match hub.nodes[ptr].sub_node {
+                    SubNode::Visual(ref material, ref gpu_data, ref skeleton) => (material, gpu_data, skeleton),
+                    _ => unreachable!(),
+                };
+                let (joint_transforms, displacements, mut flags) = self.pose_bindings(hub, material, gpu_data, skeleton);
+                let extent = match *material {
+                    // lines don't enclose anything to mark, they are recolored instead
+                    Material::Line(_) if mark => continue,
+                    Material::Line(_) => [0.0; 4],
+                    _ if mark => [0.0; 4],
+                    _ => [2.0 * highlight.width / size[0] as f32, 2.0 * highlight.width / size[1] as f32, size[0] as f32, size[1] as f32],
+                };
+                if let Material::Sprite(_) = *material {
+                    flags |= RADIAL;
+                }
+                let rgb = color::to_linear_rgb(highlight.color);
+
+                let mx_world: mint::ColumnMatrix4<_> = Matrix4::from(world_transform).into();
+                self.encoder.update_buffer(&gpu_data.instances, &[Instance::pbr(mx_world.into())], 0).unwrap();
+                self.encoder.update_constant_buffer(&self.outline_buf, &OutlineParams { color: [rgb[0], rgb[1], rgb[2], 1.0], extent, flags, _padding0: [0; 3] });
+                let data = outline_pipe::Data { vbuf: gpu_data.vertices.clone(), inst_buf: gpu_data.instances.clone(), globals: self.const_buf.clone(), params: self.outline_buf.clone(), displacement_contributions: self.displacement_contributions_buf.clone(), joint_transforms, displacements: (displacements, self.map_default.to_param().1), target: out_color.clone(), depth_target: (out_depth.clone(), (1, 1)) };
+                self.encoder.draw(&gpu_data.slice, self.pso.outline_by_material(material, mark), &data);
+            }
+        }
+    }
+}
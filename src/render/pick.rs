@@ -0,0 +1,186 @@
+//! Object picking through an ID buffer.
+//!
+//! The scene is rendered into single pixel targets holding the ID and the
+//! depth of the closest node, with a projection zoomed into the pixel under
+//! the cursor. The result is read back synchronously.
+
+use std::sync::mpsc;
+
+use cgmath::{Matrix4, SquareMatrix, Vector4};
+
+use crate::camera::Camera;
+use crate::hub::SubNode;
+use crate::material::Material;
+use crate::object::Base;
+use crate::scene::Scene;
+
+use super::{pick_pipe, Globals, Instance, PickParams, PipelineStates, Renderer};
+
+/// Format of the node ID target, `0` marks the background.
+const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+/// Format of the target keeping the depth of the picked fragment.
+const DEPTH_VALUE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Result of [`Renderer::pick`](struct.Renderer.html#method.pick).
+#[derive(Clone, Debug)]
+pub struct Pick {
+    /// The node under the cursor.
+    pub object: Base,
+    /// World space position of the picked surface.
+    pub position: mint::Point3<f32>,
+    /// Distance from the camera to the picked surface along the view direction.
+    pub depth: f32,
+}
+
+/// Single pixel targets of the picking pass.
+pub(crate) struct Targets {
+    id: wgpu::Texture,
+    depth_value: wgpu::Texture,
+    depth: wgpu::TextureView,
+    /// Receives both pixels, each in its own aligned row.
+    readback: wgpu::Buffer,
+}
+
+impl Targets {
+    fn new(device: &wgpu::Device) -> Self {
+        let create = |label: &str, format: wgpu::TextureFormat, usage: wgpu::TextureUsages| device.create_texture(&wgpu::TextureDescriptor { label: Some(label), size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 }, mip_level_count: 1, sample_count: 1, dimension: wgpu::TextureDimension::D2, format, usage, view_formats: &[] });
+        let usage = wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC;
+        Targets {
+            id: create("pick id", ID_FORMAT, usage),
+            depth_value: create("pick depth value", DEPTH_VALUE_FORMAT, usage),
+            depth: create("pick depth", DEPTH_FORMAT, wgpu::TextureUsages::RENDER_ATTACHMENT).create_view(&wgpu::TextureViewDescriptor::default()),
+            readback: device.create_buffer(&wgpu::BufferDescriptor { label: Some("pick readback"), size: 2 * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64, usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ, mapped_at_creation: false }),
+        }
+    }
+}
+
+impl PipelineStates {
+    fn pick_by_material(&self, material: &Material) -> &gfx::PipelineState<back::Resources, pick_pipe::Meta> {
+        match *material {
+            Material::Line(_) => &self.pick_line,
+            Material::Wireframe(_) => &self.pick_wireframe,
+            Material::Sprite(_) => &self.pick_sprite,
+            _ => &self.pick,
+        }
+    }
+}
+
+/// Projection restricted to the pixel at `ndc`, stretching it over the whole target.
+fn pixel_matrix(ndc: mint::Point2<f32>, size: [u32; 2]) -> Matrix4<f32> {
+    let (sx, sy) = (size[0] as f32, size[1] as f32);
+    Matrix4::new(sx, 0.0, 0.0, 0.0, 0.0, sy, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, -sx * ndc.x, -sy * ndc.y, 0.0, 1.0)
+}
+
+impl Renderer {
+    /// Returns the visible node of `scene` under `screen_pos` as seen by `camera`,
+    /// if any.
+    ///
+    /// `screen_pos` is in pixels from the top left corner of the window, like
+    /// [`Input::mouse_pos`](struct.Input.html#method.mouse_pos), and the camera is
+    /// assumed to cover the whole window. Invisible nodes are skipped, skinned and
+    /// morphed meshes are posed like in [`render`](#method.render).
+    ///
+    /// This waits for the GPU, so it's best called once per click rather than
+    /// every frame.
+    pub fn pick<P: Into<mint::Point2<f32>>>(&mut self, scene: &Scene, camera: &Camera, screen_pos: P) -> Option<Pick> {
+        let size = self.physical_size();
+        let screen_pos = screen_pos.into();
+        // center of the pixel, so the depth matches the main pass
+        let pixel = [screen_pos.x.floor().max(0.0).min(size[0] as f32 - 1.0), screen_pos.y.floor().max(0.0).min(size[1] as f32 - 1.0)];
+        let ndc = self.map_to_ndc([pixel[0] + 0.5, pixel[1] + 0.5]);
+
+        let mut hub = scene.hub.lock().unwrap();
+        hub.process_messages();
+        self.update_skeletons(&hub, scene);
+        let mut mx_camera_transform = hub[&camera].transform;
+        for w in hub.walk(&scene.first_child) {
+            if w.node as *const _ == &hub[&camera] as *const _ {
+                mx_camera_transform = w.world_transform;
+            }
+        }
+        let mx_view = Matrix4::from(mx_camera_transform.inverse_transform().unwrap());
+        let mx_proj = match hub[&camera].sub_node {
+            SubNode::Camera(ref projection) => Matrix4::from(projection.matrix(size[0] as f32 / size[1] as f32)),
+            _ => panic!("Camera had incorrect sub node"),
+        };
+        // a degenerate projection can't map the depth back to a position
+        let mx_inv_proj = mx_proj.invert()?;
+        let mx_inv_vp = (mx_proj * mx_view).invert()?;
+        let mx_pick = pixel_matrix(ndc, size) * mx_proj * mx_view;
+        let targets = match self.pick_targets.take() {
+            Some(targets) => targets,
+            None => Targets::new(&self.device),
+        };
+        self.encoder.update_constant_buffer(&self.const_buf, &Globals { mx_vp: mx_pick.into(), mx_view: mx_view.into(), mx_inv_proj: mx_inv_proj.into(), num_lights: 0, num_global_lights: 0, _padding0: [0; 2], cluster_dims: [0; 4], cluster_depth: [0.0; 4], environment: [0.0; 4], fog_color: [0.0; 4], fog_params: [0.0; 4], ssao: [0.0; 4] });
+
+        let id_view = targets.id.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_value_view = targets.depth_value.create_view(&wgpu::TextureViewDescriptor::default());
+        self.encoder.clear(&id_view, [0; 4]);
+        self.encoder.clear(&depth_value_view, [1.0; 4]);
+        self.encoder.clear_depth(&targets.depth, 1.0);
+
+        // IDs are indices into `nodes` plus one
+        let mut nodes = Vec::new();
+        for w in hub.walk(&scene.first_child) {
+            let (material, gpu_data, skeleton) = match w.node.sub_node {
+                SubNode::Visual(ref material, ref gpu_data, ref skeleton) => (material, gpu_data, skeleton),
+                _ => continue,
+            };
+            nodes.push(w.node_ptr.clone());
+
+            let (joint_transforms, displacements, flags) = self.pose_bindings(&hub, material, gpu_data, skeleton);
+
+            let mx_world: mint::ColumnMatrix4<_> = Matrix4::from(w.world_transform).into();
+            self.encoder.update_buffer(&gpu_data.instances, &[Instance::pbr(mx_world.into())], 0).unwrap();
+            self.encoder.update_constant_buffer(&self.pick_buf, &PickParams { id: nodes.len() as u32, flags, _padding0: [0; 2] });
+            let data = pick_pipe::Data { vbuf: gpu_data.vertices.clone(), inst_buf: gpu_data.instances.clone(), globals: self.const_buf.clone(), params: self.pick_buf.clone(), displacement_contributions: self.displacement_contributions_buf.clone(), joint_transforms, displacements: (displacements, self.map_default.to_param().1), id_target: id_view.clone(), depth_value_target: depth_value_view.clone(), depth_target: targets.depth.clone() };
+            self.encoder.draw(&gpu_data.slice, self.pso.pick_by_material(material), &data);
+        }
+        self.encoder.flush(&mut self.device);
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("pick readback") });
+        for (i, texture) in [&targets.id, &targets.depth_value].iter().enumerate() {
+            encoder.copy_texture_to_buffer(
+                wgpu::TexelCopyTextureInfo { texture, mip_level: 0, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
+                wgpu::TexelCopyBufferInfo { buffer: &targets.readback, layout: wgpu::TexelCopyBufferLayout { offset: i as u64 * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64, bytes_per_row: None, rows_per_image: None } },
+                wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+            );
+        }
+        self.queue.submit(Some(encoder.finish()));
+        let (sender, receiver) = mpsc::channel();
+        targets.readback.map_async(wgpu::MapMode::Read, .., move |result| {
+            let _ = sender.send(result);
+        });
+        let _ = self.device.poll(wgpu::PollType::Wait);
+        match receiver.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => {
+                error!("Unable to read back the picked pixel: {}", err);
+                self.pick_targets = Some(targets);
+                return None;
+            }
+            // the callback was dropped without being called
+            Err(_) => {
+                self.pick_targets = Some(targets);
+                return None;
+            }
+        }
+        let (id, depth) = {
+            let data = targets.readback.get_mapped_range(..);
+            let offset = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
+            let id = u32::from_ne_bytes([data[0], data[1], data[2], data[3]]);
+            let depth = f32::from_ne_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+            (id, depth)
+        };
+        targets.readback.unmap();
+        self.pick_targets = Some(targets);
+
+        let ptr = nodes.get((id as usize).checked_sub(1)?)?.clone();
+        // back from the depth range to NDC
+        let clip = Vector4::new(ndc.x, ndc.y, 2.0 * depth - 1.0, 1.0);
+        let world = mx_inv_vp * clip;
+        let view = mx_inv_proj * clip;
+        Some(Pick { object: hub.upgrade_ptr(ptr), position: [world.x / world.w, world.y / world.w, world.z / world.w].into(), depth: -view.z / view.w })
+    }
+}
@@ -0,0 +1,513 @@
+//! Post-processing effects.
+//!
+//! Effects are listed in [`Renderer::post_effects`] and applied to the
+//! rendered scene in order. [`Bloom`] works on scene-referred (HDR) colors and
+//! runs before tone mapping, every other effect runs on the tone mapped image.
+//!
+//! [`Renderer::post_effects`]: ../struct.Renderer.html#structfield.post_effects
+//! [`Bloom`]: struct.Bloom.html
+
+use std::io;
+use std::path::Path;
+
+use crate::color::{self, Color, BLACK};
+
+use super::reload::{ShaderWatcher, WgslSource};
+use super::{custom_post_pipe, post_pipe, source, tonemap_pipe, CustomPostParams, PipelineCreationError, PostParams, QuadParams, Renderer, ToneMapping, TonemapParams, FULLSCREEN_SLICE, HDR_FORMAT, QUAD_SLICE};
+
+/// A single step of the post-processing chain.
+#[derive(Clone, Debug)]
+pub enum PostEffect {
+    /// See [`Bloom`](struct.Bloom.html).
+    Bloom(Bloom),
+    /// See [`Fxaa`](struct.Fxaa.html).
+    Fxaa(Fxaa),
+    /// See [`Vignette`](struct.Vignette.html).
+    Vignette(Vignette),
+    /// See [`ColorGrading`](struct.ColorGrading.html).
+    ColorGrading(ColorGrading),
+    /// See [`CustomPass`](struct.CustomPass.html).
+    Custom(CustomPass),
+}
+
+/// Glow around bright areas of the image.
+///
+/// The bright parts are extracted with a soft threshold, blurred by a chain
+/// of dual-filter downsamples and upsamples, and added back to the scene.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bloom {
+    /// Brightness above which pixels start to bloom.
+    ///
+    /// Default: `1.0`.
+    pub threshold: f32,
+    /// Width of the transition around the threshold, relative to it.
+    ///
+    /// Default: `0.5`.
+    pub knee: f32,
+    /// Strength of the glow added back to the scene.
+    ///
+    /// Default: `0.3`.
+    pub intensity: f32,
+    /// Number of downsample levels, a higher value spreads the glow further.
+    ///
+    /// Default: `5`.
+    pub levels: u8,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom { threshold: 1.0, knee: 0.5, intensity: 0.3, levels: 5 }
+    }
+}
+
+/// Fast approximate anti-aliasing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fxaa {
+    /// Maximum length of the blur along an edge, in pixels.
+    ///
+    /// Default: `8.0`.
+    pub span_max: f32,
+    /// Reduces the blur on bright edges.
+    ///
+    /// Default: `1.0 / 8.0`.
+    pub reduce_mul: f32,
+    /// Lower bound of the blur reduction.
+    ///
+    /// Default: `1.0 / 128.0`.
+    pub reduce_min: f32,
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Fxaa { span_max: 8.0, reduce_mul: 1.0 / 8.0, reduce_min: 1.0 / 128.0 }
+    }
+}
+
+/// Darkens the image towards its borders.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vignette {
+    /// Opacity of the vignette at the borders.
+    ///
+    /// Default: `0.5`.
+    pub intensity: f32,
+    /// Distance from the center where the vignette is fully applied,
+    /// relative to the image height.
+    ///
+    /// Default: `0.75`.
+    pub radius: f32,
+    /// Width of the transition towards `radius`.
+    ///
+    /// Default: `0.45`.
+    pub softness: f32,
+    /// Color blended in at the borders.
+    ///
+    /// Default: `BLACK`.
+    pub color: Color,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Vignette { intensity: 0.5, radius: 0.75, softness: 0.45, color: BLACK }
+    }
+}
+
+/// Remaps colors through a 3D lookup table.
+///
+/// See [`Factory::load_lut`](../struct.Factory.html#method.load_lut).
+#[derive(Clone, Debug)]
+pub struct ColorGrading {
+    /// The lookup table.
+    pub lut: Lut,
+    /// Blend factor between the original and the graded colors.
+    pub intensity: f32,
+}
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+quick_error! {
+    #[doc = "Error encountered when loading a color lookup table."]
+    #[derive(Debug)]
+    pub enum LutError {
+        #[doc = "The file could not be read."]
+        Io(err: io::Error) {
+            from()
+            description("LUT file could not be read")
+            display("LUT file could not be read: {}", err)
+        }
+
+        #[doc = "The image could not be decoded."]
+        Image(err: image::ImageError) {
+            from()
+            description("LUT image could not be decoded")
+            display("LUT image could not be decoded: {}", err)
+        }
+
+        #[doc = "The image is not a strip of `N` tiles of `N` x `N` pixels."]
+        Size(width: u32, height: u32) {
+            description("LUT image has the wrong size")
+            display("LUT image of {}x{} pixels is not a strip of square tiles", width, height)
+        }
+    }
+}
+
+/// 3D color lookup table used by [`ColorGrading`](struct.ColorGrading.html).
+#[derive(Clone, Debug)]
+pub struct Lut {
+    pub(crate) view: wgpu::TextureView,
+    pub(crate) sampler: wgpu::Sampler,
+    pub(crate) size: u32,
+}
+
+impl Lut {
+    /// Uploads a lookup table from an image strip of `size` square tiles.
+    ///
+    /// Within each tile red increases to the right and green downwards, blue
+    /// increases from tile to tile.
+    pub(crate) fn from_strip(device: &wgpu::Device, queue: &wgpu::Queue, image: &image::RgbaImage) -> Result<Self, LutError> {
+        let size = image.height();
+        if size == 0 || image.width() != size * size {
+            return Err(LutError::Size(image.width(), image.height()));
+        }
+        let mut texels = Vec::with_capacity((size * size * size * 4) as usize);
+        for b in 0 .. size {
+            for g in 0 .. size {
+                for r in 0 .. size {
+                    texels.extend_from_slice(&image.get_pixel(b * size + r, g).0);
+                }
+            }
+        }
+        let extent = wgpu::Extent3d { width: size, height: size, depth_or_array_layers: size };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("color grading lut"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            // holds sRGB values, decoded by the shader after interpolation
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo { texture: &texture, mip_level: 0, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
+            &texels,
+            wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(4 * size), rows_per_image: Some(size) },
+            extent,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor { label: Some("color grading lut"), mag_filter: wgpu::FilterMode::Linear, min_filter: wgpu::FilterMode::Linear, ..Default::default() });
+        Ok(Lut { view, sampler, size })
+    }
+
+    /// Lookup table that leaves colors unchanged.
+    pub(crate) fn identity(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let image = image::RgbaImage::from_fn(4, 2, |x, y| image::Rgba([(x % 2) as u8 * 0xFF, y as u8 * 0xFF, (x / 2) as u8 * 0xFF, 0xFF]));
+        // a valid strip of two tiles
+        Lut::from_strip(device, queue, &image).unwrap()
+    }
+}
+
+/// User supplied fullscreen pass, written in WGSL.
+///
+/// The source is appended to `data/shaders/quad.wgsl`, which provides the
+/// vertex stage of the `quad` pipeline along with these bindings:
+///
+/// * `u_Post.user`: the values of [`params`](#structfield.params),
+/// * `u_Post.resolution`: width, height and their inverses,
+/// * `t_Input` and `s_Input`: the image produced by the previous effect,
+/// * `t_Depth`: the scene depth.
+///
+/// The fragment stage has to be named `fs_main`:
+///
+/// ```wgsl
+/// @fragment
+/// fn fs_main(in: QuadOutput) -> @location(0) vec4<f32> {
+///     let color = textureSample(t_Input, s_Input, in.tex_coord);
+///     return vec4<f32>(vec3<f32>(dot(color.rgb, vec3<f32>(0.2126, 0.7152, 0.0722))), color.a);
+/// }
+/// ```
+///
+/// See [`Factory::post_pass`](../struct.Factory.html#method.post_pass).
+#[derive(Clone, Debug)]
+pub struct CustomPass {
+    pub(crate) pipeline: wgpu::RenderPipeline,
+    source: WgslSource,
+    /// Values passed to the shader as `u_Post.user`.
+    pub params: [[f32; 4]; 4],
+}
+
+impl CustomPass {
+    /// Takes the included chunks from `directory` when it has them.
+    pub(crate) fn new(device: &wgpu::Device, code: &str, directory: Option<&Path>) -> Result<Self, PipelineCreationError> {
+        let mut source = WgslSource::new(format!("#include <quad>\n{}", code));
+        let pipeline = Self::create_pipeline(device, source.process(directory)?)?;
+        Ok(CustomPass { pipeline, source, params: [[0.0; 4]; 4] })
+    }
+
+    /// Compiles `code`, the code of the pass with its chunks included.
+    fn create_pipeline(device: &wgpu::Device, code: String) -> Result<wgpu::RenderPipeline, PipelineCreationError> {
+        source::check_wgsl(&code)?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("custom post pass"), source: wgpu::ShaderSource::Wgsl(code.into()) });
+        Ok(device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("custom post pass"),
+            layout: None,
+            vertex: wgpu::VertexState { module: &module, entry_point: Some("vs_quad"), compilation_options: Default::default(), buffers: &[] },
+            primitive: wgpu::PrimitiveState { topology: wgpu::PrimitiveTopology::TriangleStrip, ..Default::default() },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState { module: &module, entry_point: Some("fs_main"), compilation_options: Default::default(), targets: &[Some(HDR_FORMAT.into())] }),
+            multiview: None,
+            cache: None,
+        }))
+    }
+
+    /// Compiles the pass again if the chunks it includes changed, keeping
+    /// the previous pipeline if that fails.
+    fn reload(&mut self, device: &wgpu::Device, watcher: &ShaderWatcher) {
+        if !watcher.is_stale(&self.source) {
+            return;
+        }
+        match watcher.process(&mut self.source).and_then(|code| Self::create_pipeline(device, code)) {
+            Ok(pipeline) => self.pipeline = pipeline,
+            Err(err) => error!("Custom post pass reload failed: {}", err),
+        }
+    }
+}
+
+impl From<Bloom> for PostEffect {
+    fn from(bloom: Bloom) -> Self {
+        PostEffect::Bloom(bloom)
+    }
+}
+
+impl From<Fxaa> for PostEffect {
+    fn from(fxaa: Fxaa) -> Self {
+        PostEffect::Fxaa(fxaa)
+    }
+}
+
+impl From<Vignette> for PostEffect {
+    fn from(vignette: Vignette) -> Self {
+        PostEffect::Vignette(vignette)
+    }
+}
+
+impl From<ColorGrading> for PostEffect {
+    fn from(grading: ColorGrading) -> Self {
+        PostEffect::ColorGrading(grading)
+    }
+}
+
+impl From<CustomPass> for PostEffect {
+    fn from(pass: CustomPass) -> Self {
+        PostEffect::Custom(pass)
+    }
+}
+
+/// Mode of the bloom shader, has to match `bloom_ps.glsl`.
+#[derive(Clone, Copy, Debug)]
+enum BloomMode {
+    Prefilter = 0,
+    Downsample = 1,
+    Upsample = 2,
+    Composite = 3,
+}
+
+/// Built-in pipeline used by a post-processing draw.
+#[derive(Clone, Copy, Debug)]
+enum Pass<'a> {
+    Bloom(BloomMode),
+    Fxaa,
+    Vignette,
+    Grading(&'a Lut),
+}
+
+/// Offscreen color target in `HDR_FORMAT`.
+pub(crate) struct Target {
+    pub view: wgpu::TextureView,
+    pub size: [u32; 2],
+}
+
+impl Target {
+    pub(crate) fn new(device: &wgpu::Device, size: [u32; 2], label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: size[0], height: size[1], depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Target { view, size }
+    }
+
+    fn resolution(&self) -> [f32; 4] {
+        resolution(self.size)
+    }
+}
+
+fn resolution(size: [u32; 2]) -> [f32; 4] {
+    [size[0] as f32, size[1] as f32, 1.0 / size[0] as f32, 1.0 / size[1] as f32]
+}
+
+/// Intermediate targets of the post-processing chain.
+pub(crate) struct Targets {
+    size: [u32; 2],
+    ping_pong: [Target; 2],
+    bloom_down: Vec<Target>,
+    bloom_up: Vec<Target>,
+}
+
+impl Targets {
+    fn new(device: &wgpu::Device, size: [u32; 2], bloom_levels: usize) -> Self {
+        let level_size = |i: usize| [(size[0] >> (i + 1)).max(1), (size[1] >> (i + 1)).max(1)];
+        Targets {
+            size,
+            ping_pong: [Target::new(device, size, "post ping"), Target::new(device, size, "post pong")],
+            bloom_down: (0 .. bloom_levels).map(|i| Target::new(device, level_size(i), "bloom downsample")).collect(),
+            bloom_up: (0 .. bloom_levels.saturating_sub(1)).map(|i| Target::new(device, level_size(i), "bloom upsample")).collect(),
+        }
+    }
+}
+
+impl Renderer {
+    /// Runs `post_effects` over the offscreen scene color and resolves
+    /// the result into the back buffer.
+    pub(super) fn post_process(&mut self, scene: wgpu::TextureView, size: [u32; 2]) {
+        if let Some(ref watcher) = self.shader_watcher {
+            for effect in &mut self.post_effects {
+                if let PostEffect::Custom(ref mut pass) = *effect {
+                    pass.reload(&self.device, watcher);
+                }
+            }
+        }
+        let effects = self.post_effects.clone();
+        let bloom_levels = effects
+            .iter()
+            .filter_map(|effect| match *effect {
+                PostEffect::Bloom(ref bloom) => Some(bloom.levels.max(1) as usize),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        let targets = match self.post_targets.take() {
+            Some(targets) if targets.size == size && targets.bloom_down.len() >= bloom_levels => targets,
+            _ => Targets::new(&self.device, size, bloom_levels),
+        };
+
+        // every intermediate target has the size of the scene
+        let input_size = size;
+        let mut input = scene;
+        let mut next = 0;
+
+        // scene-referred effects, before tone mapping
+        for effect in &effects {
+            if let PostEffect::Bloom(ref bloom) = *effect {
+                let output = &targets.ping_pong[next];
+                self.bloom(bloom, &input, &targets, &output.view);
+                input = output.view.clone();
+                next = 1 - next;
+            }
+        }
+
+        let (exposure, operator) = if self.hdr { (self.exposure, self.tone_mapping) } else { (1.0, ToneMapping::None) };
+        // sRGB surfaces encode on write, others need the shader to do it
+        let output_srgb = !self.surface_format.is_srgb();
+        let out_color = self.out_color.clone();
+        let display_effects = effects.iter().filter(|effect| match **effect {
+            PostEffect::Bloom(_) => false,
+            _ => true,
+        });
+        if display_effects.clone().next().is_none() {
+            self.tonemap(&input, &out_color, exposure, operator, output_srgb);
+            self.post_targets = Some(targets);
+            return;
+        }
+
+        self.tonemap(&input, &targets.ping_pong[next].view, exposure, operator, false);
+        input = targets.ping_pong[next].view.clone();
+        next = 1 - next;
+        for effect in display_effects {
+            let output = &targets.ping_pong[next];
+            let (pass, params0, params1) = match *effect {
+                PostEffect::Custom(ref pass) => {
+                    self.custom_draw(pass, &input, input_size, &output.view);
+                    input = output.view.clone();
+                    next = 1 - next;
+                    continue;
+                }
+                PostEffect::Fxaa(ref fxaa) => (Pass::Fxaa, [fxaa.span_max, fxaa.reduce_mul, fxaa.reduce_min, 0.0], [0.0; 4]),
+                PostEffect::Vignette(ref vignette) => {
+                    let rgb = color::to_linear_rgb(vignette.color);
+                    (Pass::Vignette, [vignette.intensity, vignette.radius, vignette.softness, 0.0], [rgb[0], rgb[1], rgb[2], 0.0])
+                }
+                PostEffect::ColorGrading(ref grading) => (Pass::Grading(&grading.lut), [grading.intensity, grading.lut.size as f32, 0.0, 0.0], [0.0; 4]),
+                PostEffect::Bloom(_) => unreachable!(),
+            };
+            self.post_draw(pass, params0, params1, &input, resolution(input_size), None, &output.view);
+            input = output.view.clone();
+            next = 1 - next;
+        }
+        // custom passes can't be trusted to encode the output, so always resolve
+        self.tonemap(&input, &out_color, 1.0, ToneMapping::None, output_srgb);
+        self.post_targets = Some(targets);
+    }
+
+    fn bloom(&mut self, bloom: &Bloom, scene: &wgpu::TextureView, targets: &Targets, output: &wgpu::TextureView) {
+        let levels = (bloom.levels.max(1) as usize).min(targets.bloom_down.len());
+        let params = [bloom.threshold, bloom.knee, bloom.intensity, 0.0];
+
+        let mut input = scene.clone();
+        let mut resolution = resolution(targets.size);
+        for (i, target) in targets.bloom_down[.. levels].iter().enumerate() {
+            let mode = if i == 0 { BloomMode::Prefilter } else { BloomMode::Downsample };
+            self.post_draw(Pass::Bloom(mode), params, [0.0; 4], &input, resolution, None, &target.view);
+            input = target.view.clone();
+            resolution = target.resolution();
+        }
+        // add back each downsample level on the way up
+        for i in (0 .. levels - 1).rev() {
+            let target = &targets.bloom_up[i];
+            self.post_draw(Pass::Bloom(BloomMode::Upsample), params, [0.0; 4], &input, resolution, Some(&targets.bloom_down[i].view), &target.view);
+            input = target.view.clone();
+            resolution = target.resolution();
+        }
+        self.post_draw(Pass::Bloom(BloomMode::Composite), params, [0.0; 4], &input, resolution, Some(scene), output);
+    }
+
+    fn post_draw(&mut self, pass: Pass, params0: [f32; 4], params1: [f32; 4], input: &wgpu::TextureView, resolution: [f32; 4], source: Option<&wgpu::TextureView>, target: &wgpu::TextureView) {
+        let sampler = self.map_default.to_param().1;
+        let mode = match pass {
+            Pass::Bloom(mode) => mode as i32,
+            _ => 0,
+        };
+        self.encoder.update_constant_buffer(&self.post_buf, &PostParams { params0, params1, resolution, mode, _padding0: [0; 3] });
+        let lut = match pass {
+            Pass::Grading(lut) => lut,
+            _ => &self.lut_default,
+        };
+        let data = post_pipe::Data { params: self.post_buf.clone(), input: (input.clone(), sampler.clone()), source: (source.unwrap_or(input).clone(), sampler.clone()), lut: (lut.view.clone(), lut.sampler.clone()), target: target.clone() };
+        let pso = match pass {
+            Pass::Bloom(_) => &self.pso.bloom,
+            Pass::Fxaa => &self.pso.fxaa,
+            Pass::Vignette => &self.pso.vignette,
+            Pass::Grading(_) => &self.pso.grading,
+        };
+        self.encoder.draw(&FULLSCREEN_SLICE, pso, &data);
+    }
+
+    fn custom_draw(&mut self, pass: &CustomPass, input: &wgpu::TextureView, input_size: [u32; 2], target: &wgpu::TextureView) {
+        self.encoder.update_constant_buffer(&self.quad_buf, &QuadParams { rect: [-1.0, -1.0, 1.0, 1.0], depth: 0.0 });
+        self.encoder.update_constant_buffer(&self.custom_post_buf, &CustomPostParams { user: pass.params, resolution: resolution(input_size) });
+        let data = custom_post_pipe::Data { quad: self.quad_buf.clone(), post: self.custom_post_buf.clone(), input: (input.clone(), self.map_default.to_param().1), depth: self.out_depth_sampled.clone(), target: target.clone() };
+        self.encoder.draw(&QUAD_SLICE, &pass.pipeline, &data);
+    }
+
+    fn tonemap(&mut self, input: &wgpu::TextureView, target: &wgpu::TextureView, exposure: f32, operator: ToneMapping, output_srgb: bool) {
+        self.encoder.update_constant_buffer(&self.tonemap_buf, &TonemapParams { exposure, operator: operator.index(), output_srgb: output_srgb as i32, _padding0: 0.0 });
+        let data = tonemap_pipe::Data { params: self.tonemap_buf.clone(), input: (input.clone(), self.map_default.to_param().1), target: target.clone() };
+        self.encoder.draw(&FULLSCREEN_SLICE, &self.pso.tonemap, &data);
+    }
+}
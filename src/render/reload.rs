@@ -0,0 +1,148 @@
+//! Hot reloading of WGSL shaders, see [`Renderer::watch_shaders`](../struct.Renderer.html#method.watch_shaders).
+//!
+//! Every WGSL pipeline keeps the code it was created from, along with the
+//! chunks that code includes. When a `{name}.wgsl` file of the watched
+//! directory changes, the pipelines including the chunk `name` are compiled
+//! again the next time they are used. Files of the directory take precedence
+//! over the built-in chunks.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+
+use notify::Watcher;
+
+use super::{source, PipelineCreationError, Renderer};
+
+/// Directory set by [`Renderer::watch_shaders`](../struct.Renderer.html#method.watch_shaders),
+/// shared with the `Factory` so new pipelines include its chunks too.
+pub(crate) type ShaderDirectory = Arc<Mutex<Option<PathBuf>>>;
+
+/// WGSL code of a pipeline, before its chunks are included.
+#[derive(Clone, Debug)]
+pub(crate) struct WgslSource {
+    code: String,
+    /// Chunks included by `code`, directly or through other chunks.
+    chunks: Vec<String>,
+    /// Generation of the watched directory the code was last compiled
+    /// with, `0` if it was only compiled on creation.
+    generation: u64,
+}
+
+impl WgslSource {
+    pub(crate) fn new(code: String) -> Self {
+        WgslSource { code, chunks: Vec::new(), generation: 0 }
+    }
+
+    /// Returns the code with its chunks included, taken from `directory`
+    /// when it has them.
+    pub(crate) fn process(&mut self, directory: Option<&Path>) -> Result<String, PipelineCreationError> {
+        self.chunks.clear();
+        source::include_wgsl(&self.code, directory, &mut self.chunks)
+    }
+}
+
+/// Watches the user shader directory for changes.
+pub(crate) struct ShaderWatcher {
+    dir: PathBuf,
+    _watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    /// Incremented every time files of the directory change.
+    generation: u64,
+    /// Generation of the last change of every chunk.
+    changed: HashMap<String, u64>,
+}
+
+impl ShaderWatcher {
+    fn new(dir: PathBuf) -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(&dir, notify::RecursiveMode::NonRecursive)?;
+        Ok(ShaderWatcher { dir, _watcher: watcher, events, generation: 1, changed: HashMap::new() })
+    }
+
+    /// Records the chunks changed since the last call.
+    fn poll(&mut self) {
+        let mut changed = Vec::new();
+        while let Ok(result) = self.events.try_recv() {
+            let event = match result {
+                Ok(event) => event,
+                Err(err) => {
+                    error!("Shader directory {}: {}", self.dir.display(), err);
+                    continue;
+                }
+            };
+            match event.kind {
+                notify::EventKind::Create(_) | notify::EventKind::Modify(_) | notify::EventKind::Remove(_) => {}
+                _ => continue,
+            }
+            for path in &event.paths {
+                if path.extension().map_or(false, |ext| ext == "wgsl") {
+                    if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                        changed.push(name.to_string());
+                    }
+                }
+            }
+        }
+        if changed.is_empty() {
+            return;
+        }
+        self.generation += 1;
+        for name in changed {
+            info!("Reloading shaders including <{}>", name);
+            self.changed.insert(name, self.generation);
+        }
+    }
+
+    /// Returns the code of `source` with its chunks included, taken from the
+    /// watched directory when it has them.
+    ///
+    /// The source is considered compiled even if this or the compilation
+    /// of the result fails, so errors are reported once per change.
+    pub(crate) fn process(&self, source: &mut WgslSource) -> Result<String, PipelineCreationError> {
+        source.generation = self.generation;
+        source.process(Some(&self.dir))
+    }
+
+    /// Whether `source` has to be compiled again.
+    pub(crate) fn is_stale(&self, source: &WgslSource) -> bool {
+        // sources created before the directory was watched may use overridden chunks,
+        // and the directory may have changed since the others were created
+        source.generation == 0 || source.chunks.iter().any(|chunk| self.changed.get(chunk).map_or(false, |&generation| generation > source.generation))
+    }
+}
+
+impl Renderer {
+    /// Watches `dir` for WGSL chunks, reloading the pipelines including
+    /// them when they change.
+    ///
+    /// A file `{name}.wgsl` of the directory replaces the built-in chunk
+    /// `name`, and is included by `#include <name>` in the code of custom
+    /// materials, post-processing and render graph passes. Pipelines are
+    /// compiled again the next time they are drawn, and only if one of the
+    /// chunks they include changed. If compilation fails, the error is logged
+    /// and the previous pipeline is kept. Pipelines created by the `Factory`
+    /// afterwards include the chunks of the directory as well.
+    ///
+    /// ```rust,no_run
+    /// # let mut window = three::Window::new("");
+    /// // the whole shader lives in `shaders/water.wgsl`
+    /// window.renderer.watch_shaders("shaders").unwrap();
+    /// let shader = window.factory.custom_shader("#include <water>", "#include <water>", Default::default()).unwrap();
+    /// ```
+    ///
+    /// This is done by [`AppBuilder::shader_directory`](../struct.AppBuilder.html#method.shader_directory).
+    pub fn watch_shaders<P: Into<PathBuf>>(&mut self, dir: P) -> notify::Result<()> {
+        let dir = dir.into();
+        self.shader_watcher = Some(ShaderWatcher::new(dir.clone())?);
+        *self.shader_directory.lock().unwrap() = Some(dir);
+        Ok(())
+    }
+
+    /// Records the shader files changed since the previous frame.
+    pub(super) fn poll_shaders(&mut self) {
+        if let Some(ref mut watcher) = self.shader_watcher {
+            watcher.poll();
+        }
+    }
+}
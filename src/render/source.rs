@@ -1,71 +1,217 @@
 //! Source for for WGSL shaders used by the renderer.
+//!
+//! Shader code goes through a small preprocessor supporting:
+//!
+//! * `#include <name>`, inserting the built-in chunk `name` once,
+//! * `#include "path"`, inserting a file relative to a user shader,
+//! * `#define NAME value` and `#undef NAME`, replacing `NAME` by `value`
+//!   in the lines that follow,
+//! * `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif`, keeping the lines
+//!   of the blocks whose condition holds.
+//!
+//! Every enabled [`Features`](struct.Features.html) flag is defined by its
+//! name, so a single source provides all the variants of a pipeline. Other
+//! directives, such as `#version`, are kept as they are.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{fs, io, str};
+
+use bitflags::bitflags;
 
 use crate::data;
 use crate::util;
 
-use std::borrow::Borrow;
-use std::path::Path;
-use std::{io, ops, str};
-
-/// Source code for a single WGSL shader.
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub struct Source(pub(crate) String);
+use super::PipelineCreationError;
 
-impl ops::Deref for Source {
-    type Target = [u8];
-    fn deref(&self) -> &Self::Target {
-        self.0.as_bytes()
+bitflags! {
+    /// Optional parts of a shader, each defining the name of the flag.
+    ///
+    /// See [`Source::variant`](struct.Source.html#method.variant).
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct Features: u32 {
+        /// Vertices are transformed by the joints of a skeleton.
+        const SKINNING               = 1 << 0;
+        /// Vertices are displaced by morph targets.
+        const MORPH_TARGETS          = 1 << 1;
+        /// Normals are read from a normal map.
+        const NORMAL_MAP             = 1 << 2;
+        /// Lights may cast shadows.
+        const SHADOWS                = 1 << 3;
+        /// The surface color is read from a map.
+        const BASE_COLOR_MAP         = 1 << 4;
+        /// Metalness and roughness are read from a map.
+        const METALLIC_ROUGHNESS_MAP = 1 << 5;
+        /// Emitted light is read from a map.
+        const EMISSIVE_MAP           = 1 << 6;
+        /// Ambient occlusion is read from a map.
+        const OCCLUSION_MAP          = 1 << 7;
     }
 }
 
-impl Source {
-    fn preprocess<P: AsRef<Path>>(root: P, code: &str) -> io::Result<String> {
-        let root = root.as_ref();
-        let mut new_code = String::new();
-        for line in code.lines() {
-            if line.starts_with("#include") {
-                if let Some(arg) = line.split_whitespace().skip(1).next() {
-                    if arg.starts_with('<') {
-                        if let Some(pos) = arg[1 ..].find('>') {
-                            let name = &arg[1 .. (pos + 1)];
-                            let path = format!("data/shaders/{}.glsl", name);
-                            let content = &data::FILES.get(&path).unwrap();
-                            new_code += str::from_utf8(content.borrow()).unwrap();
-                        }
-                    } else if arg.starts_with('"') {
-                        if let Some(pos) = arg[1 ..].find('"') {
-                            let relative_path = &arg[1 .. (pos + 1)];
-                            let path = root.join(relative_path);
-                            let content = util::read_file_to_string(&path)?;
-                            let include = Self::preprocess(root, &content)?;
-                            new_code += &include;
-                        }
-                    }
-                }
-            } else {
-                new_code.push_str(&line);
-                new_code.push('\n');
-            }
-        }
-        Ok(new_code)
-    }
+/// Unprocessed source code for a single shader stage.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Source {
+    code: String,
+    /// Directory of `#include "path"` files, for user shaders.
+    root: Option<PathBuf>,
+}
 
+impl Source {
     /// Load the named shader from the default set of shaders.
     pub fn default(name: &str, suffix: &str) -> io::Result<Self> {
         let path = format!("data/shaders/{}_{}.glsl", name, suffix);
-        let unprocessed = data::FILES.get(&path).unwrap();
-        let processed = Self::preprocess("", str::from_utf8(unprocessed.borrow()).unwrap())?;
-        Ok(Source(processed))
+        let code = data::FILES.get(&path)?;
+        Ok(Source { code: str::from_utf8(code.borrow()).unwrap().to_string(), root: None })
     }
 
     /// Load the named shader from the given directory path.
     pub fn user<P: AsRef<Path>>(root: P, name: &str, suffix: &str) -> io::Result<Self> {
         let base_name = format!("{}_{}.glsl", name, suffix);
         let path = root.as_ref().join(&base_name);
-        let unprocessed = util::read_file_to_string(Path::new(&path))?;
-        let processed = Self::preprocess(root, &unprocessed)?;
-        Ok(Source(processed))
+        let code = util::read_file_to_string(Path::new(&path))?;
+        Ok(Source { code, root: Some(root.as_ref().to_path_buf()) })
+    }
+
+    /// Preprocesses the code with `features` defined.
+    pub fn variant(&self, features: Features) -> Result<String, PipelineCreationError> {
+        let root = self.root.as_ref();
+        let mut load = |name: &str, relative: bool| -> Result<String, PipelineCreationError> {
+            match root {
+                Some(root) if relative => Ok(util::read_file_to_string(root.join(name))?),
+                _ if relative => Err(PipelineCreationError::Compilation(format!("unexpected include \"{}\" of a built-in shader", name))),
+                _ => {
+                    let content = data::FILES.get(&format!("data/shaders/{}.glsl", name)).map_err(|_| PipelineCreationError::Compilation(format!("unknown include <{}>", name)))?;
+                    Ok(str::from_utf8(content.borrow()).unwrap().to_string())
+                }
+            }
+        };
+        preprocess(&self.code, features, &mut load, &mut Vec::new())
+    }
+}
+
+/// State of an `#ifdef` or `#ifndef` block.
+struct Block {
+    /// Whether the lines around the block are kept.
+    parent: bool,
+    condition: bool,
+    /// Set after `#else`.
+    negated: bool,
+}
+
+impl Block {
+    fn active(&self) -> bool {
+        self.parent && self.condition != self.negated
+    }
+}
+
+struct Preprocessor<'a> {
+    features: Features,
+    /// Returns the code of an include given its name, and whether it is a
+    /// `"path"` rather than a `<name>`.
+    load: &'a mut dyn FnMut(&str, bool) -> Result<String, PipelineCreationError>,
+    /// Names of the includes already inserted.
+    included: &'a mut Vec<String>,
+    defines: HashMap<String, String>,
+    output: String,
+}
+
+impl<'a> Preprocessor<'a> {
+    fn is_defined(&self, name: &str) -> bool {
+        self.defines.contains_key(name) || self.features.iter_names().any(|(feature, _)| feature == name)
+    }
+
+    fn run(&mut self, code: &str) -> Result<(), PipelineCreationError> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for line in code.lines() {
+            let active = blocks.last().map_or(true, Block::active);
+            let directive = match line.trim_start().strip_prefix('#') {
+                Some(directive) => directive.trim(),
+                None => {
+                    if active {
+                        self.push_line(line);
+                    }
+                    continue;
+                }
+            };
+            let (keyword, arg) = match directive.find(char::is_whitespace) {
+                Some(pos) => (&directive[.. pos], directive[pos ..].trim()),
+                None => (directive, ""),
+            };
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let condition = self.is_defined(arg) == (keyword == "ifdef");
+                    blocks.push(Block { parent: active, condition, negated: false });
+                }
+                "else" => match blocks.last_mut() {
+                    Some(block) if !block.negated => block.negated = true,
+                    _ => return Err(PipelineCreationError::Compilation("unexpected #else".to_string())),
+                },
+                "endif" => {
+                    blocks.pop().ok_or_else(|| PipelineCreationError::Compilation("unexpected #endif".to_string()))?;
+                }
+                _ if !active => {}
+                "include" => {
+                    let relative = arg.starts_with('"');
+                    let name = arg.trim_matches(|c| c == '<' || c == '>' || c == '"');
+                    if self.included.iter().any(|included| included == name) {
+                        continue;
+                    }
+                    self.included.push(name.to_string());
+                    let content = (self.load)(name, relative)?;
+                    self.run(&content)?;
+                }
+                "define" => {
+                    let (name, value) = match arg.find(char::is_whitespace) {
+                        Some(pos) => (&arg[.. pos], arg[pos ..].trim()),
+                        None => (arg, ""),
+                    };
+                    self.defines.insert(name.to_string(), value.to_string());
+                }
+                "undef" => {
+                    self.defines.remove(arg);
+                }
+                _ => {
+                    self.output.push_str(line);
+                    self.output.push('\n');
+                }
+            }
+        }
+        if !blocks.is_empty() {
+            return Err(PipelineCreationError::Compilation("unterminated #ifdef".to_string()));
+        }
+        Ok(())
     }
+
+    /// Appends `line`, replacing the defined names by their values.
+    fn push_line(&mut self, line: &str) {
+        let mut rest = line;
+        while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            let (before, word) = rest.split_at(start);
+            let end = word.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(word.len());
+            let (word, after) = word.split_at(end);
+            self.output.push_str(before);
+            match self.defines.get(word) {
+                Some(value) if !value.is_empty() => self.output.push_str(value),
+                _ => self.output.push_str(word),
+            }
+            rest = after;
+        }
+        self.output.push_str(rest);
+        self.output.push('\n');
+    }
+}
+
+/// Expands the directives of `code` with `features` defined, see the
+/// [module documentation](index.html).
+///
+/// The names of the inserted includes are pushed to `included`, which
+/// also lists the includes to skip.
+pub(crate) fn preprocess(code: &str, features: Features, load: &mut dyn FnMut(&str, bool) -> Result<String, PipelineCreationError>, included: &mut Vec<String>) -> Result<String, PipelineCreationError> {
+    let mut preprocessor = Preprocessor { features, load, included, defines: HashMap::new(), output: String::new() };
+    preprocessor.run(code)?;
+    Ok(preprocessor.output)
 }
 
 macro_rules! decl_shaders {
@@ -116,11 +262,126 @@ macro_rules! decl_shaders {
 
 decl_shaders! {
     (basic, basic, Basic),
+    (bloom, bloom, Bloom),
+    (clear, clear, Clear),
+    (debug_lines, debug_lines, DebugLines),
+    (debug_view, debug_view, DebugView),
+    (depth, depth, Depth),
+    (depth_normal, depth_normal, DepthNormal),
+    (equirect, equirectangular, Equirect),
+    (fxaa, FXAA, Fxaa),
     (gouraud, Gouraud, Gouraud),
+    (grading, grading, Grading),
+    (matcap, matcap, Matcap),
+    (normal, normal, Normal),
+    (outline, outline, Outline),
     (pbr, PBR, Pbr),
     (phong, Phong, Phong),
+    (pick, picking, Pick),
     (quad, quad, Quad),
     (shadow, shadow, Shadow),
     (skybox, skybox, Skybox),
     (sprite, sprite, Sprite),
+    (ssao, SSAO, Ssao),
+    (ssao_blur, SSAO_blur, SsaoBlur),
+    (tonemap, tonemap, Tonemap),
+    (toon, toon, Toon),
+    (vertex_lines, vertex_lines, VertexLines),
+    (vignette, vignette, Vignette),
+}
+
+/// Parses and validates WGSL code, so errors can be reported before
+/// pipeline creation instead of through the device error handler.
+pub(crate) fn check_wgsl(code: &str) -> Result<wgpu::naga::Module, PipelineCreationError> {
+    use wgpu::naga::valid::{Capabilities, ValidationFlags, Validator};
+
+    let module = wgpu::naga::front::wgsl::parse_str(code).map_err(|err| PipelineCreationError::Compilation(err.emit_to_string(code)))?;
+    Validator::new(ValidationFlags::all(), Capabilities::all()).validate(&module).map_err(|err| PipelineCreationError::Compilation(err.emit_to_string(code)))?;
+    Ok(module)
+}
+
+/// Preprocesses WGSL code, taking the chunks `#include <name>` from
+/// `{directory}/{name}.wgsl` if it exists, `data/shaders/{name}.wgsl` otherwise.
+///
+/// The names of the included chunks are pushed to `chunks`, even when
+/// including fails.
+pub(crate) fn include_wgsl(code: &str, directory: Option<&Path>, chunks: &mut Vec<String>) -> Result<String, PipelineCreationError> {
+    let mut load = |name: &str, _relative: bool| -> Result<String, PipelineCreationError> {
+        let user_path = directory.map(|dir| dir.join(format!("{}.wgsl", name)));
+        match user_path {
+            Some(ref path) if path.is_file() => fs::read_to_string(path).map_err(|err| PipelineCreationError::Compilation(format!("{}: {}", path.display(), err))),
+            _ => {
+                let content = data::FILES.get(&format!("data/shaders/{}.wgsl", name)).map_err(|_| PipelineCreationError::Compilation(format!("unknown include <{}>", name)))?;
+                Ok(str::from_utf8(content.borrow()).unwrap().to_string())
+            }
+        }
+    };
+    preprocess(code, Features::empty(), &mut load, chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Preprocesses `code` with the chunks `<a>`, which includes `<b>`, and `<b>`.
+    fn run(code: &str, features: Features) -> Result<String, PipelineCreationError> {
+        let chunks: HashMap<&str, &str> = [("a", "#include <b>\nA"), ("b", "B")].into_iter().collect();
+        let mut load = |name: &str, _relative: bool| chunks.get(name).map(|code| code.to_string()).ok_or_else(|| PipelineCreationError::Compilation(format!("unknown include <{}>", name)));
+        preprocess(code, features, &mut load, &mut Vec::new())
+    }
+
+    fn error(code: &str) -> String {
+        match run(code, Features::empty()) {
+            Err(PipelineCreationError::Compilation(log)) => log,
+            other => panic!("expected a compilation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ifdef_nesting() {
+        let code = "#ifdef SKINNING\na\n#ifdef SHADOWS\nb\n#else\nc\n#endif\n#else\nd\n#ifndef SHADOWS\ne\n#endif\n#endif\nf";
+        assert_eq!(run(code, Features::SKINNING).unwrap(), "a\nc\nf\n");
+        assert_eq!(run(code, Features::SKINNING | Features::SHADOWS).unwrap(), "a\nb\nf\n");
+        assert_eq!(run(code, Features::SHADOWS).unwrap(), "d\nf\n");
+        assert_eq!(run(code, Features::empty()).unwrap(), "d\ne\nf\n");
+    }
+
+    #[test]
+    fn other_directives_are_kept() {
+        assert_eq!(run("#version 450\nmain", Features::empty()).unwrap(), "#version 450\nmain\n");
+        // inactive blocks drop them too
+        assert_eq!(run("#ifdef SHADOWS\n#version 450\n#endif", Features::empty()).unwrap(), "");
+    }
+
+    #[test]
+    fn include_once() {
+        let mut included = Vec::new();
+        let mut load = |name: &str, _relative: bool| -> Result<String, PipelineCreationError> { Ok(if name == "a" { "#include <b>\nA".to_string() } else { "B".to_string() }) };
+        let output = preprocess("#include <a>\n#include <b>\n#include <a>\nmain", Features::empty(), &mut load, &mut included).unwrap();
+        assert_eq!(output, "B\nA\nmain\n");
+        assert_eq!(included, ["a", "b"]);
+        // includes of inactive blocks are not loaded
+        assert_eq!(run("#ifdef SHADOWS\n#include <missing>\n#endif\nmain", Features::empty()).unwrap(), "main\n");
+        assert_eq!(error("#include <missing>"), "unknown include <missing>");
+    }
+
+    #[test]
+    fn define() {
+        let code = "#define SIZE 4\nlet x = SIZE + SIZE_2 + a.SIZE;\n#undef SIZE\nSIZE";
+        assert_eq!(run(code, Features::empty()).unwrap(), "let x = 4 + SIZE_2 + a.4;\nSIZE\n");
+        // names defined without a value are kept, but enable their blocks
+        assert_eq!(run("#define FLAG\n#ifdef FLAG\nFLAG\n#endif", Features::empty()).unwrap(), "FLAG\n");
+        assert_eq!(run("#define FLAG\n#undef FLAG\n#ifdef FLAG\nx\n#endif", Features::empty()).unwrap(), "");
+    }
+
+    #[test]
+    fn unbalanced_blocks() {
+        assert_eq!(error("#ifdef SHADOWS\nx"), "unterminated #ifdef");
+        assert_eq!(error("#ifdef SHADOWS\n#else\n#else\n#endif"), "unexpected #else");
+        assert_eq!(error("#else"), "unexpected #else");
+        assert_eq!(error("x\n#endif"), "unexpected #endif");
+        // blocks can't span includes
+        let mut load = |_: &str, _: bool| -> Result<String, PipelineCreationError> { Ok("#ifdef SHADOWS".to_string()) };
+        assert!(preprocess("#include <a>\n#endif", Features::empty(), &mut load, &mut Vec::new()).is_err());
+    }
 }
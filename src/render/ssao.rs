@@ -0,0 +1,158 @@
+//! Screen-space ambient occlusion.
+//!
+//! When [`Renderer::ssao`] is set, the opaque meshes are first rendered into a
+//! view space normal and depth target. The occlusion is estimated from it by
+//! sampling a hemisphere around every pixel, then smoothed by a separable blur
+//! that stops at depth discontinuities. The lit materials multiply their
+//! ambient term by the result, on top of `Pbr::occlusion_map`.
+//!
+//! Skinning and morph targets are not applied in the prepass, so animated
+//! meshes are occluded according to their rest pose.
+//!
+//! [`Renderer::ssao`]: ../struct.Renderer.html#structfield.ssao
+
+use cgmath::Matrix4;
+
+use crate::hub::{Hub, SubNode};
+use crate::material::Material;
+use crate::scene::Scene;
+
+use super::{depth_normal_pipe, post, post_pipe, ssao_pipe, Instance, PostParams, Renderer, SsaoParams, FULLSCREEN_SLICE};
+
+/// Maximum value of [`Ssao::samples`](struct.Ssao.html#structfield.samples).
+///
+/// Note: has to match `MAX_SAMPLES` in `ssao_ps.glsl`.
+pub const MAX_SAMPLES: usize = 64;
+
+/// Settings of the screen-space ambient occlusion pass.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ssao {
+    /// Radius of the sampled hemisphere, in world units.
+    ///
+    /// Default: `0.5`.
+    pub radius: f32,
+    /// Exponent applied to the visibility, higher values darken the occlusion.
+    ///
+    /// Default: `1.0`.
+    pub strength: f32,
+    /// Depth offset preventing flat surfaces from occluding themselves.
+    ///
+    /// Default: `0.025`.
+    pub bias: f32,
+    /// Number of samples per pixel, up to [`MAX_SAMPLES`](constant.MAX_SAMPLES.html).
+    ///
+    /// Default: `16`.
+    pub samples: u8,
+    /// How strongly depth differences stop the blur, `0.0` blurs across edges.
+    ///
+    /// Default: `8.0`.
+    pub blur_sharpness: f32,
+}
+
+impl Default for Ssao {
+    fn default() -> Self {
+        Ssao { radius: 0.5, strength: 1.0, bias: 0.025, samples: 16, blur_sharpness: 8.0 }
+    }
+}
+
+impl Ssao {
+    fn sample_count(&self) -> usize {
+        (self.samples as usize).max(1).min(MAX_SAMPLES)
+    }
+}
+
+/// Element of the Halton low-discrepancy sequence.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Cosine distributed hemisphere offsets, denser close to the center.
+fn kernel(count: usize) -> [[f32; 4]; MAX_SAMPLES] {
+    let mut kernel = [[0.0; 4]; MAX_SAMPLES];
+    for (i, offset) in kernel[.. count].iter_mut().enumerate() {
+        let u = halton(i as u32 + 1, 2);
+        let v = halton(i as u32 + 1, 3);
+        let phi = 2.0 * std::f32::consts::PI * u;
+        let r = v.sqrt();
+        let t = (i + 1) as f32 / count as f32;
+        let scale = 0.1 + 0.9 * t * t;
+        *offset = [r * phi.cos() * scale, r * phi.sin() * scale, (1.0 - v).sqrt() * scale, 0.0];
+    }
+    kernel
+}
+
+/// Intermediate targets of the SSAO pass.
+pub(crate) struct Targets {
+    size: [u32; 2],
+    normal_depth: post::Target,
+    occlusion: post::Target,
+    blur: post::Target,
+}
+
+impl Renderer {
+    /// Computes the ambient occlusion of `scene` and returns the blurred result.
+    ///
+    /// Only the pixels inside `viewport`, given as `[x, y, width, height]`
+    /// from the top left corner, are occluded.
+    pub(super) fn ssao_pass(&mut self, ssao: &Ssao, hub: &Hub, scene: &Scene, mx_proj: Matrix4<f32>, size: [u32; 2], viewport: [u32; 4]) -> wgpu::TextureView {
+        let targets = match self.ssao_targets.take() {
+            Some(targets) if targets.size == size => targets,
+            _ => Targets {
+                size,
+                normal_depth: post::Target::new(&self.device, size, "ssao normal depth"),
+                occlusion: post::Target::new(&self.device, size, "ssao occlusion"),
+                blur: post::Target::new(&self.device, size, "ssao blur"),
+            },
+        };
+
+        // prepass, zero depth marks the background
+        self.encoder.clear(&targets.normal_depth.view, [0.0; 4]);
+        self.encoder.clear_depth(&self.out_depth, 1.0);
+        self.encoder.set_viewport(viewport);
+        for w in hub.walk(&scene.first_child) {
+            let (material, gpu_data) = match w.node.sub_node {
+                SubNode::Visual(ref material, ref gpu_data, _) => (material, gpu_data),
+                _ => continue,
+            };
+            // lines and sprites don't occlude anything
+            match *material {
+                Material::Line(_) | Material::Wireframe(_) | Material::Sprite(_) => continue,
+                _ => {}
+            }
+            let mx_world: mint::ColumnMatrix4<_> = Matrix4::from(w.world_transform).into();
+            self.encoder.update_buffer(&gpu_data.instances, &[Instance::pbr(mx_world.into())], 0).unwrap();
+            let data = depth_normal_pipe::Data { vbuf: gpu_data.vertices.clone(), inst_buf: gpu_data.instances.clone(), globals: self.const_buf.clone(), target: targets.normal_depth.view.clone(), depth_target: self.out_depth.clone() };
+            self.encoder.draw(&gpu_data.slice, &self.pso.depth_normal, &data);
+        }
+        // the fullscreen passes below sample the whole target
+        self.encoder.set_viewport([0, 0, size[0], size[1]]);
+
+        let count = ssao.sample_count();
+        // texture coordinates start from the bottom left corner
+        let (width, height) = (size[0] as f32, size[1] as f32);
+        let uv_viewport = [viewport[0] as f32 / width, 1.0 - (viewport[1] + viewport[3]) as f32 / height, viewport[2] as f32 / width, viewport[3] as f32 / height];
+        self.encoder.update_constant_buffer(&self.ssao_buf, &SsaoParams { kernel: kernel(count), proj: mx_proj.into(), params: [ssao.radius, ssao.bias, ssao.strength, count as f32], viewport: uv_viewport });
+        let sampler = self.map_default.to_param().1;
+        let data = ssao_pipe::Data { globals: self.const_buf.clone(), params: self.ssao_buf.clone(), normal_depth: (targets.normal_depth.view.clone(), sampler.clone()), target: targets.occlusion.view.clone() };
+        self.encoder.draw(&FULLSCREEN_SLICE, &self.pso.ssao, &data);
+
+        // separable bilateral blur, horizontal then vertical
+        let resolution = [size[0] as f32, size[1] as f32, 1.0 / size[0] as f32, 1.0 / size[1] as f32];
+        for &(direction, ref input, ref output) in &[([1.0, 0.0], &targets.occlusion, &targets.blur), ([0.0, 1.0], &targets.blur, &targets.occlusion)] {
+            self.encoder.update_constant_buffer(&self.post_buf, &PostParams { params0: [direction[0], direction[1], ssao.blur_sharpness, 0.0], params1: [0.0; 4], resolution, mode: 0, _padding0: [0; 3] });
+            let data = post_pipe::Data { params: self.post_buf.clone(), input: (input.view.clone(), sampler.clone()), source: (input.view.clone(), sampler.clone()), lut: (self.lut_default.view.clone(), self.lut_default.sampler.clone()), target: output.view.clone() };
+            self.encoder.draw(&FULLSCREEN_SLICE, &self.pso.ssao_blur, &data);
+        }
+
+        let view = targets.occlusion.view.clone();
+        self.ssao_targets = Some(targets);
+        view
+    }
+}
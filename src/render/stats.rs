@@ -0,0 +1,228 @@
+//! Per-frame statistics, see [`Renderer::stats`](../struct.Renderer.html#method.stats).
+
+use std::sync::mpsc;
+use std::time::Duration;
+use std::{fmt, mem};
+
+use crate::material::Material;
+
+use super::{PipelineStates, Renderer};
+
+/// Maximum number of GPU timestamps per frame.
+const MAX_TIMESTAMPS: u32 = 16;
+
+/// Work done by the renderer in a frame.
+///
+/// Counts cover the scene geometry drawn by the shadow and main passes,
+/// full screen passes such as post-processing and SSAO are not included.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    /// Number of draw calls.
+    pub draw_calls: u32,
+    /// Number of triangles, counting every instance.
+    pub triangles: u64,
+    /// Number of mesh instances drawn, several per draw call when instancing.
+    pub instances: u32,
+    /// Number of times the pipeline changed between consecutive draw calls.
+    pub pipeline_switches: u32,
+    /// Number of meshes skipped as outside of the camera frustum.
+    pub culled_objects: u32,
+    /// Number of shadow maps rendered.
+    pub shadow_passes: u32,
+    /// Number of buffer writes, including instance and uniform data.
+    pub buffer_uploads: u32,
+    /// GPU time spent in each pass, if the adapter supports timestamp queries.
+    ///
+    /// The timings are read back asynchronously, so they are usually a
+    /// couple of frames older than the counts.
+    pub gpu_timings: Vec<(&'static str, Duration)>,
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "draw calls: {}", self.draw_calls)?;
+        writeln!(f, "triangles: {}", self.triangles)?;
+        writeln!(f, "instances: {}", self.instances)?;
+        writeln!(f, "pipeline switches: {}", self.pipeline_switches)?;
+        writeln!(f, "culled: {}", self.culled_objects)?;
+        writeln!(f, "shadow passes: {}", self.shadow_passes)?;
+        write!(f, "buffer uploads: {}", self.buffer_uploads)?;
+        for &(label, duration) in &self.gpu_timings {
+            write!(f, "\n{}: {:.3} ms", label, duration.as_secs_f64() * 1000.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Accumulates the statistics of the frame being rendered.
+#[derive(Debug, Default)]
+pub(crate) struct Recorder {
+    pub(crate) stats: RenderStats,
+    /// Address of the last used pipeline state.
+    last_pipeline: usize,
+}
+
+impl Recorder {
+    pub(crate) fn draw(&mut self, pipeline: *const (), triangles: u64, instances: u32) {
+        let address = pipeline as usize;
+        if address != self.last_pipeline {
+            self.stats.pipeline_switches += 1;
+            self.last_pipeline = address;
+        }
+        self.stats.draw_calls += 1;
+        self.stats.triangles += triangles * instances as u64;
+        self.stats.instances += instances;
+    }
+}
+
+/// Number of triangles in `num_vertices` drawn with `material`.
+pub(crate) fn triangles(material: &Material, num_vertices: u32) -> u64 {
+    match *material {
+        Material::Line(_) => 0,
+        Material::Sprite(_) => num_vertices.saturating_sub(2) as u64,
+        _ => num_vertices as u64 / 3,
+    }
+}
+
+impl PipelineStates {
+    /// The pipeline `Renderer::render_mesh` picks for `material`.
+    pub(crate) fn main_by_material(&self, material: &Material) -> *const () {
+        match *material {
+            // the variants of a material, by features or render state, count as one pipeline
+            Material::Pbr(_) => &self.pbr as *const _ as *const (),
+            Material::Basic(_) => &self.mesh_basic_fill as *const _ as *const (),
+            Material::Line(_) => &self.line_basic as *const _ as *const (),
+            Material::Wireframe(_) => &self.mesh_basic_wireframe as *const _ as *const (),
+            Material::Lambert(_) => &self.mesh_gouraud as *const _ as *const (),
+            Material::Phong(_) => &self.mesh_phong as *const _ as *const (),
+            Material::Toon(_) => &self.mesh_toon as *const _ as *const (),
+            Material::Matcap(_) => &self.mesh_matcap as *const _ as *const (),
+            Material::Normal(_) => &self.mesh_normal as *const _ as *const (),
+            Material::Depth(_) => &self.mesh_depth as *const _ as *const (),
+            Material::Sprite(_) => &self.sprite as *const _ as *const (),
+            Material::CustomBasic(ref b) => &b.pipeline as *const _ as *const (),
+            Material::Custom(ref custom) => custom.shader.id(),
+        }
+    }
+}
+
+/// Timestamp queries written between passes.
+pub(crate) struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buf: wgpu::Buffer,
+    readback_buf: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Label of the pass ending at each timestamp of this frame.
+    labels: Vec<&'static str>,
+    /// Set while the timestamps of a previous frame are being read back.
+    receiver: Option<mpsc::Receiver<Vec<(&'static str, Duration)>>>,
+}
+
+impl GpuTimer {
+    /// Returns `None` if the device can't write timestamps between passes.
+    pub(crate) fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        let features = wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS;
+        if !device.features().contains(features) {
+            return None;
+        }
+        let size = MAX_TIMESTAMPS as u64 * wgpu::QUERY_SIZE as u64;
+        Some(GpuTimer {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor { label: Some("gpu timer"), ty: wgpu::QueryType::Timestamp, count: MAX_TIMESTAMPS }),
+            resolve_buf: device.create_buffer(&wgpu::BufferDescriptor { label: Some("gpu timer resolve"), size, usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC, mapped_at_creation: false }),
+            readback_buf: device.create_buffer(&wgpu::BufferDescriptor { label: Some("gpu timer readback"), size, usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ, mapped_at_creation: false }),
+            period: queue.get_timestamp_period(),
+            labels: Vec::new(),
+            receiver: None,
+        })
+    }
+
+    /// Returns the timings of a previous frame once they have been read back.
+    fn poll(&mut self) -> Option<Vec<(&'static str, Duration)>> {
+        let result = self.receiver.as_ref()?.try_recv();
+        match result {
+            Ok(timings) => {
+                self.receiver = None;
+                Some(timings)
+            }
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.receiver = None;
+                None
+            }
+        }
+    }
+}
+
+impl Renderer {
+    /// Statistics of the last frame drawn by [`render`](#method.render),
+    /// including the viewports drawn since the previous `render`.
+    pub fn stats(&self) -> &RenderStats {
+        &self.stats
+    }
+
+    /// Writes a GPU timestamp ending the pass called `label`.
+    ///
+    /// Does nothing if timestamps are not supported, or the previous ones
+    /// are still being read back.
+    pub(super) fn gpu_mark(&mut self, label: &'static str) {
+        let timer = match self.gpu_timer {
+            Some(ref mut timer) if timer.receiver.is_none() && (timer.labels.len() as u32) < MAX_TIMESTAMPS => timer,
+            _ => return,
+        };
+        // the pass has to be submitted before the timestamp
+        self.encoder.flush(&mut self.device);
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("gpu timer") });
+        encoder.write_timestamp(&timer.query_set, timer.labels.len() as u32);
+        self.queue.submit(Some(encoder.finish()));
+        timer.labels.push(label);
+    }
+
+    /// Publishes the statistics of the finished frame and starts reading
+    /// back its timestamps.
+    pub(super) fn end_frame_stats(&mut self) {
+        let mut stats = mem::take(&mut self.recorder).stats;
+        stats.gpu_timings = self.stats.gpu_timings.clone();
+
+        if self.gpu_timer.is_none() {
+            self.gpu_timer = GpuTimer::new(&self.device, &self.queue);
+        }
+        if let Some(ref mut timer) = self.gpu_timer {
+            if let Some(timings) = timer.poll() {
+                stats.gpu_timings = timings;
+            }
+            if timer.receiver.is_none() && timer.labels.len() > 1 {
+                let count = timer.labels.len() as u32;
+                let size = count as u64 * wgpu::QUERY_SIZE as u64;
+                let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("gpu timer resolve") });
+                encoder.resolve_query_set(&timer.query_set, 0 .. count, &timer.resolve_buf, 0);
+                encoder.copy_buffer_to_buffer(&timer.resolve_buf, 0, &timer.readback_buf, 0, size);
+                self.queue.submit(Some(encoder.finish()));
+
+                let (sender, receiver) = mpsc::channel();
+                let labels = mem::take(&mut timer.labels);
+                let (readback, period) = (timer.readback_buf.clone(), timer.period);
+                timer.readback_buf.map_async(wgpu::MapMode::Read, .. size, move |result| {
+                    if result.is_err() {
+                        return;
+                    }
+                    let timestamps: Vec<u64> = {
+                        let data = readback.get_mapped_range(.. size);
+                        data.chunks(8).map(|bytes| u64::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]])).collect()
+                    };
+                    readback.unmap();
+                    // the first timestamp only marks the start of the frame
+                    let timings = labels[1 ..].iter().zip(timestamps.windows(2)).map(|(&label, pair)| (label, Duration::from_nanos((pair[1].saturating_sub(pair[0]) as f64 * period as f64) as u64))).collect();
+                    let _ = sender.send(timings);
+                });
+                timer.receiver = Some(receiver);
+            }
+            timer.labels.clear();
+        }
+
+        if let Some(ref mut text) = self.stats_overlay {
+            text.set_text(stats.to_string());
+        }
+        self.stats = stats;
+    }
+}
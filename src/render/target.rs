@@ -0,0 +1,115 @@
+//! Offscreen render targets.
+
+use crate::camera::Camera;
+use crate::scene::Scene;
+use crate::texture::Texture;
+
+use super::{Output, Renderer, HDR_FORMAT};
+
+/// Format of the depth attachment of a `RenderTarget`, and of the window.
+pub(super) const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
+/// Creates the depth and stencil of the window, returning the attachment view
+/// and a view of the depth aspect alone, which is the only one that can be sampled.
+pub(super) fn window_depth(device: &wgpu::Device, size: [u32; 2]) -> (wgpu::TextureView, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("window depth"),
+        size: wgpu::Extent3d { width: size[0], height: size[1], depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: DEPTH_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let sampled = texture.create_view(&wgpu::TextureViewDescriptor { label: Some("window depth sampled"), aspect: wgpu::TextureAspect::DepthOnly, ..Default::default() });
+    (texture.create_view(&wgpu::TextureViewDescriptor::default()), sampled)
+}
+
+/// Color format of a `RenderTarget`.
+///
+/// The scene pipelines only support the formats the window renders with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TargetFormat {
+    /// Same format as the window, with values clamped to `[0, 1]`.
+    Color,
+    /// Floating point format of the HDR target, keeps values above 1.
+    Hdr,
+}
+
+impl TargetFormat {
+    pub(crate) fn resolve(self, surface_format: wgpu::TextureFormat) -> wgpu::TextureFormat {
+        match self {
+            TargetFormat::Color => surface_format,
+            TargetFormat::Hdr => HDR_FORMAT,
+        }
+    }
+}
+
+/// Offscreen color and depth target the scene can be rendered into.
+///
+/// Created by [`Factory::render_target`](../struct.Factory.html#method.render_target)
+/// and filled by [`Renderer::render_to`](struct.Renderer.html#method.render_to).
+/// The result can be displayed by any material through [`texture`](#method.texture).
+///
+/// A target can't be read and written by the same pass: materials of the
+/// scene rendered into a target must not use its texture.
+#[derive(Clone, Debug)]
+pub struct RenderTarget {
+    color: wgpu::TextureView,
+    format: wgpu::TextureFormat,
+    depth: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    size: [u32; 2],
+}
+
+impl RenderTarget {
+    pub(crate) fn new(device: &wgpu::Device, size: [u32; 2], format: wgpu::TextureFormat, sampler: wgpu::Sampler) -> Self {
+        let extent = wgpu::Extent3d { width: size[0], height: size[1], depth_or_array_layers: 1 };
+        let create = |label: &str, format: wgpu::TextureFormat, usage: wgpu::TextureUsages| {
+            let texture = device.create_texture(&wgpu::TextureDescriptor { label: Some(label), size: extent, mip_level_count: 1, sample_count: 1, dimension: wgpu::TextureDimension::D2, format, usage, view_formats: &[] });
+            texture.create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        RenderTarget {
+            color: create("render target color", format, wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC),
+            format,
+            depth: create("render target depth", DEPTH_FORMAT, wgpu::TextureUsages::RENDER_ATTACHMENT),
+            sampler,
+            size,
+        }
+    }
+
+    /// Returns the color attachment as a texture, to be used as the `map` of a material.
+    ///
+    /// The texture must not be displayed in the scene rendered into this
+    /// target, as wgpu rejects a texture bound both as an attachment and for
+    /// sampling in the same pass.
+    ///
+    /// The texel range is flipped vertically, as rendered images are stored
+    /// top row first while loaded images are stored bottom row first.
+    pub fn texture(&self) -> Texture<[f32; 4]> {
+        Texture::new(self.color.clone(), self.sampler.clone(), self.size).flip_v()
+    }
+
+    /// Returns the width and height of the target, in pixels.
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+}
+
+impl Renderer {
+    /// Renders `scene` by `camera` into `target`.
+    ///
+    /// The aspect ratio of the camera follows the target size. SSAO,
+    /// post-processing and UI text only apply to the window, so
+    /// [`Renderer::hdr`](#structfield.hdr) has no effect here: use
+    /// [`TargetFormat::Hdr`](enum.TargetFormat.html) to keep HDR values.
+    ///
+    /// No material of `scene` may use [`target.texture()`](struct.RenderTarget.html#method.texture).
+    pub fn render_to(&mut self, target: &RenderTarget, scene: &Scene, camera: &Camera) {
+        let size = target.size;
+        let output = Output { color: target.color.clone(), format: target.format, depth: target.depth.clone(), size, viewport: [0, 0, size[0], size[1]], clear: true, window: false, ui: false };
+        self.render_impl(scene, camera, output);
+        self.encoder.flush(&mut self.device);
+    }
+}
@@ -0,0 +1,56 @@
+//! Pipelines built from the same shaders with different features and render
+//! states.
+
+use std::collections::HashMap;
+
+use crate::material::RenderState;
+
+use super::source::{Features, Source};
+use super::PipelineCreationError;
+
+/// Creates a pipeline from the preprocessed vertex and fragment code.
+type Create<P> = Box<dyn Fn(&wgpu::Device, &str, &str, &RenderState) -> Result<P, PipelineCreationError>>;
+
+/// Pipeline variants keyed by their `Features` and `RenderState`, created on
+/// first use.
+pub(crate) struct Variants<P> {
+    vs: Source,
+    ps: Source,
+    create: Create<P>,
+    /// `None` for the variants that failed to be created, so they are not
+    /// tried again every frame.
+    pipelines: HashMap<(Features, RenderState), Option<P>>,
+}
+
+impl<P> Variants<P> {
+    pub(crate) fn new<F>(vs: Source, ps: Source, create: F) -> Self
+    where
+        F: Fn(&wgpu::Device, &str, &str, &RenderState) -> Result<P, PipelineCreationError> + 'static,
+    {
+        Variants { vs, ps, create: Box::new(create), pipelines: HashMap::new() }
+    }
+
+    /// Returns the pipeline with `features` enabled and drawing with `state`,
+    /// creating it if needed.
+    ///
+    /// Returns `None` if the variant can't be created, the error being
+    /// logged on the first attempt only.
+    pub(crate) fn get(&mut self, device: &wgpu::Device, features: Features, state: &RenderState) -> Option<&P> {
+        let mut state = *state;
+        // a NaN bias would make the key unequal to itself
+        if !state.slope_scale_bias.is_finite() {
+            state.slope_scale_bias = 0.0;
+        }
+        let Variants { ref vs, ref ps, ref create, ref mut pipelines } = *self;
+        pipelines
+            .entry((features, state))
+            .or_insert_with(|| Self::build(vs, ps, create, device, features, &state).map_err(|err| error!("Pipeline creation failed for {:?}: {}", features, err)).ok())
+            .as_ref()
+    }
+
+    fn build(vs: &Source, ps: &Source, create: &Create<P>, device: &wgpu::Device, features: Features, state: &RenderState) -> Result<P, PipelineCreationError> {
+        let vs = vs.variant(features)?;
+        let ps = ps.variant(features)?;
+        create(device, &vs, &ps, state)
+    }
+}
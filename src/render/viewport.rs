@@ -0,0 +1,91 @@
+//! Rendering into a part of the window.
+
+use crate::camera::Camera;
+use crate::scene::Scene;
+
+use super::{Output, Renderer};
+
+/// Rectangle of the window a camera renders into.
+///
+/// Coordinates are relative to the window size, with the origin in the top
+/// left corner, so the layout survives resizes. Drawing four cameras into
+/// the four quadrants of the window:
+///
+/// ```rust,no_run
+/// # let mut window = three::Window::new("Example");
+/// # let cameras: Vec<three::camera::Camera> = Vec::new();
+/// for (i, camera) in cameras.iter().enumerate() {
+///     let x = (i % 2) as f32 * 0.5;
+///     let y = (i / 2) as f32 * 0.5;
+///     let viewport = three::render::Viewport::new(x, y, 0.5, 0.5);
+///     window.renderer.render_viewport(&window.scene, camera, &viewport);
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    /// Left edge, from `0.0` to `1.0`.
+    pub x: f32,
+    /// Top edge, from `0.0` to `1.0`.
+    pub y: f32,
+    /// Width, relative to the window width.
+    pub width: f32,
+    /// Height, relative to the window height.
+    pub height: f32,
+    /// Clear color and depth inside the viewport before drawing.
+    ///
+    /// Set to `false` to draw on top of whatever is already there. Note that
+    /// [`Renderer::ssao`](struct.Renderer.html#structfield.ssao) always
+    /// resets the depth.
+    ///
+    /// Default: `true`.
+    pub clear: bool,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport { x: 0.0, y: 0.0, width: 1.0, height: 1.0, clear: true }
+    }
+}
+
+impl Viewport {
+    /// Creates a viewport that clears its area before drawing.
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Viewport { x, y, width, height, clear: true }
+    }
+
+    /// Returns `[x, y, width, height]` in pixels of a target of the given size,
+    /// clamped to the target and at least a pixel wide.
+    ///
+    /// Returns `None` if the target is empty, e.g. when the window is minimized.
+    pub(crate) fn to_pixels(&self, size: [u32; 2]) -> Option<[u32; 4]> {
+        if size[0] == 0 || size[1] == 0 {
+            return None;
+        }
+        let edge = |value: f32, extent: u32| (value.max(0.0).min(1.0) * extent as f32).round() as u32;
+        let x = edge(self.x, size[0]).min(size[0] - 1);
+        let y = edge(self.y, size[1]).min(size[1] - 1);
+        let right = edge(self.x + self.width, size[0]).max(x + 1);
+        let bottom = edge(self.y + self.height, size[1]).max(y + 1);
+        Some([x, y, right - x, bottom - y])
+    }
+}
+
+impl Renderer {
+    /// Renders `scene` by `camera` into a part of the window.
+    ///
+    /// The aspect ratio of the camera follows the viewport size. Several
+    /// viewports can be drawn in the same frame, e.g. for split-screen or
+    /// editor views. UI text and debug quads are only drawn by
+    /// [`render`](#method.render), which should come first if the scene
+    /// background is supposed to cover the rest of the window.
+    /// Post-processing effects are applied to the whole window.
+    pub fn render_viewport(&mut self, scene: &Scene, camera: &Camera, viewport: &Viewport) {
+        let size = self.physical_size();
+        let Some(pixels) = viewport.to_pixels(size) else {
+            return;
+        };
+        let output = Output { color: self.out_color.clone(), format: self.surface_format, depth: self.out_depth.clone(), size, viewport: pixels, clear: viewport.clear, window: true, ui: false };
+        self.render_impl(scene, camera, output);
+        self.encoder.flush(&mut self.device);
+    }
+}
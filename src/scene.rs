@@ -5,6 +5,7 @@ use crate::{
     hub::{Hub, HubPtr, SubNode},
     node,
     object::{Base, DowncastObject, Group, Object},
+    render::ibl::Environment,
     texture::{CubeMap, Texture},
 };
 
@@ -22,6 +23,55 @@ pub enum Background {
     Texture(Texture<[f32; 4]>),
     /// Skybox
     Skybox(CubeMap<[f32; 4]>),
+    /// Panorama surrounding the scene, usually loaded with
+    /// [`Factory::load_hdr_texture`](../struct.Factory.html#method.load_hdr_texture).
+    ///
+    /// The horizontal axis of the texture spans the full turn around the
+    /// vertical axis, the vertical axis goes from straight down to straight up.
+    Equirectangular(Texture<[f32; 4]>),
+}
+
+/// Atmospheric fog, blending distant objects towards a color.
+///
+/// See [`Scene::fog`](struct.Scene.html#structfield.fog).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fog {
+    /// Fog increasing linearly between two distances from the camera.
+    Linear {
+        /// Distance where the fog starts.
+        near: f32,
+        /// Distance where the fog fully covers objects.
+        far: f32,
+        /// Color of the fog.
+        color: Color,
+    },
+    /// Fog increasing exponentially with the distance from the camera.
+    Exponential {
+        /// Amount of fog per world unit, a value around `0.05` is a thick fog.
+        density: f32,
+        /// Color of the fog.
+        color: Color,
+    },
+}
+
+impl Fog {
+    /// Mode and parameters of the fog, has to match `fog.glsl`.
+    pub(crate) fn params(&self) -> (i32, Color, [f32; 2]) {
+        match *self {
+            Fog::Linear { near, far, color } => (1, color, [near, far]),
+            Fog::Exponential { density, color } => (2, color, [density, 0.0]),
+        }
+    }
+}
+
+/// Makes the fog thin out with height, see
+/// [`Scene::fog_height`](struct.Scene.html#structfield.fog_height).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FogHeight {
+    /// Height below which the fog has its full density.
+    pub base: f32,
+    /// How fast the fog thins out above `base`, per world unit.
+    pub falloff: f32,
 }
 
 /// The root node of a tree of game objects that may be rendered by a [`Camera`].
@@ -32,6 +82,22 @@ pub struct Scene {
     pub(crate) first_child: Option<node::NodePointer>,
     /// See [`Background`](struct.Background.html).
     pub background: Background,
+    /// Image-based lighting applied to `Material::Pbr` meshes.
+    ///
+    /// Use [`Environment::cube_map`](../render/ibl/struct.Environment.html#method.cube_map)
+    /// to show the same cube map as `Background::Skybox`.
+    ///
+    /// Default: `None`.
+    pub environment: Option<Environment>,
+    /// See [`Fog`](enum.Fog.html). Default: `None`.
+    pub fog: Option<Fog>,
+    /// Optional height falloff of the fog. Default: `None`.
+    pub fog_height: Option<FogHeight>,
+    /// Use the `Background::Color` as the fog color, so that distant objects
+    /// fade into the background. Other backgrounds keep the fog color.
+    ///
+    /// Default: `false`.
+    pub fog_blend_background: bool,
 }
 
 impl Scene {
@@ -115,7 +181,7 @@ impl Scene {
 /// # use three::Object;
 /// # let mut win = three::Window::new("SyncGuard example");
 /// # let geometry = three::Geometry::default();
-/// # let material = three::material::Basic { color: three::color::RED, map: None };
+/// # let material = three::material::Basic { color: three::color::RED, ..Default::default() };
 /// # let mesh = win.factory.mesh(geometry, material);
 /// # let enemy = Enemy { mesh, is_visible: true };
 /// # win.scene.add(&enemy);
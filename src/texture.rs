@@ -42,6 +42,15 @@ impl Texture {
         self.tex1 = [base.x as f32 + size.x as f32, self.total_size[1] as f32 - base.y as f32];
     }
 
+    /// Flips the texel range upside down.
+    //TODO: Remove dead_code lint once `render::target` is built
+    #[allow(dead_code)]
+    pub(crate) fn flip_v(mut self) -> Self {
+        self.tex0[1] = self.total_size[1] as f32;
+        self.tex1[1] = 0.0;
+        self
+    }
+
     /// Returns normalized UV rectangle (x0, y0, x1, y1) of the current texel range.
     pub fn uv_range(&self) -> [f32; 4] {
         [self.tex0[0] / self.total_size[0] as f32, self.tex0[1] / self.total_size[1] as f32, self.tex1[0] / self.total_size[0] as f32, self.tex1[1] / self.total_size[1] as f32]